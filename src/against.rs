@@ -0,0 +1,86 @@
+//! Comparing every module of a corpus with a set of base modules, such as a
+//! shared runtime that is already shipped, for `--against`.
+
+use crate::{shorten_hash, Category, Counts, WideHash};
+use std::collections::{BTreeMap, HashSet};
+
+/// The items of the base modules, and how many bytes of each module of the
+/// corpus are copies of them.
+pub struct Against {
+    /// The hash of every strict item of the base modules, by category.
+    items: BTreeMap<Category, HashSet<WideHash>>,
+    pub base_size: u64,
+    pub base_files: usize,
+    /// The bytes of each module (indexed like `Counts::files`) that are copies
+    /// of base items, by category.
+    pub covered: Vec<BTreeMap<Category, u64>>,
+}
+
+impl Against {
+    /// Compare with the modules measured by `base`.
+    pub fn new(base: &Counts) -> Self {
+        Against {
+            items: base
+                .items
+                .iter()
+                .filter(|(category, _)| Category::ALL.contains(category))
+                .map(|(category, items)| (*category, items.keys().cloned().collect()))
+                .collect(),
+            base_size: base.total_size,
+            base_files: base.files.len(),
+            covered: vec![],
+        }
+    }
+
+    /// Whether the base modules contain the item of `category` with `hash`.
+    pub fn contains(&self, category: Category, hash: &WideHash) -> bool {
+        self.items
+            .get(&category)
+            .map_or(false, |items| items.contains(hash))
+    }
+
+    /// Shorten the hashes of the base items to `bytes` bytes, like those of
+    /// the corpus.
+    pub fn shorten_hashes(&mut self, bytes: usize) {
+        for items in self.items.values_mut() {
+            *items = std::mem::take(items)
+                .into_iter()
+                .map(|hash| shorten_hash(hash, bytes))
+                .collect();
+        }
+    }
+
+    /// Add an item of `size` bytes of the module at index `module`, counting it
+    /// if it's in the base.
+    pub fn add(&mut self, module: usize, category: Category, hash: &WideHash, size: u64) {
+        if self.covered.len() <= module {
+            self.covered.resize_with(module + 1, BTreeMap::new);
+        }
+        if self.contains(category, hash) {
+            *self.covered[module].entry(category).or_insert(0) += size;
+        }
+    }
+
+    /// The total size of the distinct items of `counts`, and of those of them
+    /// in the base, for each strict category with items, leaving out items
+    /// smaller than `--min-size` like the duplication does.
+    pub fn overlap(&self, counts: &Counts) -> Vec<(Category, u64, u64)> {
+        let mut categories = vec![];
+        for category in Category::ALL {
+            let (mut size, mut covered) = (0, 0);
+            for (hash, entry) in counts.items.get(category).into_iter().flatten() {
+                if entry.size < counts.min_size {
+                    continue;
+                }
+                size += entry.size;
+                if self.contains(*category, hash) {
+                    covered += entry.size;
+                }
+            }
+            if size > 0 {
+                categories.push((*category, covered, size));
+            }
+        }
+        categories
+    }
+}
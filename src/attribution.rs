@@ -0,0 +1,135 @@
+//! Attributing the redundant bytes of duplicated items to the files their
+//! copies are in, for `--treemap` and `--folded`.
+
+use crate::{hex, shorten_hash, shorten_keys, Category, Counts, WideHash};
+use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// What the redundant copies of a category's items are grouped by.
+#[derive(PartialEq, Eq, Hash)]
+pub enum Group {
+    /// The index (into `Counts::files`) of the file they're in.
+    File(usize),
+    /// The name of the custom sections.
+    CustomSection(String),
+}
+
+/// Which of a group's redundant copies the bytes are for.
+#[derive(PartialEq, Eq, Hash)]
+pub enum Item {
+    Hash(WideHash),
+    /// The index (into `Counts::files`) of the file a custom section is in.
+    File(usize),
+}
+
+/// The labels of a group and of its items, with the redundant bytes of each
+/// item.
+pub type Labeled = (String, Vec<(String, u64)>);
+
+/// The redundant bytes of every duplicated item.
+#[derive(Default)]
+pub struct Attribution {
+    bytes: BTreeMap<Category, HashMap<Group, HashMap<Item, u64>>>,
+}
+
+impl Attribution {
+    /// Add a redundant copy of `size` bytes.
+    pub fn add(&mut self, category: Category, group: Group, item: Item, size: u64) {
+        *self
+            .bytes
+            .entry(category)
+            .or_default()
+            .entry(group)
+            .or_default()
+            .entry(item)
+            .or_insert(0) += size;
+    }
+
+    /// Shorten the hashes of the items to `bytes` bytes, merging those that
+    /// then collide.
+    pub fn shorten_hashes(&mut self, bytes: usize) {
+        for groups in self.bytes.values_mut() {
+            for items in groups.values_mut() {
+                shorten_keys(
+                    items,
+                    |item| match item {
+                        Item::Hash(hash) => Item::Hash(shorten_hash(hash, bytes)),
+                        item => item,
+                    },
+                    |size, other| *size += other,
+                );
+            }
+        }
+    }
+
+    /// Every category's groups, labeled with the paths of files and the names
+    /// of functions from `counts`.
+    pub fn labeled(&self, counts: &Counts) -> BTreeMap<Category, Vec<Labeled>> {
+        self.bytes
+            .iter()
+            .map(|(category, groups)| {
+                let groups = groups
+                    .iter()
+                    .map(|(group, items)| {
+                        let label = match group {
+                            Group::File(file) => counts.files[*file].display().to_string(),
+                            Group::CustomSection(name) => format!("custom section \"{}\"", name),
+                        };
+                        let items = items
+                            .iter()
+                            .map(|(item, size)| (item_label(counts, *category, item), *size))
+                            .collect();
+                        (label, items)
+                    })
+                    .collect();
+                (*category, groups)
+            })
+            .collect()
+    }
+
+    /// Write the redundant bytes to `path` as folded stacks of
+    /// `category;group;item`, one per line, followed by the number of bytes,
+    /// for flamegraph tools such as `inferno-flamegraph`.
+    pub fn write_folded(&self, counts: &Counts, path: &Path) -> Result<()> {
+        let mut lines = vec![];
+        for (category, groups) in self.labeled(counts) {
+            for (group, items) in groups {
+                for (item, size) in items {
+                    let stack = [category.name(), &group, &item].map(frame).join(";");
+                    lines.push((stack, size));
+                }
+            }
+        }
+        lines.sort();
+
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        for (stack, size) in lines {
+            writeln!(out, "{} {}", stack, size)?;
+        }
+        out.flush()
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+fn item_label(counts: &Counts, category: Category, item: &Item) -> String {
+    match item {
+        Item::Hash(hash) => {
+            let name = counts.name(category, hash);
+            name.map_or_else(|| hex(&hash[..8]), str::to_string)
+        }
+        Item::File(file) => counts.files[*file].display().to_string(),
+    }
+}
+
+/// `label` as a frame of a folded stack, which can't contain the `;` that
+/// separates frames, as in the array types of Rust symbols, or line breaks.
+fn frame(label: &str) -> String {
+    label.replace(';', ",").replace(['\n', '\r'], " ")
+}
@@ -0,0 +1,171 @@
+//! Baseline snapshots of a corpus's duplication, and absolute limits on it,
+//! for failing CI when it regresses.
+
+use crate::{percent, run::Run, Category, Counts, Duplication};
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+#[derive(Serialize, Deserialize)]
+pub struct Baseline {
+    /// Snapshots from before there was a version are version 1 too.
+    #[serde(default = "first_version")]
+    schema_version: u32,
+    total_size: u64,
+    duplicated: u64,
+    /// The duplication of each strict category, keyed by its name.
+    categories: BTreeMap<String, CategoryBaseline>,
+    /// What produced the snapshot, which older snapshots don't say.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run: Option<Run>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CategoryBaseline {
+    within_modules: u64,
+    across_modules: u64,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl Baseline {
+    pub fn new(counts: &Counts, run: Run) -> Self {
+        let mut duplicated = Duplication::default();
+        let mut categories = BTreeMap::new();
+        for category in Category::ALL {
+            let dupe = counts.duplicated(*category);
+            duplicated += dupe;
+            categories.insert(
+                category.name().to_string(),
+                CategoryBaseline {
+                    within_modules: dupe.within_modules,
+                    across_modules: dupe.across_modules,
+                },
+            );
+        }
+        Baseline {
+            schema_version: 1,
+            total_size: counts.total_size,
+            duplicated: duplicated.total(),
+            categories,
+            run: Some(run),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let baseline: Baseline = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse baseline {}", path.display()))?;
+        ensure!(
+            baseline.schema_version == 1,
+            "unsupported baseline schema version {} in {}",
+            baseline.schema_version,
+            path.display()
+        );
+        Ok(baseline)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// The percentage of the corpus that is duplicated.
+    fn duplicated_percent(&self) -> f64 {
+        percent(self.duplicated, self.total_size)
+    }
+
+    /// Fail if the duplicated percentage of `self` is more than
+    /// `max_regression` percentage points above that of `baseline`, printing
+    /// both percentages if `verbose`.
+    pub fn check(&self, baseline: &Baseline, max_regression: f64, verbose: bool) -> Result<()> {
+        let old = baseline.duplicated_percent();
+        let new = self.duplicated_percent();
+        if verbose {
+            println!(
+                "Duplicated data: {:.02}% (baseline {:.02}%, {:+.02} points)",
+                new,
+                old,
+                new - old
+            );
+        }
+        if new - old > max_regression {
+            bail!(
+                "duplicated data regressed from {:.02}% to {:.02}% of the corpus, more than the \
+                 allowed {}%",
+                old,
+                new,
+                max_regression
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Fail if the duplicated percentage of the corpus is above `total`, or that
+/// of any category's items is above its limit in `categories`, printing the
+/// checked percentages if `verbose`.
+pub fn check_limits(
+    counts: &Counts,
+    total: Option<f64>,
+    categories: &[(Category, f64)],
+    verbose: bool,
+) -> Result<()> {
+    let mut checks = vec![];
+    if let Some(limit) = total {
+        let mut duplicated = Duplication::default();
+        for category in Category::ALL {
+            duplicated += counts.duplicated(*category);
+        }
+        let percent = percent(duplicated.total(), counts.total_size);
+        checks.push(("the corpus".to_string(), percent, limit));
+    }
+    for (category, limit) in categories {
+        let size = counts
+            .category_totals
+            .get(category)
+            .map_or(0, |totals| totals.size);
+        let percent = percent(counts.duplicated(*category).total(), size);
+        checks.push((category.name().to_string(), percent, *limit));
+    }
+
+    let mut exceeded = vec![];
+    for (what, percent, limit) in checks {
+        if verbose {
+            println!("Duplicated {}: {:.02}% (limit {}%)", what, percent, limit);
+        }
+        if percent > limit {
+            exceeded.push(format!("{:.02}% of {}, above {}%", percent, what, limit));
+        }
+    }
+    if !exceeded.is_empty() {
+        bail!(
+            "duplicated data exceeded its limit: {}",
+            exceeded.join("; ")
+        );
+    }
+    Ok(())
+}
+
+/// Parse a per-category limit like `code-bodies=20%`, naming a strict category
+/// with dashes for spaces.
+pub fn parse_category_limit(s: &str) -> Result<(Category, f64)> {
+    let (name, limit) = s
+        .split_once('=')
+        .with_context(|| format!("expected `<category>=<percent>`, got: {}", s))?;
+    let category = Category::ALL
+        .iter()
+        .find(|category| category.name().replace(' ', "-").eq_ignore_ascii_case(name))
+        .with_context(|| format!("unknown category: {}", name))?;
+    Ok((*category, parse_percent(limit)?))
+}
+
+/// Parse a maximum regression given in percentage points, like `1%` or `0.5`.
+pub fn parse_percent(s: &str) -> Result<f64> {
+    s.trim_end_matches('%')
+        .parse::<f64>()
+        .with_context(|| format!("invalid percentage: {}", s))
+}
@@ -0,0 +1,60 @@
+//! Keeping the item maps under a memory budget, for `--max-memory`, by dropping
+//! the details kept about every item as they get close to it, and then
+//! shortening the hashes that key them, rather than getting killed for running
+//! out of memory halfway through a long run.
+
+use anyhow::{Context, Result};
+
+/// Parse a number of bytes with an optional binary unit, like `8GiB`, `512M`,
+/// or `1048576`.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let digits = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let shift = match unit.trim().trim_end_matches("iB").trim_end_matches('B') {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => anyhow::bail!("unknown unit in size: {}", s),
+    };
+    let number = number
+        .parse::<f64>()
+        .with_context(|| format!("invalid size: {}", s))?;
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+/// A detail about every item that stopped being kept to stay under the
+/// budget, in the order they're dropped.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Degradation {
+    /// The bytes of every item, for `--verify`.
+    VerifyKeys,
+    /// The files containing every item and the offsets of its copies.
+    ItemFiles,
+    /// The bytes of every hash past the first eight.
+    ShortHashes,
+    /// Nothing is left to drop, so the budget is exceeded.
+    OverBudget,
+}
+
+impl Degradation {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Degradation::VerifyKeys => {
+                "stopped verifying duplicates byte for byte, so hash collisions go unnoticed"
+            }
+            Degradation::ItemFiles => {
+                "stopped tracking which files contain each item, so the reports listing them, \
+                 such as `--who` and `--similarity-matrix`, are incomplete"
+            }
+            Degradation::ShortHashes => {
+                "shortened every hash to 8 bytes, so distinct items are more likely to be \
+                 counted as duplicates"
+            }
+            Degradation::OverBudget => "exceeded the budget, with no details left to drop",
+        }
+    }
+}
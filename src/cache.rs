@@ -0,0 +1,117 @@
+//! An on-disk cache of the items of each file, so that incremental runs over a
+//! mostly unchanged corpus only parse new or changed files.
+//!
+//! Every cached file is stored as `<key>` in the cache directory, where the key
+//! is a hash of the file's contents and of the options that affect which items
+//! get added. It holds one record per added item: the category, the size as a
+//! little-endian `u64`, and the item's hash.
+
+use crate::{Category, WideHash};
+use anyhow::{Context, Result};
+use std::{fs, io, path::PathBuf};
+
+/// The cached form of an item: its category, its hash, and its size.
+pub type Entry = (Category, WideHash, u64);
+
+/// Bump this whenever the items added for a file, or how they are hashed,
+/// change.
+const VERSION: u32 = 13;
+
+pub struct Cache {
+    dir: PathBuf,
+    fingerprint: String,
+    hash_bytes: usize,
+}
+
+impl Cache {
+    /// Cache files in `dir`. `fingerprint` must describe every option that
+    /// affects which items get added, and `hash_bytes` is the length of the
+    /// items' hashes.
+    pub fn new(dir: PathBuf, fingerprint: String, hash_bytes: usize) -> Self {
+        Cache {
+            dir,
+            fingerprint,
+            hash_bytes,
+        }
+    }
+
+    /// The size of one record in a cache file.
+    fn record_size(&self) -> usize {
+        1 + 8 + self.hash_bytes
+    }
+
+    /// The key of the file with the contents `wasm`.
+    pub fn key(&self, wasm: &[u8]) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&VERSION.to_le_bytes());
+        hasher.update(self.fingerprint.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(&(self.hash_bytes as u32).to_le_bytes());
+        hasher.update(wasm);
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Get the cached items for `key`, if there are any.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<Entry>>> {
+        let path = self.dir.join(key);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        let record_size = self.record_size();
+        if bytes.len() % record_size != 0 {
+            log::warn!("Ignoring corrupt cache file: {}", path.display());
+            return Ok(None);
+        }
+
+        let mut entries = Vec::with_capacity(bytes.len() / record_size);
+        for record in bytes.chunks_exact(record_size) {
+            let category = match category(record[0]) {
+                Some(category) => category,
+                None => {
+                    log::warn!("Ignoring corrupt cache file: {}", path.display());
+                    return Ok(None);
+                }
+            };
+            let size = u64::from_le_bytes(record[1..9].try_into().unwrap());
+            let hash = record[9..].into();
+            entries.push((category, hash, size));
+        }
+        Ok(Some(entries))
+    }
+
+    /// Cache `entries` for `key`.
+    pub fn put(&self, key: &str, entries: &[Entry]) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+
+        let mut bytes = Vec::with_capacity(entries.len() * self.record_size());
+        for (category, hash, size) in entries {
+            bytes.push(*category as u8);
+            bytes.extend_from_slice(&size.to_le_bytes());
+            bytes.extend_from_slice(hash);
+        }
+
+        // Write to a temporary file first so that an interrupted run doesn't
+        // leave a truncated cache file behind.
+        let path = self.dir.join(key);
+        let tmp = self.dir.join(format!("{}.tmp", key));
+        fs::write(&tmp, bytes).with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// The category whose discriminant is `byte`.
+fn category(byte: u8) -> Option<Category> {
+    Category::ALL
+        .iter()
+        .chain(Category::NORMALIZED)
+        .chain(Category::ANALYSES)
+        .chain(Category::RESOURCES)
+        .chain(Category::ELEM_KINDS)
+        .chain(Category::DATA_KINDS)
+        .copied()
+        .find(|category| *category as u8 == byte)
+}
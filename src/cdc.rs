@@ -0,0 +1,86 @@
+//! Content-defined chunking in the style of FastCDC.
+//!
+//! Chunk boundaries are placed where a gear rolling hash of the preceding bytes
+//! matches a mask, so an insertion or deletion only moves the boundaries near
+//! it and the rest of the data still splits into the same chunks. Normalized
+//! chunking uses a harder mask before the target average size and an easier
+//! one after it, which keeps chunk sizes close to the average.
+
+/// Splits data into content-defined chunks.
+pub struct Chunker {
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    /// The mask used before reaching `avg_size`.
+    hard_mask: u64,
+    /// The mask used after reaching `avg_size`.
+    easy_mask: u64,
+    gear: [u64; 256],
+}
+
+impl Chunker {
+    /// Create a chunker targeting chunks of roughly `avg_size` bytes, which
+    /// must be a power of two of at least 64. Chunks are between a quarter of
+    /// and eight times the average size.
+    pub fn new(avg_size: usize) -> Self {
+        assert!(avg_size.is_power_of_two() && avg_size >= 64);
+        let bits = avg_size.trailing_zeros();
+
+        // The gear hash shifts left once per byte, so its high bits depend on
+        // the most bytes; take the mask bits from there.
+        let mask = |bits: u32| !(u64::MAX >> bits);
+
+        let mut gear = [0; 256];
+        let mut state = 0u64;
+        for g in gear.iter_mut() {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut x = state;
+            x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            *g = x ^ (x >> 31);
+        }
+
+        Chunker {
+            min_size: avg_size / 4,
+            avg_size,
+            max_size: avg_size * 8,
+            hard_mask: mask(bits + 1),
+            easy_mask: mask(bits - 1),
+            gear,
+        }
+    }
+
+    /// Split `data` into chunks.
+    pub fn split<'a>(&self, mut data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = vec![];
+        while !data.is_empty() {
+            let (chunk, rest) = data.split_at(self.cut_point(data));
+            chunks.push(chunk);
+            data = rest;
+        }
+        chunks
+    }
+
+    /// Find the length of the first chunk of `data`.
+    fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let max = data.len().min(self.max_size);
+        let normal = max.min(self.avg_size);
+
+        let mut hash = 0u64;
+        for (i, byte) in data.iter().enumerate().take(max).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(self.gear[*byte as usize]);
+            let mask = if i < normal {
+                self.hard_mask
+            } else {
+                self.easy_mask
+            };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        max
+    }
+}
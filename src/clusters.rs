@@ -0,0 +1,95 @@
+//! Proposing how to factor the most duplicated functions and segments out into
+//! a few shared modules, for `--shared-modules`.
+//!
+//! Items are taken greedily, the ones saving the most bytes first. Each goes
+//! into the shared module whose users (the modules importing any of its items)
+//! overlap the most with the modules containing the item, so that modules link
+//! against as few shared modules as they can, unless no shared module overlaps
+//! much and there is room to start another one. Items that don't fit in any
+//! shared module's budget are left out.
+
+use crate::{Category, Counts, SizeEntry, WideHash};
+use std::collections::HashSet;
+
+/// The categories of items that can be moved into a shared module.
+const CATEGORIES: &[Category] = &[
+    Category::CodeBodies,
+    Category::DataSegments,
+    Category::ElemSegments,
+];
+
+/// How much an item's modules must overlap with a shared module's users, as
+/// the size of their intersection over the size of their union, for the item
+/// to go into it rather than into a new one.
+const MIN_OVERLAP: f64 = 0.5;
+
+/// A proposed shared module.
+#[derive(Default)]
+pub struct SharedModule<'a> {
+    /// Its items, the ones saving the most bytes first.
+    pub items: Vec<(Category, &'a WideHash, &'a SizeEntry)>,
+    /// The modules of the corpus that would import any of its items.
+    pub users: HashSet<usize>,
+    /// The total size of its items.
+    pub size: u64,
+    /// The bytes saved by replacing every copy of its items with an import.
+    pub savings: u64,
+}
+
+/// Propose up to `k` shared modules of at most `budget` bytes of items each
+/// for `counts`.
+///
+/// Requires the files of every item to be tracked.
+pub fn propose(counts: &Counts, k: usize, budget: u64) -> Vec<SharedModule<'_>> {
+    // Items only duplicated within a single module are better merged inside
+    // that module.
+    let mut candidates: Vec<_> = CATEGORIES
+        .iter()
+        .flat_map(|category| {
+            counts
+                .duplicated_items(*category, 0)
+                .into_iter()
+                .filter(|(_, entry)| entry.files.len() > 1 && entry.size <= budget)
+                .map(move |(hash, entry)| (*category, hash, entry))
+        })
+        .collect();
+    candidates.sort_by_key(|(category, hash, entry)| {
+        (std::cmp::Reverse(savings(entry)), *category, *hash)
+    });
+
+    let mut modules: Vec<SharedModule> = vec![];
+    for (category, hash, entry) in candidates {
+        let best = modules
+            .iter()
+            .enumerate()
+            .filter(|(_, module)| module.size + entry.size <= budget)
+            .map(|(i, module)| (i, overlap(&module.users, &entry.files)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        let index = match best {
+            Some((i, overlap)) if overlap >= MIN_OVERLAP || modules.len() == k => i,
+            _ if modules.len() < k => {
+                modules.push(SharedModule::default());
+                modules.len() - 1
+            }
+            _ => continue,
+        };
+        let module = &mut modules[index];
+        module.items.push((category, hash, entry));
+        module.users.extend(&entry.files);
+        module.size += entry.size;
+        module.savings += savings(entry);
+    }
+    modules
+}
+
+/// The bytes saved by replacing every copy of an item with an import of a
+/// single shared copy, before the cost of the imports.
+pub fn savings(entry: &SizeEntry) -> u64 {
+    entry.size * (entry.count - 1)
+}
+
+/// The Jaccard index of `users` and `files`, which has no duplicates.
+fn overlap(users: &HashSet<usize>, files: &[usize]) -> f64 {
+    let common = files.iter().filter(|file| users.contains(file)).count();
+    common as f64 / (users.len() + files.len() - common) as f64
+}
@@ -0,0 +1,59 @@
+//! The sections of component-model binaries, which `wasmparser` can't parse
+//! yet, so that their core modules are measured like any other and the
+//! interface definitions around them on their own.
+//!
+//! Components are split into sections like modules, but with their own section
+//! ids. Their entries are still changing along with the proposal, so each
+//! type, instance, alias, and canonical function section is one item rather
+//! than each of its entries.
+
+use crate::dedupe::read_u32;
+use anyhow::{ensure, Context, Result};
+use std::ops::Range;
+
+pub const CUSTOM: u8 = 0;
+pub const CORE_MODULE: u8 = 1;
+pub const CORE_INSTANCE: u8 = 2;
+pub const CORE_TYPE: u8 = 3;
+pub const COMPONENT: u8 = 4;
+pub const INSTANCE: u8 = 5;
+pub const ALIAS: u8 = 6;
+pub const TYPE: u8 = 7;
+pub const CANON: u8 = 8;
+
+/// Whether `wasm` is a component rather than a core module, going by the layer
+/// in the second half of its version field.
+pub fn is_component(wasm: &[u8]) -> bool {
+    wasm.len() >= 8 && wasm.starts_with(b"\0asm") && wasm[6..8] == [1, 0]
+}
+
+/// The id and the range of the contents of every section of the component
+/// `wasm`.
+pub fn sections(wasm: &[u8]) -> Result<Vec<(u8, Range<usize>)>> {
+    ensure!(is_component(wasm), "not a component");
+    let mut sections = vec![];
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_u32(wasm, &mut pos)? as usize;
+        let end = pos
+            .checked_add(size)
+            .filter(|end| *end <= wasm.len())
+            .context("section extends past the end of the component")?;
+        sections.push((id, pos..end));
+        pos = end;
+    }
+    Ok(sections)
+}
+
+/// The name and data of the custom section with `contents`.
+pub fn custom_section(contents: &[u8]) -> Result<(&str, &[u8])> {
+    let mut pos = 0;
+    let len = read_u32(contents, &mut pos)? as usize;
+    let name = contents
+        .get(pos..pos + len)
+        .context("custom section name is too long")?;
+    let name = std::str::from_utf8(name).context("custom section name is not UTF-8")?;
+    Ok((name, &contents[pos + len..]))
+}
@@ -0,0 +1,101 @@
+//! Compressed sizes, for measuring duplication in terms of transfer size.
+
+use anyhow::Result;
+use std::io::Write;
+
+/// A general-purpose compression format that Wasm is commonly served with.
+#[derive(Clone, Copy)]
+pub enum Codec {
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+/// The compressed size of some data with each codec in `Codec::ALL`, in order.
+pub type Sizes = [u64; 3];
+
+impl Codec {
+    pub const ALL: &'static [Codec] = &[Codec::Gzip, Codec::Brotli, Codec::Zstd];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip -9",
+            Codec::Brotli => "brotli -q 11",
+            Codec::Zstd => "zstd -19",
+        }
+    }
+
+    /// The size of `data` compressed with this codec at the level named by
+    /// `name`.
+    pub fn compressed_size(self, data: &[u8]) -> Result<u64> {
+        let compressed = match self {
+            Codec::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            Codec::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(vec![], 4096, 11, 22);
+                encoder.write_all(data)?;
+                encoder.into_inner()
+            }
+            Codec::Zstd => zstd::bulk::compress(data, 19)?,
+        };
+        Ok(compressed.len() as u64)
+    }
+}
+
+/// The size of `data` compressed with every codec.
+pub fn sizes(data: &[u8]) -> Result<Sizes> {
+    let mut sizes = Sizes::default();
+    for (size, codec) in sizes.iter_mut().zip(Codec::ALL) {
+        *size = codec.compressed_size(data)?;
+    }
+    Ok(sizes)
+}
+
+/// Samples of the corpus to train a shared zstd dictionary on.
+pub struct DictionarySamples {
+    samples: Vec<Vec<u8>>,
+    size: usize,
+    dictionary_size: usize,
+}
+
+impl DictionarySamples {
+    /// Collect samples for a dictionary of `dictionary_size` bytes. zstd
+    /// recommends training on about a hundred times as many bytes as the
+    /// dictionary will have.
+    pub fn new(dictionary_size: usize) -> Self {
+        DictionarySamples {
+            samples: vec![],
+            size: 0,
+            dictionary_size,
+        }
+    }
+
+    /// Add a sample, unless there are already enough.
+    pub fn add(&mut self, data: &[u8]) {
+        if self.size < self.dictionary_size * 100 {
+            self.samples.push(data.to_vec());
+            self.size += data.len();
+        }
+    }
+
+    /// Train a dictionary on the samples.
+    pub fn train(&self) -> Result<Vec<u8>> {
+        Ok(zstd::dict::from_samples(
+            &self.samples,
+            self.dictionary_size,
+        )?)
+    }
+}
+
+/// The size of `data` compressed with zstd, on its own and with `dictionary`.
+pub fn zstd_sizes(data: &[u8], dictionary: &[u8]) -> Result<(u64, u64)> {
+    let standalone = zstd::bulk::compress(data, 19)?.len() as u64;
+    let with_dictionary = zstd::bulk::Compressor::with_dictionary(19, dictionary)?
+        .compress(data)?
+        .len() as u64;
+    Ok((standalone, with_dictionary))
+}
@@ -0,0 +1,82 @@
+//! `--config` files, which give default values for the command-line options.
+//!
+//! A config file is a TOML table whose keys are the long names of the options,
+//! with either dashes or underscores:
+//!
+//! ```toml
+//! who = true
+//! who-min-size = 65536
+//! ignore-custom-section = [".debug_*", "name"]
+//! ```
+//!
+//! Options given on the command line override those in the config file.
+
+use anyhow::{bail, Context, Result};
+use std::{ffi::OsString, path::PathBuf};
+
+/// The command-line arguments, with those from the `--config` file, if any,
+/// inserted before the ones given on the command line.
+pub fn args() -> Result<Vec<OsString>> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let path = match config_path(&args) {
+        Some(path) => path,
+        None => return Ok(args),
+    };
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let table: toml::value::Table = toml::from_str(&contents)
+        .with_context(|| format!("failed to parse config {}", path.display()))?;
+
+    let given: Vec<String> = args[1..]
+        .iter()
+        .filter_map(|arg| arg.to_str())
+        .filter_map(|arg| arg.strip_prefix("--"))
+        .map(|arg| arg.split('=').next().unwrap().to_string())
+        .collect();
+    let mut from_config = vec![];
+    for (key, value) in table {
+        let flag = key.replace('_', "-");
+        if flag == "config" || given.contains(&flag) {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Boolean(true) => {
+                from_config.push(OsString::from(format!("--{}", flag)));
+                continue;
+            }
+            toml::Value::Boolean(false) => continue,
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                _ => bail!("unsupported value for `{}` in {}", key, path.display()),
+            };
+            from_config.push(OsString::from(format!("--{}", flag)));
+            from_config.push(OsString::from(value));
+        }
+    }
+    args.splice(1..1, from_config);
+    Ok(args)
+}
+
+/// The path given to `--config` in `args`.
+fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let arg = match arg.to_str() {
+            Some(arg) => arg,
+            None => continue,
+        };
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
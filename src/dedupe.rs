@@ -0,0 +1,298 @@
+//! The experimental `dedupe` subcommand, which actually removes the duplication
+//! that the measurements find.
+//!
+//! Every duplicated code body, data segment, and custom section payload of at
+//! least the minimum size is written once to `blobs/<hash>` in the output
+//! directory. Each module is rewritten to `modules/<path>` with those items
+//! taken out:
+//!
+//! * extracted code bodies are replaced by `unreachable`,
+//! * extracted data segments keep their kind and offset but get an empty
+//!   payload (their blob holds the whole original segment), and
+//! * extracted custom sections are removed.
+//!
+//! A `dedupe-manifest` custom section appended to every rewritten module lists
+//! what was extracted, one item per line, so that a loader can put the blobs
+//! back:
+//!
+//! * `code <function body index> <blob>`
+//! * `data <data segment index> <blob>`
+//! * `custom <section index> <blob> <section name>`
+//!
+//! Section indices count every section of the original module, starting at
+//! zero. Items inside nested modules are left alone.
+
+use crate::{hash, hex, print_row, Category, Counts, Options};
+use anyhow::{bail, ensure, Context, Result};
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the custom section listing a rewritten module's extracted items.
+const MANIFEST: &str = "dedupe-manifest";
+
+/// The body of a function that contains nothing but `unreachable`.
+const UNREACHABLE_BODY: &[u8] = &[0x00, 0x00, 0x0b];
+
+/// Write the blobs and rewritten modules for the corpus measured in `counts`,
+/// located in `corpus`, to `out_dir`, and print how their sizes compare to the
+/// corpus.
+pub fn run(
+    counts: &Counts,
+    corpus: &Path,
+    out_dir: &Path,
+    min_size: u64,
+    options: &Options,
+) -> Result<()> {
+    let blobs_dir = out_dir.join("blobs");
+    let modules_dir = out_dir.join("modules");
+    fs::create_dir_all(&blobs_dir)
+        .with_context(|| format!("failed to create {}", blobs_dir.display()))?;
+
+    let mut store = Store {
+        counts,
+        min_size,
+        dir: blobs_dir,
+        written: HashSet::new(),
+        size: 0,
+    };
+    let mut wasm = vec![];
+    let mut rewritten_size = 0;
+    for path in &counts.files {
+        counts.load(path, &mut wasm)?;
+        let rewritten = rewrite(&wasm, &mut store)
+            .with_context(|| format!("failed to rewrite {}", path.display()))?;
+        rewritten_size += rewritten.len() as u64;
+
+        let out = modules_dir.join(relative_path(corpus, path));
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        fs::write(&out, rewritten).with_context(|| format!("failed to write {}", out.display()))?;
+    }
+
+    print_row("Total size", counts.total_size, counts.total_size, options);
+    print_row(
+        "Rewritten modules",
+        rewritten_size,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        &format!("Blobs ({})", store.written.len()),
+        store.size,
+        counts.total_size,
+        options,
+    );
+    let deduped = rewritten_size + store.size;
+    print_row(
+        "Total deduplicated size",
+        deduped,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "Saved",
+        counts.total_size.saturating_sub(deduped),
+        counts.total_size,
+        options,
+    );
+    Ok(())
+}
+
+/// The path of `path`, which was found by walking `corpus`, relative to
+/// `corpus`.
+pub fn relative_path(corpus: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(corpus) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+        _ => path
+            .file_name()
+            .map_or_else(|| path.to_path_buf(), PathBuf::from),
+    }
+}
+
+/// The content-addressed blob directory.
+struct Store<'a> {
+    counts: &'a Counts,
+    min_size: u64,
+    dir: PathBuf,
+    /// The names of the blobs written so far.
+    written: HashSet<String>,
+    /// The total size of the blobs written so far.
+    size: u64,
+}
+
+impl Store<'_> {
+    /// If the item `data` of the given category is duplicated and large
+    /// enough, make sure it is in the store and return its blob's name.
+    fn extract(&mut self, category: Category, data: &[u8]) -> Result<Option<String>> {
+        if (data.len() as u64) < self.min_size {
+            return Ok(None);
+        }
+        let hash = hash(data, &self.counts.hasher);
+        let duplicated = self
+            .counts
+            .items
+            .get(&category)
+            .and_then(|items| items.get(&hash))
+            .map_or(false, |entry| entry.count > 1);
+        if !duplicated {
+            return Ok(None);
+        }
+
+        let name = hex(&hash);
+        if self.written.insert(name.clone()) {
+            let path = self.dir.join(&name);
+            fs::write(&path, data)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            self.size += data.len() as u64;
+        }
+        Ok(Some(name))
+    }
+}
+
+/// Rewrite the module `wasm` with its duplicated items extracted into `store`.
+fn rewrite(wasm: &[u8], store: &mut Store) -> Result<Vec<u8>> {
+    ensure!(
+        wasm.len() >= 8 && wasm.starts_with(b"\0asm"),
+        "not a Wasm module"
+    );
+
+    let mut module = wasm_encoder::Module::new();
+    let mut manifest = String::new();
+    let mut pos = 8;
+    let mut section_index = 0;
+    while pos < wasm.len() {
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_u32(wasm, &mut pos)? as usize;
+        let start = pos;
+        let end = start
+            .checked_add(size)
+            .filter(|end| *end <= wasm.len())
+            .context("section extends past the end of the module")?;
+        let contents = &wasm[start..end];
+        pos = end;
+
+        match id {
+            // Custom section.
+            0 => {
+                let mut name_pos = start;
+                let name_len = read_u32(wasm, &mut name_pos)? as usize;
+                ensure!(
+                    name_pos + name_len <= end,
+                    "custom section name is too long"
+                );
+                let name = std::str::from_utf8(&wasm[name_pos..name_pos + name_len])
+                    .context("custom section name is not UTF-8")?;
+                let data = &wasm[name_pos + name_len..end];
+                match store.extract(Category::CustomSections, data)? {
+                    Some(blob) => {
+                        writeln!(manifest, "custom {} {} {}", section_index, blob, name)?;
+                    }
+                    None => {
+                        module.section(&wasm_encoder::RawSection { id, data: contents });
+                    }
+                }
+            }
+            // Code section.
+            10 => {
+                let mut entry_pos = start;
+                let count = read_u32(wasm, &mut entry_pos)?;
+                let mut section = vec![];
+                write_u32(&mut section, count);
+                for i in 0..count {
+                    let body_size = read_u32(wasm, &mut entry_pos)? as usize;
+                    ensure!(entry_pos + body_size <= end, "code body is too long");
+                    let body = &wasm[entry_pos..entry_pos + body_size];
+                    entry_pos += body_size;
+                    let body = match store.extract(Category::CodeBodies, body)? {
+                        Some(blob) => {
+                            writeln!(manifest, "code {} {}", i, blob)?;
+                            UNREACHABLE_BODY
+                        }
+                        None => body,
+                    };
+                    write_u32(&mut section, body.len() as u32);
+                    section.extend_from_slice(body);
+                }
+                module.section(&wasm_encoder::RawSection { id, data: &section });
+            }
+            // Data section.
+            11 => {
+                let mut reader = wasmparser::DataSectionReader::new(contents, start)?;
+                let mut section = vec![];
+                write_u32(&mut section, reader.get_count());
+                for i in 0..reader.get_count() {
+                    let data = reader.read()?;
+                    let segment = &wasm[data.range.start..data.range.end];
+                    let blob = match store.extract(Category::DataSegments, segment)? {
+                        Some(blob) => blob,
+                        None => {
+                            section.extend_from_slice(segment);
+                            continue;
+                        }
+                    };
+                    writeln!(manifest, "data {} {}", i, blob)?;
+
+                    // Keep everything before the payload's length, and give it
+                    // an empty payload instead. The byte before the length is
+                    // either the segment's flags or the `end` of its offset
+                    // expression, neither of which has its high bit set.
+                    let payload_start = data.data.as_ptr() as usize - wasm.as_ptr() as usize;
+                    let mut len_start = payload_start - 1;
+                    while len_start > data.range.start && wasm[len_start - 1] & 0x80 != 0 {
+                        len_start -= 1;
+                    }
+                    section.extend_from_slice(&wasm[data.range.start..len_start]);
+                    write_u32(&mut section, 0);
+                }
+                module.section(&wasm_encoder::RawSection { id, data: &section });
+            }
+            _ => {
+                module.section(&wasm_encoder::RawSection { id, data: contents });
+            }
+        }
+        section_index += 1;
+    }
+
+    if !manifest.is_empty() {
+        module.section(&wasm_encoder::CustomSection {
+            name: MANIFEST,
+            data: manifest.as_bytes(),
+        });
+    }
+    Ok(module.finish())
+}
+
+/// Read an unsigned LEB128 `u32` at `*pos` in `bytes`, advancing `*pos` past
+/// it.
+pub fn read_u32(bytes: &[u8], pos: &mut usize) -> Result<u32> {
+    let mut result = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(*pos).context("unexpected end of module")?;
+        *pos += 1;
+        result |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    bail!("invalid LEB128 u32")
+}
+
+/// Write `n` as an unsigned LEB128.
+pub fn write_u32(out: &mut Vec<u8>, mut n: u32) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
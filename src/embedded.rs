@@ -0,0 +1,59 @@
+//! Finding Wasm modules embedded in the custom sections of other modules, as
+//! some bundlers, test harnesses, and component adapters do.
+
+use crate::dedupe::read_u32;
+
+/// The magic number and version that every module starts with.
+const HEADER: &[u8; 8] = b"\0asm\x01\0\0\0";
+
+/// The largest known section id, of the tag section.
+const MAX_SECTION_ID: u8 = 13;
+
+/// The modules embedded in the custom section contents `data`, as ranges of
+/// `data`.
+pub fn modules(data: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut modules = vec![];
+    let mut start = 0;
+    while let Some(found) = find(&data[start..], HEADER) {
+        let module_start = start + found;
+        let module_end = module_start + len(&data[module_start..]);
+        let module = &data[module_start..module_end];
+        if wasmparser::Parser::new(0)
+            .parse_all(module)
+            .all(|payload| payload.is_ok())
+        {
+            modules.push(module_start..module_end);
+            start = module_end;
+        } else {
+            start = module_start + 1;
+        }
+    }
+    modules
+}
+
+/// The length of the module at the start of `bytes`: its header and every
+/// section after it that looks well-formed.
+fn len(bytes: &[u8]) -> usize {
+    let mut pos = HEADER.len();
+    loop {
+        let end = pos;
+        match bytes.get(pos) {
+            Some(id) if *id <= MAX_SECTION_ID => pos += 1,
+            _ => return end,
+        }
+        let size = match read_u32(bytes, &mut pos) {
+            Ok(size) => size as usize,
+            Err(_) => return end,
+        };
+        match pos.checked_add(size).filter(|next| *next <= bytes.len()) {
+            Some(next) => pos = next,
+            None => return end,
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
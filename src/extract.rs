@@ -0,0 +1,412 @@
+//! Finding the items to deduplicate in the payloads of Wasm modules.
+//!
+//! Each kind of item is found by a `DedupeKeyExtractor`, which library users
+//! can implement to experiment with their own dedupe keys, such as per-crate
+//! code regions. The strict categories of the report are found by the
+//! extractors here, both for `Counts` and for a library user's `Counter`, so
+//! the two always agree on what an item is.
+
+use crate::{gc, Category, CustomKey, CustomSectionFilter};
+use anyhow::{Context, Result};
+use std::{borrow::Cow, collections::HashSet};
+
+/// An item found by a [`DedupeKeyExtractor`].
+pub struct Item<'a> {
+    /// The bytes identifying the item: items with equal keys are duplicates.
+    pub key: Cow<'a, [u8]>,
+    /// The size of the item, which for a normalized key may differ from the
+    /// key's length.
+    pub size: u64,
+}
+
+impl<'a> Item<'a> {
+    /// An item keyed by its own bytes.
+    pub fn exact(bytes: &'a [u8]) -> Self {
+        Item {
+            key: Cow::Borrowed(bytes),
+            size: bytes.len() as u64,
+        }
+    }
+}
+
+/// Finds the items of one kind in the payloads of modules.
+pub trait DedupeKeyExtractor {
+    /// The name of the kind of item, such as `code bodies`.
+    fn name(&self) -> &str;
+
+    /// Push the items in `payload`, which was parsed from the file `wasm`, to
+    /// `items`. Called for every payload of every module in the file,
+    /// including nested ones, in order, so extractors may keep state between
+    /// calls.
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()>;
+}
+
+/// Data segments, keyed by their bytes.
+pub struct DataSegments;
+
+impl DedupeKeyExtractor for DataSegments {
+    fn name(&self) -> &str {
+        Category::DataSegments.name()
+    }
+
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()> {
+        if let wasmparser::Payload::DataSection(reader) = payload {
+            let mut reader = reader.clone();
+            for _ in 0..reader.get_count() {
+                let data = reader.read()?;
+                items.push(Item::exact(&wasm[data.range.start..data.range.end]));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Element segments, keyed by their bytes.
+pub struct ElemSegments;
+
+impl DedupeKeyExtractor for ElemSegments {
+    fn name(&self) -> &str {
+        Category::ElemSegments.name()
+    }
+
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()> {
+        if let wasmparser::Payload::ElementSection(reader) = payload {
+            let mut reader = reader.clone();
+            for _ in 0..reader.get_count() {
+                let elem = reader.read()?;
+                items.push(Item::exact(&wasm[elem.range.start..elem.range.end]));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Code bodies, keyed by their bytes.
+pub struct CodeBodies;
+
+impl DedupeKeyExtractor for CodeBodies {
+    fn name(&self) -> &str {
+        Category::CodeBodies.name()
+    }
+
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()> {
+        if let wasmparser::Payload::CodeSectionEntry(body) = payload {
+            let range = body.range();
+            items.push(Item::exact(&wasm[range.start..range.end]));
+        }
+        Ok(())
+    }
+}
+
+/// Custom sections, keyed by their contents, or their name and contents with
+/// `--custom-key name+data`, and only those that `--ignore-custom-section` and
+/// `--only-custom-section` let through.
+#[derive(Default)]
+pub struct CustomSections {
+    pub(crate) key: CustomKey,
+    pub(crate) filter: CustomSectionFilter,
+}
+
+impl DedupeKeyExtractor for CustomSections {
+    fn name(&self) -> &str {
+        Category::CustomSections.name()
+    }
+
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()> {
+        if let wasmparser::Payload::CustomSection {
+            name, data, range, ..
+        } = payload
+        {
+            if self.filter.includes(name) {
+                items.push(Item::exact(self.key.key(wasm, range, data)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Entries of the exception-handling tag section, keyed by their bytes.
+pub struct Tags;
+
+impl DedupeKeyExtractor for Tags {
+    fn name(&self) -> &str {
+        Category::Tags.name()
+    }
+
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()> {
+        if let wasmparser::Payload::TagSection(reader) = payload {
+            let mut reader = reader.clone();
+            for _ in 0..reader.get_count() {
+                let start = reader.original_position();
+                reader.read()?;
+                items.push(Item::exact(&wasm[start..reader.original_position()]));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The recursion groups of the GC proposal in type sections, keyed by their
+/// bytes.
+pub struct GcRecGroups;
+
+impl DedupeKeyExtractor for GcRecGroups {
+    fn name(&self) -> &str {
+        Category::GcRecGroups.name()
+    }
+
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()> {
+        if let wasmparser::Payload::TypeSection(reader) = payload {
+            let range = reader.range();
+            match gc::rec_groups(&wasm[range.start..range.end]) {
+                Ok(groups) => items.extend(groups.into_iter().map(Item::exact)),
+                // Such as the module and instance types of the module linking
+                // proposal.
+                Err(e) => log::debug!("Skipping unsupported type section: {:#}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Entries of the table section, keyed by their bytes.
+pub struct Tables;
+
+impl DedupeKeyExtractor for Tables {
+    fn name(&self) -> &str {
+        Category::Tables.name()
+    }
+
+    fn extract<'a>(
+        &mut self,
+        wasm: &'a [u8],
+        payload: &wasmparser::Payload<'a>,
+        items: &mut Vec<Item<'a>>,
+    ) -> Result<()> {
+        if let wasmparser::Payload::TableSection(reader) = payload {
+            let mut reader = reader.clone();
+            for _ in 0..reader.get_count() {
+                let start = reader.original_position();
+                reader.read()?;
+                items.push(Item::exact(&wasm[start..reader.original_position()]));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The extractors of the strict categories of the report, with every custom
+/// section keyed by its contents.
+pub fn builtin() -> Vec<Box<dyn DedupeKeyExtractor>> {
+    builtin_with(CustomSections::default())
+        .into_iter()
+        .map(|(_, extractor)| extractor as Box<dyn DedupeKeyExtractor>)
+        .collect()
+}
+
+/// The extractors of some categories, with the category of each.
+pub(crate) type Extractors = Vec<(Category, Box<dyn DedupeKeyExtractor + Send + Sync>)>;
+
+/// The extractors of the strict categories, in the order of `Category::ALL`,
+/// with `custom_sections` for the custom sections.
+pub(crate) fn builtin_with(custom_sections: CustomSections) -> Extractors {
+    vec![
+        (Category::DataSegments, Box::new(DataSegments)),
+        (Category::ElemSegments, Box::new(ElemSegments)),
+        (Category::CodeBodies, Box::new(CodeBodies)),
+        (Category::CustomSections, Box::new(custom_sections)),
+        (Category::Tags, Box::new(Tags)),
+        (Category::GcRecGroups, Box::new(GcRecGroups)),
+        (Category::Tables, Box::new(Tables)),
+    ]
+}
+
+/// The totals of the items of one extractor.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Totals {
+    /// The size of every occurrence of every item.
+    pub size: u64,
+    /// The number of occurrences of every item.
+    pub items: u64,
+    /// The size of every occurrence but the first of every distinct item.
+    pub duplicated: u64,
+}
+
+/// Measures the duplication of the items that a set of extractors find in a
+/// corpus.
+pub struct Counter {
+    extractors: Vec<Box<dyn DedupeKeyExtractor>>,
+    /// The hashes of the keys each extractor has seen.
+    seen: Vec<HashSet<blake3::Hash>>,
+    totals: Vec<Totals>,
+}
+
+impl Counter {
+    pub fn new(extractors: Vec<Box<dyn DedupeKeyExtractor>>) -> Self {
+        Counter {
+            seen: extractors.iter().map(|_| HashSet::new()).collect(),
+            totals: vec![Totals::default(); extractors.len()],
+            extractors,
+        }
+    }
+
+    /// Add the items in the file `wasm`, and in any modules nested within it.
+    pub fn add_module(&mut self, wasm: &[u8]) -> Result<()> {
+        let mut items = vec![];
+        let mut input = wasm;
+        let mut parsers = vec![wasmparser::Parser::new(0)];
+        while let Some(parser) = parsers.last_mut() {
+            let (payload, consumed) =
+                match parser.parse(input, true).context("failed to parse Wasm")? {
+                    wasmparser::Chunk::NeedMoreData(_) => unreachable!(),
+                    wasmparser::Chunk::Parsed { consumed, payload } => (payload, consumed),
+                };
+            input = &input[consumed..];
+
+            for (i, extractor) in self.extractors.iter_mut().enumerate() {
+                extractor
+                    .extract(wasm, &payload, &mut items)
+                    .with_context(|| format!("failed to extract {}", extractor.name()))?;
+                let totals = &mut self.totals[i];
+                for item in items.drain(..) {
+                    totals.size += item.size;
+                    totals.items += 1;
+                    if !self.seen[i].insert(blake3::hash(&item.key)) {
+                        totals.duplicated += item.size;
+                    }
+                }
+            }
+
+            match payload {
+                wasmparser::Payload::ModuleSectionEntry { parser, .. } => parsers.push(parser),
+                wasmparser::Payload::End => {
+                    parsers.pop();
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The name and totals of every extractor.
+    pub fn totals(&self) -> impl Iterator<Item = (&str, Totals)> + '_ {
+        self.extractors
+            .iter()
+            .map(|extractor| extractor.name())
+            .zip(self.totals.iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_encoder::{
+        CodeSection, Function, FunctionSection, Instruction, Module, TypeSection, ValType,
+    };
+
+    /// A module with one function of type `[] -> [i32]` returning `value`.
+    fn module(value: i32) -> Vec<u8> {
+        let mut types = TypeSection::new();
+        types.function(vec![], vec![ValType::I32]);
+        let mut functions = FunctionSection::new();
+        functions.function(0);
+        let mut function = Function::new(vec![]);
+        function.instruction(Instruction::I32Const(value));
+        function.instruction(Instruction::End);
+        let mut code = CodeSection::new();
+        code.function(&function);
+        let mut module = Module::new();
+        module.section(&types).section(&functions).section(&code);
+        module.finish()
+    }
+
+    /// Code bodies keyed by their length alone.
+    struct BodyLengths;
+
+    impl DedupeKeyExtractor for BodyLengths {
+        fn name(&self) -> &str {
+            "body lengths"
+        }
+
+        fn extract<'a>(
+            &mut self,
+            _wasm: &'a [u8],
+            payload: &wasmparser::Payload<'a>,
+            items: &mut Vec<Item<'a>>,
+        ) -> Result<()> {
+            if let wasmparser::Payload::CodeSectionEntry(body) = payload {
+                let size = body.range().end - body.range().start;
+                items.push(Item {
+                    key: Cow::Owned(size.to_le_bytes().to_vec()),
+                    size: size as u64,
+                });
+            }
+            Ok(())
+        }
+    }
+
+    fn totals(counter: &Counter, name: &str) -> Totals {
+        counter
+            .totals()
+            .find(|(n, _)| *n == name)
+            .map(|(_, totals)| totals)
+            .unwrap()
+    }
+
+    #[test]
+    fn builtin_code_bodies_are_duplicated_across_modules() {
+        let mut counter = Counter::new(builtin());
+        counter.add_module(&module(1)).unwrap();
+        counter.add_module(&module(1)).unwrap();
+        let code = totals(&counter, "code bodies");
+        assert_eq!(code.items, 2);
+        assert_eq!(code.duplicated * 2, code.size);
+    }
+
+    #[test]
+    fn custom_extractors_use_their_own_keys() {
+        let mut extractors = builtin();
+        extractors.push(Box::new(BodyLengths));
+        let mut counter = Counter::new(extractors);
+        counter.add_module(&module(1)).unwrap();
+        counter.add_module(&module(2)).unwrap();
+        assert_eq!(totals(&counter, "code bodies").duplicated, 0);
+        let lengths = totals(&counter, "body lengths");
+        assert_eq!(lengths.duplicated * 2, lengths.size);
+    }
+}
@@ -0,0 +1,72 @@
+//! The Wasm proposals that files are validated against, for `--features`.
+
+use anyhow::{bail, Result};
+use wasmparser::WasmFeatures;
+
+/// Enables a proposal in a set of features.
+type Enable = fn(&mut WasmFeatures);
+
+/// The proposals that `--features` may name, and how to enable each of them.
+const PROPOSALS: &[(&str, Enable)] = &[
+    ("reference-types", |f| f.reference_types = true),
+    ("multi-value", |f| f.multi_value = true),
+    ("bulk-memory", |f| f.bulk_memory = true),
+    ("module-linking", |f| f.module_linking = true),
+    ("simd", |f| f.simd = true),
+    ("relaxed-simd", |f| f.relaxed_simd = true),
+    ("threads", |f| f.threads = true),
+    ("tail-call", |f| f.tail_call = true),
+    ("multi-memory", |f| f.multi_memory = true),
+    ("exceptions", |f| f.exceptions = true),
+    ("memory64", |f| f.memory64 = true),
+];
+
+/// Parse `list`, a comma-separated list of proposal names, `all`, or `mvp`.
+pub fn parse(list: &str) -> Result<WasmFeatures> {
+    let mut features = mvp();
+    for name in list
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        match name {
+            "all" => {
+                for (_, enable) in PROPOSALS {
+                    enable(&mut features);
+                }
+            }
+            "mvp" => {}
+            _ => match PROPOSALS.iter().find(|(proposal, _)| *proposal == name) {
+                Some((_, enable)) => enable(&mut features),
+                None => bail!(
+                    "unknown proposal `{}` in `--features`; expected `all`, `mvp`, or one of: {}",
+                    name,
+                    PROPOSALS
+                        .iter()
+                        .map(|(proposal, _)| *proposal)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+        }
+    }
+    Ok(features)
+}
+
+/// The features of the MVP, which every other proposal is added to.
+fn mvp() -> WasmFeatures {
+    WasmFeatures {
+        reference_types: false,
+        multi_value: false,
+        bulk_memory: false,
+        module_linking: false,
+        simd: false,
+        relaxed_simd: false,
+        threads: false,
+        tail_call: false,
+        deterministic_only: false,
+        multi_memory: false,
+        exceptions: false,
+        memory64: false,
+    }
+}
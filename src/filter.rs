@@ -0,0 +1,45 @@
+//! The approximate counting filter behind `--low-memory`.
+//!
+//! The first pass over the corpus adds every item to the filter, and the second
+//! pass only keeps the items that the filter says may have been seen more than
+//! once. The filter can have false positives, which just cost some memory, but
+//! never false negatives, so the measurements are exact.
+
+use crate::Category;
+
+/// The number of counters each item increments.
+const HASHES: u64 = 3;
+
+/// A counting Bloom filter whose counters saturate at two.
+pub struct CountingFilter {
+    counters: Vec<u8>,
+}
+
+impl CountingFilter {
+    /// A filter with `size` counters, of a byte each.
+    pub fn new(size: usize) -> Self {
+        CountingFilter {
+            counters: vec![0; size.max(1)],
+        }
+    }
+
+    /// The indices of the counters of the item hashing to `hash`.
+    fn indices(&self, category: Category, hash: &[u8]) -> impl Iterator<Item = usize> {
+        let h = u64::from_le_bytes(hash[..8].try_into().unwrap())
+            ^ (category as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let step = h.rotate_left(32) | 1;
+        let len = self.counters.len() as u64;
+        (0..HASHES).map(move |i| (h.wrapping_add(i.wrapping_mul(step)) % len) as usize)
+    }
+
+    pub fn add(&mut self, category: Category, hash: &[u8]) {
+        for i in self.indices(category, hash) {
+            self.counters[i] = (self.counters[i] + 1).min(2);
+        }
+    }
+
+    /// Whether the item hashing to `hash` may have been added more than once.
+    pub fn maybe_duplicated(&self, category: Category, hash: &[u8]) -> bool {
+        self.indices(category, hash).all(|i| self.counters[i] > 1)
+    }
+}
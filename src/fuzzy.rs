@@ -0,0 +1,192 @@
+//! Near-duplicate detection with MinHash signatures and locality-sensitive
+//! hashing (LSH).
+//!
+//! Each item is split into overlapping byte shingles, and its signature is the
+//! minimum shingle hash in each of `BINS` bins (one-permutation hashing, with
+//! empty bins filled by rotation densification). The fraction of bins in which
+//! two signatures agree estimates the Jaccard similarity of the items' shingle
+//! sets. Signatures are bucketed by bands of `ROWS` bins so that only items
+//! that are likely to be similar get compared.
+
+use std::collections::HashMap;
+
+/// The number of bytes in each shingle.
+const SHINGLE: usize = 8;
+
+/// The number of bins in each signature.
+const BINS: usize = 64;
+
+/// The number of bins in each LSH band.
+const ROWS: usize = 4;
+
+/// A MinHash signature of an item.
+type Signature = [u64; BINS];
+
+/// An item that has been added to an `Index`.
+pub struct Item {
+    /// The item's size, in bytes.
+    pub size: u64,
+    /// The index (into `Counts::files`) of the first file containing the item.
+    pub file: usize,
+    signature: Signature,
+}
+
+/// A group of items that are near-duplicates of each other.
+pub struct Cluster {
+    /// Indices into `Index::items`. The first member is the cluster's largest
+    /// item, which the others are compared against.
+    pub members: Vec<usize>,
+    /// The total size of the cluster's items.
+    pub size: u64,
+    /// The estimated number of bytes the non-representative members share with
+    /// the representative member.
+    pub shared: u64,
+}
+
+/// A collection of items to search for near-duplicates.
+#[derive(Default)]
+pub struct Index {
+    pub items: Vec<Item>,
+}
+
+impl Index {
+    /// Add an item. Items smaller than a single shingle are ignored.
+    pub fn add(&mut self, data: &[u8], file: usize) {
+        if let Some(signature) = signature(data) {
+            self.items.push(Item {
+                size: data.len() as u64,
+                file,
+                signature,
+            });
+        }
+    }
+
+    /// Group the items into clusters of near-duplicates whose estimated
+    /// similarity to their cluster's largest item is at least `threshold`
+    /// (between 0 and 1).
+    ///
+    /// Clusters are returned with the most estimated shared bytes first.
+    pub fn clusters(&self, threshold: f64) -> Vec<Cluster> {
+        let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+        for (i, item) in self.items.iter().enumerate() {
+            for band in 0..BINS / ROWS {
+                let rows = &item.signature[band * ROWS..(band + 1) * ROWS];
+                let key = rows.iter().fold(0, |h, row| mix(h ^ row));
+                buckets.entry((band, key)).or_default().push(i);
+            }
+        }
+
+        let mut sets = UnionFind::new(self.items.len());
+        for bucket in buckets.values() {
+            let first = bucket[0];
+            for &other in &bucket[1..] {
+                if self.similarity(first, other) >= threshold {
+                    sets.union(first, other);
+                }
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..self.items.len() {
+            groups.entry(sets.find(i)).or_default().push(i);
+        }
+
+        let mut clusters: Vec<_> = groups
+            .into_values()
+            .filter(|members| members.len() > 1)
+            .map(|mut members| {
+                members.sort_by_key(|i| (std::cmp::Reverse(self.items[*i].size), *i));
+                let representative = members[0];
+                let size = members.iter().map(|i| self.items[*i].size).sum();
+                let shared = members[1..]
+                    .iter()
+                    .map(|i| {
+                        (self.similarity(representative, *i) * self.items[*i].size as f64) as u64
+                    })
+                    .sum();
+                Cluster {
+                    members,
+                    size,
+                    shared,
+                }
+            })
+            .collect();
+        clusters.sort_by_key(|c| (std::cmp::Reverse(c.shared), c.members[0]));
+        clusters
+    }
+
+    /// The estimated Jaccard similarity of two items' shingle sets.
+    fn similarity(&self, a: usize, b: usize) -> f64 {
+        let a = &self.items[a].signature;
+        let b = &self.items[b].signature;
+        let same = a.iter().zip(b.iter()).filter(|(a, b)| a == b).count();
+        same as f64 / BINS as f64
+    }
+}
+
+fn signature(data: &[u8]) -> Option<Signature> {
+    if data.len() < SHINGLE {
+        return None;
+    }
+
+    let mut signature = [u64::MAX; BINS];
+    for shingle in data.windows(SHINGLE) {
+        let h = mix(u64::from_le_bytes(shingle.try_into().unwrap()));
+        let bin = (h >> 58) as usize;
+        let value = h << 6;
+        if value < signature[bin] {
+            signature[bin] = value;
+        }
+    }
+
+    // Fill each empty bin from the next non-empty bin, offset by the distance
+    // travelled so that borrowed values don't spuriously match. There is at
+    // least one shingle, so at least one bin is non-empty.
+    let filled = signature;
+    for (bin, value) in signature.iter_mut().enumerate() {
+        let mut distance = 1;
+        while *value == u64::MAX {
+            let donor = filled[(bin + distance) % BINS];
+            if donor != u64::MAX {
+                *value = donor.wrapping_add(distance as u64 * 0x9e37_79b9_7f4a_7c15);
+            }
+            distance += 1;
+        }
+    }
+
+    Some(signature)
+}
+
+/// The splitmix64 finalizer.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        UnionFind {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let a = self.find(a);
+        let b = self.find(b);
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
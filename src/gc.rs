@@ -0,0 +1,99 @@
+//! Splitting type sections into the recursion groups of the GC proposal.
+//!
+//! `wasmparser` doesn't know about GC types yet, so this scans just enough of
+//! the type section's encoding to find where each recursion group ends.
+
+use crate::dedupe::read_u32;
+use anyhow::{bail, Context, Result};
+
+/// The recursion groups of the type section `section`, including the implicit
+/// ones around each lone non-function type, as byte slices of `section`.
+/// Plain function types that aren't in a GC recursion group are skipped,
+/// since they predate the GC proposal.
+pub fn rec_groups(section: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut pos = 0;
+    let count = read_u32(section, &mut pos)?;
+    let mut groups = vec![];
+    for _ in 0..count {
+        let start = pos;
+        let form = *section.get(pos).context("unexpected end of type section")?;
+        if form == 0x4e {
+            // An explicit recursion group.
+            pos += 1;
+            for _ in 0..read_u32(section, &mut pos)? {
+                sub_type(section, &mut pos)?;
+            }
+        } else {
+            sub_type(section, &mut pos)?;
+        }
+        if form != 0x60 {
+            groups.push(&section[start..pos]);
+        }
+    }
+    Ok(groups)
+}
+
+/// Skip the sub type at `*pos`.
+fn sub_type(bytes: &[u8], pos: &mut usize) -> Result<()> {
+    let mut form = byte(bytes, pos)?;
+    if let 0x50 | 0x4f = form {
+        // `sub` or `sub final`, with a list of supertype indices.
+        for _ in 0..read_u32(bytes, pos)? {
+            read_u32(bytes, pos)?;
+        }
+        form = byte(bytes, pos)?;
+    }
+    composite_type(bytes, pos, form)
+}
+
+/// Skip the composite type of the given `form` whose contents are at `*pos`.
+fn composite_type(bytes: &[u8], pos: &mut usize, form: u8) -> Result<()> {
+    match form {
+        // Function type.
+        0x60 => {
+            for _ in 0..2 {
+                for _ in 0..read_u32(bytes, pos)? {
+                    value_type(bytes, pos)?;
+                }
+            }
+        }
+        // Struct type.
+        0x5f => {
+            for _ in 0..read_u32(bytes, pos)? {
+                value_type(bytes, pos)?;
+                byte(bytes, pos)?;
+            }
+        }
+        // Array type.
+        0x5e => {
+            value_type(bytes, pos)?;
+            byte(bytes, pos)?;
+        }
+        _ => bail!("unknown type form 0x{:02x}", form),
+    }
+    Ok(())
+}
+
+/// Skip the value or packed storage type at `*pos`.
+fn value_type(bytes: &[u8], pos: &mut usize) -> Result<()> {
+    match byte(bytes, pos)? {
+        // `(ref ht)` and `(ref null ht)`, where the heap type is either one
+        // byte of an abstract heap type or a type index as an `s33`.
+        0x64 | 0x63 => {
+            let heap_type = byte(bytes, pos)?;
+            if heap_type & 0x80 != 0 || heap_type & 0x40 == 0 {
+                *pos -= 1;
+                read_u32(bytes, pos)?;
+            }
+        }
+        // Numeric, vector, packed, and abbreviated reference types.
+        _ => {}
+    }
+    Ok(())
+}
+
+fn byte(bytes: &[u8], pos: &mut usize) -> Result<u8> {
+    let byte = *bytes.get(*pos).context("unexpected end of type section")?;
+    *pos += 1;
+    Ok(byte)
+}
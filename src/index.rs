@@ -0,0 +1,156 @@
+//! Exporting every category's hash index, for joining the indexes of separate
+//! corpora offline without scanning them again, and reading one back to add
+//! more files to it.
+//!
+//! An index file is little-endian throughout:
+//!
+//! * the magic bytes `WDIX` and a `u32` format version, currently 1;
+//! * the hasher's name, as a `u8` length and that many UTF-8 bytes, and the
+//!   length of every hash, as a `u32`;
+//! * the corpus's total size and number of files, as `u64`s;
+//! * the number of categories, as a `u32`, followed by each category: its name,
+//!   as a `u8` length and that many UTF-8 bytes, and its number of items, as a
+//!   `u64`, followed by each item's hash, its size, the number of copies of it
+//!   in the corpus, and the number of files containing it, as `u64`s.
+//!
+//! Indexes can only be joined when they were written with the same hasher and
+//! hash length.
+
+use crate::{Category, Counts, SizeEntry};
+use anyhow::{bail, ensure, Context, Result};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 4] = b"WDIX";
+const VERSION: u32 = 1;
+
+/// Write the index of every category in `counts` to `path`.
+pub fn write(counts: &Counts, path: &Path) -> Result<()> {
+    // Write to a temporary file first, so that an interrupted run doesn't
+    // leave a truncated index behind in place of the one it appends to.
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let file =
+        File::create(&tmp).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    write_index(counts, &mut out).with_context(|| format!("failed to write {}", path.display()))?;
+    drop(out);
+    std::fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Add the files and items of the index at `path` to `counts`, as if the files
+/// it was written from had been measured before any file added after.
+pub fn read_into(counts: &mut Counts, path: &Path) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    read_index(counts, &mut BufReader::new(file))
+        .with_context(|| format!("failed to read index {}", path.display()))
+}
+
+fn read_index(counts: &mut Counts, input: &mut impl Read) -> Result<()> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    ensure!(&magic == MAGIC, "not an index file");
+    let version = read_u32(input)?;
+    ensure!(version == VERSION, "unsupported index version {}", version);
+    let hasher = read_name(input)?;
+    let hash_bytes = read_u32(input)? as usize;
+    let expected = format!("{:?}", counts.hasher.algorithm).to_lowercase();
+    if hasher != expected || hash_bytes != counts.hasher.bytes {
+        bail!(
+            "the index was written with `--hasher {} --hash-bytes {}`, not `--hasher {} \
+             --hash-bytes {}`",
+            hasher,
+            hash_bytes,
+            expected,
+            counts.hasher.bytes
+        );
+    }
+    counts.total_size += read_u64(input)?;
+    counts.indexed_files += read_u64(input)?;
+
+    for _ in 0..read_u32(input)? {
+        let name = read_name(input)?;
+        let category = Category::from_name(&name)
+            .with_context(|| format!("unknown category in index: {}", name))?;
+        let totals = counts.category_totals.entry(category).or_default();
+        let items = counts.items.entry(category).or_default();
+        for _ in 0..read_u64(input)? {
+            let mut hash = vec![0; hash_bytes].into_boxed_slice();
+            input.read_exact(&mut hash)?;
+            let size = read_u64(input)?;
+            let count = read_u64(input)?;
+            let modules = read_u64(input)?;
+            let entry = items.entry(hash).or_insert_with(|| {
+                totals.unique += 1;
+                SizeEntry {
+                    size,
+                    // No module added after this one is the last module of an
+                    // indexed item.
+                    last_module: usize::MAX,
+                    ..SizeEntry::default()
+                }
+            });
+            entry.count += count;
+            entry.modules += modules;
+            entry.indexed += count;
+            totals.size += size * count;
+            totals.items += count;
+        }
+    }
+    Ok(())
+}
+
+fn write_index(counts: &Counts, out: &mut impl Write) -> Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    write_name(
+        out,
+        &format!("{:?}", counts.hasher.algorithm).to_lowercase(),
+    )?;
+    out.write_all(&(counts.hasher.bytes as u32).to_le_bytes())?;
+    out.write_all(&counts.total_size.to_le_bytes())?;
+    out.write_all(&(counts.files.len() as u64 + counts.indexed_files).to_le_bytes())?;
+
+    out.write_all(&(counts.items.len() as u32).to_le_bytes())?;
+    for (category, items) in &counts.items {
+        write_name(out, category.name())?;
+        out.write_all(&(items.len() as u64).to_le_bytes())?;
+        for (hash, entry) in counts.sorted_items(*category) {
+            out.write_all(hash)?;
+            out.write_all(&entry.size.to_le_bytes())?;
+            out.write_all(&entry.count.to_le_bytes())?;
+            out.write_all(&entry.modules.to_le_bytes())?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_name(input: &mut impl Read) -> Result<String> {
+    let mut len = [0; 1];
+    input.read_exact(&mut len)?;
+    let mut name = vec![0; len[0] as usize];
+    input.read_exact(&mut name)?;
+    String::from_utf8(name).context("name is not UTF-8")
+}
+
+fn write_name(out: &mut impl Write, name: &str) -> Result<()> {
+    out.write_all(&[name.len() as u8])?;
+    out.write_all(name.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,198 @@
+//! The summary as versioned JSON, for `--format json=v1`.
+//!
+//! Tools built on this output rely on its shape, so a version of the schema
+//! only ever gains fields. Removing, renaming, or changing the meaning of a
+//! field needs a new version, which has to be asked for explicitly.
+
+use crate::{against::Against, run::Run, Category, Counts, Duplication};
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct SummaryV1 {
+    schema_version: u32,
+    /// The total size of every file.
+    total_size: u64,
+    files: usize,
+    /// The files that failed validation with `--features`, which are left out
+    /// of every measurement.
+    skipped_files: usize,
+    /// The files over `--max-file-size`, and their total size, which are left
+    /// out of every measurement.
+    oversized_files: u64,
+    oversized_bytes: u64,
+    /// Items smaller than this are left out of the duplicated bytes.
+    min_size: u64,
+    /// The duplicated bytes of the strict categories.
+    duplicated: DuplicationV1,
+    /// Every category that was measured, strict ones first.
+    categories: Vec<CategoryV1>,
+    /// What produced the summary.
+    run: Run,
+}
+
+#[derive(Serialize)]
+struct DuplicationV1 {
+    total: u64,
+    within_modules: u64,
+    across_modules: u64,
+}
+
+#[derive(Serialize)]
+struct CategoryV1 {
+    name: &'static str,
+    /// Whether the category counts towards the total duplicated bytes, rather
+    /// than being a normalization or an analysis that overlaps with them.
+    strict: bool,
+    /// The total size of every occurrence of every item.
+    size: u64,
+    /// The number of occurrences of every item.
+    items: u64,
+    /// The number of distinct items.
+    unique: u64,
+    duplicated: DuplicationV1,
+}
+
+impl From<Duplication> for DuplicationV1 {
+    fn from(dupe: Duplication) -> Self {
+        DuplicationV1 {
+            total: dupe.total(),
+            within_modules: dupe.within_modules,
+            across_modules: dupe.across_modules,
+        }
+    }
+}
+
+/// Print the summary of `counts`, measured by `run`, to stdout as version 1 of
+/// the schema.
+pub fn print_v1(counts: &Counts, run: Run) -> Result<()> {
+    let mut duplicated = Duplication::default();
+    for category in Category::ALL {
+        duplicated += counts.duplicated(*category);
+    }
+
+    let mut categories: Vec<_> = counts.items.keys().copied().collect();
+    categories.sort_by_key(|category| !Category::ALL.contains(category));
+    let summary = SummaryV1 {
+        schema_version: 1,
+        total_size: counts.total_size,
+        files: counts.files.len(),
+        skipped_files: counts.skipped.len(),
+        oversized_files: counts.oversized_files,
+        oversized_bytes: counts.oversized_bytes,
+        min_size: counts.min_size,
+        duplicated: duplicated.into(),
+        categories: categories
+            .into_iter()
+            .map(|category| {
+                let totals = counts
+                    .category_totals
+                    .get(&category)
+                    .copied()
+                    .unwrap_or_default();
+                CategoryV1 {
+                    name: category.name(),
+                    strict: Category::ALL.contains(&category),
+                    size: totals.size,
+                    items: totals.items,
+                    unique: totals.unique,
+                    duplicated: counts.duplicated(category).into(),
+                }
+            })
+            .collect(),
+        run,
+    };
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    serde_json::to_writer_pretty(&mut out, &summary)?;
+    std::io::Write::write_all(&mut out, b"\n")?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct OverlapV1 {
+    schema_version: u32,
+    base: CorpusV1,
+    candidate: CorpusV1,
+    /// Items smaller than this are left out.
+    min_size: u64,
+    /// The total size of the candidate's distinct items, and of those of them
+    /// also in the base, over every strict category.
+    size: u64,
+    in_base: u64,
+    categories: Vec<OverlapCategoryV1>,
+    /// The bytes of each candidate module that are copies of base items.
+    modules: Vec<OverlapModuleV1>,
+    /// What produced the summary.
+    run: Run,
+}
+
+#[derive(Serialize)]
+struct CorpusV1 {
+    files: usize,
+    total_size: u64,
+}
+
+#[derive(Serialize)]
+struct OverlapCategoryV1 {
+    name: &'static str,
+    size: u64,
+    in_base: u64,
+}
+
+#[derive(Serialize)]
+struct OverlapModuleV1 {
+    path: String,
+    size: u64,
+    in_base: u64,
+}
+
+/// Print how much of the candidate corpus measured in `counts` is in the base
+/// corpus of `against`, as version 1 of the `overlap` schema.
+pub fn print_overlap_v1(counts: &Counts, against: &Against, run: Run) -> Result<()> {
+    let categories: Vec<_> = against
+        .overlap(counts)
+        .into_iter()
+        .map(|(category, in_base, size)| OverlapCategoryV1 {
+            name: category.name(),
+            size,
+            in_base,
+        })
+        .collect();
+    let overlap = OverlapV1 {
+        schema_version: 1,
+        base: CorpusV1 {
+            files: against.base_files,
+            total_size: against.base_size,
+        },
+        candidate: CorpusV1 {
+            files: counts.files.len(),
+            total_size: counts.total_size,
+        },
+        min_size: counts.min_size,
+        size: categories.iter().map(|category| category.size).sum(),
+        in_base: categories.iter().map(|category| category.in_base).sum(),
+        categories,
+        modules: counts
+            .files
+            .iter()
+            .enumerate()
+            .map(|(file, path)| OverlapModuleV1 {
+                path: path.display().to_string(),
+                size: counts.file_sizes[file],
+                in_base: against
+                    .covered
+                    .get(file)
+                    .map_or(0, |categories| categories.values().sum()),
+            })
+            .collect(),
+        run,
+    };
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    serde_json::to_writer_pretty(&mut out, &overlap)?;
+    std::io::Write::write_all(&mut out, b"\n")?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Telling which source language a module was compiled from, for
+//! `--by-language`, since how to remove its duplication depends on the
+//! toolchain.
+//!
+//! The `producers` section says so directly when it's there, but it's often
+//! stripped, so the imports and exports that each language's runtime and
+//! bindings need are the fallback, and then the paths of the Rust standard
+//! library that panic messages embed.
+
+use crate::producers;
+use anyhow::Result;
+use wasmparser::{ImportSectionEntryType, Payload, Type, TypeDef};
+
+pub const RUST: &str = "Rust";
+pub const C: &str = "C/C++";
+pub const GO: &str = "Go";
+pub const ASSEMBLYSCRIPT: &str = "AssemblyScript";
+pub const UNKNOWN: &str = "unknown";
+
+/// The language that `wasm` was compiled from, or `UNKNOWN`.
+pub fn language(wasm: &[u8]) -> &'static str {
+    match producers::producers(wasm) {
+        Ok(producers) => {
+            if let Some(language) = from_producers(&producers) {
+                return language;
+            }
+        }
+        Err(e) => log::debug!("Failed to read the producers section: {:#}", e),
+    }
+    match from_interface(wasm) {
+        Ok(Some(language)) => return language,
+        Ok(None) => {}
+        Err(e) => log::debug!("Failed to read the imports and exports: {:#}", e),
+    }
+    if has_rust_paths(wasm) {
+        return RUST;
+    }
+    UNKNOWN
+}
+
+/// The language named by the `language` field of the `producers` section, or
+/// else implied by its tools and SDKs.
+fn from_producers(producers: &[(String, String)]) -> Option<&'static str> {
+    let language = |field: Option<&str>| {
+        producers.iter().find_map(|(f, name)| {
+            if field.map_or(false, |field| field != f) {
+                return None;
+            }
+            let name = name.to_lowercase();
+            Some(match name.as_str() {
+                "rust" | "rustc" | "wasm-bindgen" => RUST,
+                "c" | "c89" | "c99" | "c11" | "c17" | "c++" | "c_plus_plus" | "clang"
+                | "emscripten" => C,
+                "go" | "tinygo" => GO,
+                "assemblyscript" => ASSEMBLYSCRIPT,
+                _ if name.starts_with("c++") || name.starts_with("objective-c") => C,
+                _ => return None,
+            })
+        })
+    };
+    // Rust modules are also processed by `clang` when they link C code in, so
+    // the `language` field wins over the tools.
+    language(Some("language")).or_else(|| language(None))
+}
+
+/// The language whose runtime or bindings the imports and exports of `wasm`
+/// belong to.
+fn from_interface(wasm: &[u8]) -> Result<Option<&'static str>> {
+    let mut types = vec![];
+    let mut c_exports = false;
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::TypeSection(mut reader) => {
+                for _ in 0..reader.get_count() {
+                    types.push(match reader.read()? {
+                        TypeDef::Func(ty) => Some(ty),
+                        _ => None,
+                    });
+                }
+            }
+            Payload::ImportSection(mut reader) => {
+                for _ in 0..reader.get_count() {
+                    let import = reader.read()?;
+                    let field = import.field.unwrap_or("");
+                    if import.module == "go"
+                        || import.module == "gojs"
+                        || field.starts_with("syscall/js.")
+                        || field.starts_with("runtime.")
+                    {
+                        return Ok(Some(GO));
+                    }
+                    if import.module == "wbg"
+                        || import.module == "__wbindgen_placeholder__"
+                        || field.starts_with("__wbindgen_")
+                        || field.starts_with("__wbg_")
+                    {
+                        return Ok(Some(RUST));
+                    }
+                    if field.starts_with("emscripten_")
+                        || field.starts_with("_emscripten_")
+                        || field.starts_with("invoke_")
+                        || field.starts_with("__cxa_")
+                    {
+                        return Ok(Some(C));
+                    }
+                    // AssemblyScript's `abort(message, fileName, line, column)`.
+                    if let ImportSectionEntryType::Function(index) = import.ty {
+                        let abort = types
+                            .get(index as usize)
+                            .and_then(Option::as_ref)
+                            .map_or(false, |ty| {
+                                ty.params.iter().all(|p| *p == Type::I32) && ty.params.len() == 4
+                            });
+                        if import.module == "env" && field == "abort" && abort {
+                            return Ok(Some(ASSEMBLYSCRIPT));
+                        }
+                    }
+                }
+            }
+            Payload::ExportSection(mut reader) => {
+                for _ in 0..reader.get_count() {
+                    let field = reader.read()?.field;
+                    match field {
+                        "__new" | "__pin" | "__unpin" | "__collect" | "__rtti_base" => {
+                            return Ok(Some(ASSEMBLYSCRIPT))
+                        }
+                        _ if field.starts_with("__wbindgen_") => return Ok(Some(RUST)),
+                        "stackSave" | "stackRestore" | "stackAlloc" | "__wasm_call_ctors" => {
+                            c_exports = true
+                        }
+                        _ if field.starts_with("emscripten_") => c_exports = true,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // Rust modules linked by `wasm-ld` can export these too.
+    if c_exports && !has_rust_paths(wasm) {
+        return Ok(Some(C));
+    }
+    Ok(None)
+}
+
+/// Whether `wasm` embeds the paths of the Rust standard library's sources,
+/// which its panic messages point to.
+fn has_rust_paths(wasm: &[u8]) -> bool {
+    contains(wasm, b"/rustc/") || contains(wasm, b"library/core/src/")
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
@@ -0,0 +1,5559 @@
+//! The measurements behind `measure-wasm-dedupe-wins`, which is a thin binary
+//! around `main`.
+//!
+//! The items it deduplicates are found by the extractors of the [`extract`]
+//! module, which library users can add their own to, to experiment with other
+//! dedupe keys without forking the tool.
+
+use anyhow::{ensure, Context, Result};
+use rayon::prelude::*;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+use structopt::StructOpt;
+
+mod against;
+mod attribution;
+mod baseline;
+mod budget;
+mod cache;
+mod cdc;
+mod clusters;
+mod component;
+mod compress;
+mod config;
+mod dedupe;
+mod embedded;
+pub mod extract;
+mod features;
+mod filter;
+mod fuzzy;
+mod gc;
+mod index;
+mod json;
+mod language;
+mod memory_init;
+mod merge;
+mod metrics;
+mod names;
+mod ngrams;
+mod normalize;
+mod outline;
+mod overlap;
+mod plan;
+mod preprocess;
+mod producers;
+mod reloc;
+mod remote;
+mod run;
+mod sample;
+mod sections;
+mod serve;
+mod shard;
+mod shared;
+mod source_map;
+mod split;
+mod sqlite;
+mod stats;
+mod strings;
+mod strip;
+mod timings;
+mod treemap;
+mod weights;
+
+/// Measure the available wins from deduplicating various parts of WebAssembly
+/// binaries.
+///
+/// ### Example
+///
+/// $ measure-wasm-dedupe-wins path/to/corpus/of/Wasm/binaries
+#[derive(StructOpt)]
+struct Options {
+    /// A directory containing the Wasm binaries we should measure.
+    ///
+    /// This directory tree is recursively traversed to find Wasm binaries.
+    /// Required unless a subcommand or `--manifest` is given.
+    #[structopt(parse(from_os_str))]
+    corpus: Option<PathBuf>,
+
+    /// Download and measure the Wasm files at the HTTP(S) URLs listed in this
+    /// file, one per line, instead of a corpus directory.
+    #[structopt(long, parse(from_os_str))]
+    manifest: Option<PathBuf>,
+
+    /// The directory to download the files of `--manifest` into. Files that
+    /// are already there aren't downloaded again.
+    #[structopt(long, parse(from_os_str), default_value = "wasm-downloads")]
+    download_dir: PathBuf,
+
+    /// The number of files of `--manifest` to download at a time.
+    #[structopt(long, default_value = "8")]
+    download_jobs: usize,
+
+    /// List the files containing each duplicated item that is at least
+    /// `--who-min-size` bytes large.
+    #[structopt(long)]
+    who: bool,
+
+    /// The minimum size, in bytes, of the duplicated items listed by `--who`.
+    #[structopt(long, default_value = "1024")]
+    who_min_size: u64,
+
+    /// Attribute the duplicated code bodies listed by `--who` to the first
+    /// name that the `name` section gives a function with that body,
+    /// demangling Rust and C++ symbols.
+    #[structopt(long)]
+    function_names: bool,
+
+    /// Also report the duplication of canonical ABI glue: every function of
+    /// the adapter modules that `wit-component` adds to components (the ones
+    /// importing or exporting its `$imports` table, and the WASI preview 1
+    /// adapter), and the functions of other modules that are exported or
+    /// named like bindings glue, such as `cabi_realloc`, `cabi_post_*`, and
+    /// `<interface>#<function>`.
+    #[structopt(long)]
+    canonical_abi_glue: bool,
+
+    /// Also report how many bytes of the code bodies named after the
+    /// functions of each crate or namespace, like `core` or `std`, are
+    /// duplicated. Implies `--function-names`.
+    #[structopt(long)]
+    by_crate: bool,
+
+    /// Also report how many bytes of code bodies belong to functions whose
+    /// demangled names differ only in their generic arguments, which are
+    /// likely monomorphizations of the same generic function. Implies
+    /// `--function-names`.
+    #[structopt(long)]
+    monomorphizations: bool,
+
+    /// The number of generic functions that `--monomorphizations` lists.
+    #[structopt(long, default_value = "20")]
+    monomorphizations_top: usize,
+
+    /// Also hash only the payload bytes of data segments, ignoring their kind,
+    /// memory index, and offset expression, and report how much more
+    /// duplication that exposes.
+    #[structopt(long)]
+    offset_independent_data: bool,
+
+    /// Also hash element segments with their function indices renumbered in
+    /// order of first use, so that segments referencing the same functions at
+    /// different indices in different modules match, and report how much more
+    /// duplication that exposes.
+    #[structopt(long)]
+    normalize_elem: bool,
+
+    /// Also hash code bodies with their function, global, and type index
+    /// immediates replaced by placeholders, so that the same function compiled
+    /// into different modules matches, and report how much more duplication
+    /// that exposes.
+    #[structopt(long)]
+    structural_code: bool,
+
+    /// Also hash code bodies with their local declarations sorted by type and
+    /// merged into one run per type (renumbering local indices to match), and
+    /// report how much more duplication that exposes.
+    #[structopt(long)]
+    sort_locals: bool,
+
+    /// Also hash data segments, element segments, and code bodies by their
+    /// decoded contents, so that over-long LEB128 encodings emitted by some
+    /// producers don't prevent matches, and report how much more duplication
+    /// that exposes.
+    #[structopt(long)]
+    canonical_lebs: bool,
+
+    /// Also hash code bodies with their constant immediates (the literals of
+    /// `i32.const`, `i64.const`, `f32.const`, `f64.const`, and `v128.const`)
+    /// masked out, and report how much more duplication that exposes: the
+    /// functions that could be merged by passing the constants as
+    /// parameters.
+    #[structopt(long)]
+    mask_constants: bool,
+
+    /// Also measure relocatable Wasm object files (`.o` files, and any file
+    /// with a `linking` section): hash their code bodies and data segments
+    /// with the immediates their `reloc.*` sections relocate zeroed, and list
+    /// the most duplicated function symbols.
+    #[structopt(long)]
+    relocatable: bool,
+
+    /// The number of duplicated function symbols to list with
+    /// `--relocatable`.
+    #[structopt(long, default_value = "20")]
+    relocatable_top: usize,
+
+    /// Also find clusters of near-duplicate data segments, element segments,
+    /// and code bodies, and estimate how many bytes their members share.
+    #[structopt(long)]
+    fuzzy: bool,
+
+    /// The minimum estimated similarity, between 0 and 1, for `--fuzzy` to
+    /// consider two items near-duplicates.
+    #[structopt(long, default_value = "0.8")]
+    fuzzy_threshold: f64,
+
+    /// The minimum size, in bytes, of the items that `--fuzzy` considers.
+    #[structopt(long, default_value = "64")]
+    fuzzy_min_size: u64,
+
+    /// Also split every file into content-defined chunks, ignoring Wasm
+    /// structure, and report chunk-level duplication. This models what a
+    /// content-addressed delivery system would achieve.
+    #[structopt(long)]
+    cdc: bool,
+
+    /// The target average chunk size, in bytes, for `--cdc`. Must be a power
+    /// of two of at least 64.
+    #[structopt(long, default_value = "8192")]
+    cdc_avg_size: usize,
+
+    /// Also split the payload of each data segment that isn't an exact
+    /// duplicate into small content-defined chunks, and report how much
+    /// duplication that finds on top of exact segment matching.
+    #[structopt(long)]
+    sub_segments: bool,
+
+    /// The target average chunk size, in bytes, for `--sub-segments`. Must be
+    /// a power of two of at least 64.
+    #[structopt(long, default_value = "256")]
+    sub_segment_avg_size: usize,
+
+    /// Also find the UTF-8 strings in the payload of each data segment that
+    /// isn't an exact duplicate, and report how much duplication that finds on
+    /// top of exact segment matching.
+    #[structopt(long)]
+    strings: bool,
+
+    /// The minimum size, in bytes, of the strings found by `--strings`.
+    #[structopt(long, default_value = "8")]
+    strings_min_size: usize,
+
+    /// Also estimate how many bytes overlapping the data segment payloads and
+    /// code bodies that share a prefix or suffix, like tail merging a string
+    /// table, would save on top of exact duplication.
+    #[structopt(long)]
+    overlap: bool,
+
+    /// The minimum size, in bytes, of the prefixes and suffixes shared by
+    /// `--overlap`.
+    #[structopt(long, default_value = "16")]
+    overlap_min_size: usize,
+
+    /// Also estimate how many bytes moving the duplicated active data segments
+    /// into a shared module as passive segments, which each module copies into
+    /// its memory with `memory.init` through an imported function, would save
+    /// once the added imports, calls, and functions are paid for.
+    #[structopt(long)]
+    memory_init: bool,
+
+    /// Also model splitting every file into separately fetchable code, data,
+    /// debug info, and other resources, and report how many of their bytes a
+    /// shared HTTP cache could serve.
+    #[structopt(long)]
+    http_split: bool,
+
+    /// Also count the import module names, import field names, and export
+    /// names of every file, and report how many of their bytes a string table
+    /// shared across modules would save.
+    #[structopt(long)]
+    names: bool,
+
+    /// Also estimate how many bytes outlining instruction sequences that are
+    /// repeated across code bodies into shared helper functions would save.
+    #[structopt(long)]
+    outline: bool,
+
+    /// Also tally the most common sequences of this many consecutive
+    /// instructions, by opcode, across all code bodies.
+    #[structopt(long)]
+    ngrams: Option<usize>,
+
+    /// The number of sequences that `--ngrams` lists.
+    #[structopt(long, default_value = "20")]
+    ngrams_top: usize,
+
+    /// Also report the corpus size and the duplicated bytes after compressing
+    /// with gzip, brotli, and zstd, to show duplication in terms of transfer
+    /// size.
+    #[structopt(long)]
+    compressed: bool,
+
+    /// Also train a zstd dictionary on the corpus, and report how much smaller
+    /// the corpus compresses with that shared dictionary than without it.
+    #[structopt(long)]
+    zstd_dict: bool,
+
+    /// The maximum size, in bytes, of the dictionary trained by `--zstd-dict`.
+    #[structopt(long, default_value = "112640")]
+    zstd_dict_size: usize,
+
+    /// Also pair up modules that share most of their items, and report how
+    /// much smaller shipping one module of each pair in full plus a delta for
+    /// the other would be than shipping both in full.
+    #[structopt(long)]
+    delta: bool,
+
+    /// The minimum fraction, between 0 and 1, of a module's bytes that must be
+    /// shared with another module for `--delta` to encode it as a delta
+    /// against that module.
+    #[structopt(long, default_value = "0.5")]
+    delta_min_shared: f64,
+
+    /// Leave items that are in more than this many files out of the bytes
+    /// that pairs of modules share, for `--delta` and the similarity matrix.
+    /// Every pair of their files would share them, so counting them takes
+    /// time and memory quadratic in the number of files.
+    #[structopt(long, default_value = "1000")]
+    max_pair_files: usize,
+
+    /// Write, for every pair of modules with items in common, the number of
+    /// deduplicated bytes they share to this CSV file.
+    #[structopt(long, parse(from_os_str))]
+    similarity_matrix: Option<PathBuf>,
+
+    /// Only write the pairs sharing the most bytes to `--similarity-matrix`,
+    /// at most this many of them.
+    #[structopt(long)]
+    similarity_top: Option<usize>,
+
+    /// Report how many bytes of each module are copies of items of this base
+    /// module, or of the modules in this directory, such as a shared runtime
+    /// that every module could link against instead.
+    #[structopt(long, parse(from_os_str))]
+    against: Option<PathBuf>,
+
+    /// Write a Graphviz graph of the modules to this file, with an edge
+    /// between every two modules sharing at least `--dot-min-shared`
+    /// deduplicated bytes, weighted by those bytes.
+    #[structopt(long, parse(from_os_str))]
+    dot: Option<PathBuf>,
+
+    /// The minimum number of bytes that two modules must share for `--dot` to
+    /// draw an edge between them.
+    #[structopt(long, default_value = "65536")]
+    dot_min_shared: u64,
+
+    /// Also report, for every module, the other module it shares the most
+    /// deduplicated bytes with, and how much of the module that is.
+    #[structopt(long)]
+    most_similar: bool,
+
+    /// Also report, for every module, how many of its code bodies are
+    /// byte-identical to another body of the same module, and their bytes,
+    /// which intra-module merging like binaryen's
+    /// `--merge-similar-functions` can already remove.
+    #[structopt(long)]
+    within_module_code: bool,
+
+    /// Also print, for every file, the size of each of its sections and their
+    /// share of the file, next to how many of the file's bytes are
+    /// duplicated.
+    #[structopt(long)]
+    section_sizes: bool,
+
+    /// Also report how much deduplicating only the 1, 10, 100, ... most
+    /// redundant items would save.
+    #[structopt(long)]
+    win_curve: bool,
+
+    /// Also report how the duplicated items of each category are distributed
+    /// by size.
+    #[structopt(long)]
+    histogram: bool,
+
+    /// Leave duplicated items smaller than this many bytes, which aren't worth
+    /// the indirection of deduplicating them, out of the reported wins, and
+    /// report their redundant bytes separately.
+    #[structopt(long, default_value = "0")]
+    min_size: u64,
+
+    /// Also report the duplicated bytes weighted by how often each file is
+    /// downloaded, according to this CSV file of `path,downloads` rows, whose
+    /// paths may contain `*` and `?` wildcards. Files it doesn't list have no
+    /// weight.
+    #[structopt(long, parse(from_os_str))]
+    weights: Option<PathBuf>,
+
+    /// Leave the custom sections with this name, which may contain `*` and
+    /// `?` wildcards (like `.debug_*`), out of the measurements. May be given
+    /// more than once.
+    #[structopt(long, number_of_values = 1)]
+    ignore_custom_section: Vec<String>,
+
+    /// Only measure the custom sections with this name, which may contain `*`
+    /// and `?` wildcards. May be given more than once.
+    #[structopt(long, number_of_values = 1)]
+    only_custom_section: Vec<String>,
+
+    /// Also print a table of custom section names, ranked by how many bytes
+    /// of the sections with each name are duplicated.
+    #[structopt(long)]
+    custom_section_names: bool,
+
+    /// What identifies a custom section: `data` (its data only, so that copies
+    /// under different names are duplicates) or `name+data` (its name and data,
+    /// which are then both counted in its size).
+    #[structopt(long, default_value = "data")]
+    custom_key: CustomKey,
+
+    /// Also break the duplicated data and element segments down by kind:
+    /// active, passive, and declared.
+    #[structopt(long)]
+    by_kind: bool,
+
+    /// Also print a table of how many bytes of the element segments that
+    /// initialize each table are duplicated, with passive and declared
+    /// segments in their own rows.
+    #[structopt(long)]
+    elem_by_table: bool,
+
+    /// Also print a table of how many bytes of the data segments that
+    /// initialize each memory are duplicated, with passive segments in their
+    /// own row, for modules using the multi-memory proposal.
+    #[structopt(long)]
+    data_by_memory: bool,
+
+    /// Also measure the Wasm modules embedded in custom sections, as some
+    /// bundlers and test harnesses do, and report their duplication
+    /// separately.
+    #[structopt(long)]
+    embedded_modules: bool,
+
+    /// Strip the `name` and DWARF (`.debug_*`) custom sections from every file
+    /// before measuring it, to measure the bytes that actually ship.
+    #[structopt(long)]
+    strip_debug: bool,
+
+    /// Run every file through this command, such as `wasm-opt -Oz`, before
+    /// measuring it. `{input}` and `{output}` in the command stand for the
+    /// paths of the module to read and the one to write, and `{input} -o
+    /// {output}` is appended when it contains neither.
+    #[structopt(long)]
+    preprocess: Option<String>,
+
+    /// Validate every file against these Wasm proposals, a comma-separated
+    /// list such as `simd,threads`, `all`, or `mvp`, and skip the files that
+    /// fail with a warning. Without it files aren't validated, only parsed,
+    /// so that those using any proposal the parser knows are measured.
+    #[structopt(long)]
+    features: Option<String>,
+
+    /// Also report how much of the duplication is within and how much across
+    /// the directories this many levels below the corpus directory, such as
+    /// one directory per application with `--group-by-dir 1`.
+    #[structopt(long)]
+    group_by_dir: Option<usize>,
+
+    /// With `--group-by-dir`, also estimate the win from splitting each
+    /// directory's modules into an app-specific half and a half shared with
+    /// other directories.
+    #[structopt(long)]
+    split_suggestions: bool,
+
+    /// Also report how much of the duplication is within and how much across
+    /// the toolchains (Rust, Emscripten, TinyGo, ...) that produced the files,
+    /// according to their `producers` sections.
+    #[structopt(long)]
+    by_toolchain: bool,
+
+    /// Also report how much of the duplication is within and how much across
+    /// the source languages (Rust, C/C++, Go, AssemblyScript) of the files,
+    /// according to their `producers` sections or else their imports and
+    /// exports.
+    #[structopt(long)]
+    by_language: bool,
+
+    /// Read default values for these options from this TOML file, whose keys
+    /// are the options' long names. Options given on the command line take
+    /// precedence.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// When the run started, and its arguments, for its provenance.
+    #[structopt(skip)]
+    started: run::Started,
+
+    /// Write a candidate shared module containing the most duplicated code
+    /// bodies, data segments, and element segments to this WAT file, annotated
+    /// with how many modules would import each of them.
+    #[structopt(long, parse(from_os_str))]
+    shared_module: Option<PathBuf>,
+
+    /// The number of items of each kind to put in `--shared-module`.
+    #[structopt(long, default_value = "100")]
+    shared_module_items: usize,
+
+    /// Propose up to this many shared modules to move the most duplicated code
+    /// bodies, data segments, and element segments into, grouping the items
+    /// used by the same modules together, and report their contents and
+    /// savings.
+    #[structopt(long)]
+    shared_modules: Option<usize>,
+
+    /// The most bytes of items to put in each of `--shared-modules`.
+    #[structopt(long, default_value = "1048576")]
+    shared_module_budget: u64,
+
+    /// The number of items to list for each of `--shared-modules`.
+    #[structopt(long, default_value = "10")]
+    shared_modules_top: usize,
+
+    /// Write a snapshot of the total size and duplication to this JSON file,
+    /// for use with `--check-baseline`.
+    #[structopt(long, parse(from_os_str))]
+    write_baseline: Option<PathBuf>,
+
+    /// Compare the duplication with a snapshot written by `--write-baseline`,
+    /// and fail if the duplicated percentage of the corpus grew by more than
+    /// `--max-regression`.
+    #[structopt(long, parse(from_os_str))]
+    check_baseline: Option<PathBuf>,
+
+    /// The largest increase, in percentage points (like `1%`), of the
+    /// duplicated percentage of the corpus that `--check-baseline` allows.
+    #[structopt(long, default_value = "0%", parse(try_from_str = baseline::parse_percent))]
+    max_regression: f64,
+
+    /// Fail if more than this percentage (like `15%`) of the corpus is
+    /// duplicated.
+    #[structopt(long, parse(try_from_str = baseline::parse_percent))]
+    fail_if_duplication_above: Option<f64>,
+
+    /// Fail if more than a percentage of a strict category's bytes are
+    /// duplicated, like `code-bodies=20%`. May be given multiple times.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = baseline::parse_category_limit))]
+    fail_if_category_duplication_above: Vec<(Category, f64)>,
+
+    /// Cache the items of every file in this directory, keyed by the file's
+    /// contents, so that later runs only parse new or changed files.
+    ///
+    /// Ignored by `--fuzzy`, `--outline`, `--overlap`, `--memory-init`,
+    /// `--ngrams`, `--compressed`, `--zstd-dict`, and `--verify`, which need
+    /// the contents of every item, and by `--custom-section-names`,
+    /// `--elem-by-table`, `--data-by-memory`, `--function-names`,
+    /// `--by-crate`, `--monomorphizations`, `--relocatable`,
+    /// `--canonical-abi-glue`, `--embedded-modules`, `--by-kind`,
+    /// `--treemap`, `--folded`, and `--emit-plan`.
+    #[structopt(long, parse(from_os_str))]
+    cache: Option<PathBuf>,
+
+    /// Write the files, items, which files contain each item, and the summary
+    /// metrics to tables of this SQLite database.
+    #[structopt(long, parse(from_os_str))]
+    output_db: Option<PathBuf>,
+
+    /// Write a JSON object with the path, size, bytes duplicated so far by
+    /// category, and parse time of each file to this file (or `-` for stdout)
+    /// as the corpus is scanned, one per line.
+    #[structopt(long, parse(from_os_str))]
+    jsonl: Option<PathBuf>,
+
+    /// Write the total size, file count, and duplicated bytes per category to
+    /// this file in the Prometheus text format.
+    #[structopt(long, parse(from_os_str))]
+    metrics_out: Option<PathBuf>,
+
+    /// Write every item's hash, size, number of copies, and number of files
+    /// containing it, by category, to this binary file, to join with the
+    /// indexes of other corpora offline.
+    #[structopt(long, parse(from_os_str))]
+    export_index: Option<PathBuf>,
+
+    /// Add the items of an index written by `--export-index` to the
+    /// measurements, as if its files were measured again, and write the
+    /// accumulated index back to the same file.
+    #[structopt(long, parse(from_os_str))]
+    append_to: Option<PathBuf>,
+
+    /// Measure only the files in this shard of the corpus or `--manifest`, like
+    /// `3/8`, to export its index and combine it with the other shards' with
+    /// `merge-index`.
+    #[structopt(long)]
+    shard: Option<shard::Shard>,
+
+    /// Measure only a random sample of this fraction of the files, like `10%`,
+    /// and estimate the totals of the whole corpus from it.
+    #[structopt(long)]
+    sample: Option<sample::Fraction>,
+
+    /// The seed that picks the files of `--sample`, to pick the same ones
+    /// again.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// Write a JSON plan for other tools to deduplicate the corpus with to this
+    /// file: every duplicated item's category, hash, and size, and the file
+    /// and offset of each of its copies.
+    #[structopt(long, parse(from_os_str))]
+    emit_plan: Option<PathBuf>,
+
+    /// Write an SVG treemap of the duplicated bytes to this file, by category,
+    /// then by file or custom section name, then by item.
+    #[structopt(long, parse(from_os_str))]
+    treemap: Option<PathBuf>,
+
+    /// Write the duplicated bytes to this file as folded stacks of
+    /// `category;file;item` for flamegraph tools.
+    #[structopt(long, parse(from_os_str))]
+    folded: Option<PathBuf>,
+
+    /// The length, in bytes, of the hash that identifies each item, between 8
+    /// and 64. Every item's hash is kept in memory, so longer hashes use more
+    /// memory on large corpora, for an already negligible collision risk.
+    #[structopt(long, default_value = "32")]
+    hash_bytes: usize,
+
+    /// The algorithm to hash items with: `blake3`, `xxh3` (much faster, but
+    /// at most 16 bytes long), or `sha256` (at most 32 bytes long, for
+    /// comparing hashes with other content-addressed stores).
+    #[structopt(long, default_value = "blake3")]
+    hasher: HashAlgorithm,
+
+    /// Keep the bytes of every item, and check that each duplicate is
+    /// byte-for-byte identical to the first item with the same hash rather
+    /// than a hash collision. Uses as much memory as the corpus is large.
+    #[structopt(long)]
+    verify: bool,
+
+    /// Bound the memory use on corpora with huge numbers of distinct items by
+    /// scanning the corpus twice: first to fill an approximate counting filter
+    /// of `--filter-size` bytes, and then to only keep track of the items that
+    /// the filter says may be duplicated. The results are the same.
+    #[structopt(long)]
+    low_memory: bool,
+
+    /// Print the sizes in the summary in KiB, MiB, and GiB rather than bytes.
+    #[structopt(long)]
+    human: bool,
+
+    /// How to print the summary: `human`, or `json=v1` for version 1 of the
+    /// JSON schema, which only ever gains fields.
+    #[structopt(long, default_value = "human")]
+    format: OutputFormat,
+
+    /// Whether to color the duplicated percentages in the summary: `auto`
+    /// (when stdout is a terminal and `NO_COLOR` isn't set), `always`, or
+    /// `never`.
+    #[structopt(long, default_value = "auto")]
+    color: ColorChoice,
+
+    /// Color duplicated percentages of the corpus yellow from this percentage
+    /// (like `10%`) on.
+    #[structopt(long, default_value = "10%", parse(try_from_str = baseline::parse_percent))]
+    color_yellow: f64,
+
+    /// Color duplicated percentages of the corpus red from this percentage on,
+    /// and green below `--color-yellow`.
+    #[structopt(long, default_value = "25%", parse(try_from_str = baseline::parse_percent))]
+    color_red: f64,
+
+    /// Print only a single `key=value` summary line instead of the report, or
+    /// nothing at all with `--check-baseline`, whose result is then only in
+    /// the exit status.
+    #[structopt(long)]
+    quiet: bool,
+
+    /// The size, in bytes, of the `--low-memory` filter. Larger filters let
+    /// fewer unique items through to the second pass.
+    #[structopt(long, default_value = "268435456")]
+    filter_size: usize,
+
+    /// Keep the estimated memory of the item maps under this size (like
+    /// `8GiB`) by dropping the details kept about every item, such as which
+    /// files contain it, once they get close to it. The report says what was
+    /// dropped.
+    #[structopt(long, parse(try_from_str = budget::parse_size))]
+    max_memory: Option<u64>,
+
+    /// Skip files larger than this (like `512MiB`), with a warning, rather
+    /// than letting a few huge modules dominate the run time and memory. The
+    /// report says how many files and bytes were skipped.
+    #[structopt(long, parse(try_from_str = budget::parse_size))]
+    max_file_size: Option<u64>,
+
+    /// Report the time spent listing directories, reading files, parsing, and
+    /// hashing, and the files that took the longest to count.
+    #[structopt(long)]
+    timings: bool,
+
+    /// Where the time went, with `--timings`.
+    #[structopt(skip)]
+    phases: Option<Arc<timings::Phases>>,
+
+    /// The number of slowest files to list with `--timings`.
+    #[structopt(long, default_value = "10")]
+    timings_top: usize,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Extract duplicated items into a content-addressed store of blobs, and
+    /// rewrite each module to reference them through a manifest.
+    ///
+    /// This is an experimental prototype for validating the measured wins end
+    /// to end.
+    Dedupe {
+        /// A directory containing the Wasm binaries to deduplicate.
+        #[structopt(parse(from_os_str))]
+        corpus: PathBuf,
+
+        /// The directory to write the blobs and rewritten modules to.
+        #[structopt(parse(from_os_str))]
+        out_dir: PathBuf,
+
+        /// The minimum size, in bytes, of the duplicated items to extract.
+        #[structopt(long, default_value = "64")]
+        min_size: u64,
+    },
+
+    /// Merge the byte-identical function bodies within each module, validate
+    /// the merged modules against `--features` (default `all`), and print the
+    /// size they actually saved next to the predicted savings.
+    MergeFunctions {
+        /// A directory containing the Wasm binaries to merge.
+        #[structopt(parse(from_os_str))]
+        corpus: PathBuf,
+
+        /// The directory to write the merged modules to.
+        #[structopt(long, parse(from_os_str))]
+        out_dir: Option<PathBuf>,
+    },
+
+    /// Measure two corpora and print how many bytes of the candidate's items
+    /// are already in the base, such as a new application's modules that
+    /// devices with the base applications installed already have.
+    Overlap {
+        /// The directory containing the base corpus, or a single base module.
+        #[structopt(long, parse(from_os_str))]
+        base: PathBuf,
+
+        /// The directory containing the candidate corpus.
+        #[structopt(long, parse(from_os_str))]
+        candidate: PathBuf,
+    },
+
+    /// Measure two corpora and print how the total size and duplication
+    /// changed from the old one to the new one, along with the large
+    /// duplicates (of at least `--who-min-size` bytes) that are new.
+    Diff {
+        /// The directory containing the old corpus.
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        /// The directory containing the new corpus.
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+
+    /// Measure a corpus and serve a browsable report of it over HTTP, with a
+    /// page for every file and every item.
+    Serve {
+        /// A directory containing the Wasm binaries to measure.
+        #[structopt(parse(from_os_str))]
+        corpus: PathBuf,
+
+        /// The local port to serve the report on.
+        #[structopt(long, default_value = "8080")]
+        port: u16,
+    },
+
+    /// Merge indexes written by `--export-index`, such as those of separate
+    /// shards of a corpus, and print the report of the combined corpus.
+    MergeIndex {
+        /// The indexes to merge, all written with the same `--hasher` and
+        /// `--hash-bytes`.
+        #[structopt(parse(from_os_str), required = true)]
+        indexes: Vec<PathBuf>,
+
+        /// Write the merged index to this file.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Print statistics about a corpus other than its duplication.
+    Stats {
+        #[structopt(subcommand)]
+        stats: Stats,
+    },
+}
+
+#[derive(StructOpt)]
+enum Stats {
+    /// Tally how often each opcode occurs in the code of a corpus, and how
+    /// many bytes its instructions take up, largest first.
+    Opcodes {
+        /// A directory containing the Wasm binaries to tally.
+        #[structopt(parse(from_os_str))]
+        corpus: PathBuf,
+
+        /// The number of opcodes to list.
+        #[structopt(long, default_value = "50")]
+        top: usize,
+    },
+}
+
+/// Run the tool with the process's command-line arguments.
+pub fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = config::args()?;
+    let started = run::Started::new(&args);
+    let mut options = Options::from_iter(args);
+    options.started = started;
+    if let Some(path) = &options.config {
+        log::info!("Read default options from {}", path.display());
+    }
+    if options.timings {
+        options.phases = Some(Arc::new(timings::Phases::new()));
+    }
+    ensure!(
+        !options.color.enabled() || options.color_yellow <= options.color_red,
+        "`--color-yellow` must be at most `--color-red`"
+    );
+    ensure!(options.ngrams != Some(0), "`--ngrams` must be at least 1");
+    ensure!(
+        options.shared_modules != Some(0),
+        "`--shared-modules` must be at least 1"
+    );
+    ensure!(
+        (8..=64).contains(&options.hash_bytes),
+        "`--hash-bytes` must be between 8 and 64"
+    );
+    ensure!(
+        options.cdc_avg_size.is_power_of_two() && options.cdc_avg_size >= 64,
+        "`--cdc-avg-size` must be a power of two of at least 64"
+    );
+    ensure!(
+        options.sub_segment_avg_size.is_power_of_two() && options.sub_segment_avg_size >= 64,
+        "`--sub-segment-avg-size` must be a power of two of at least 64"
+    );
+    // The offsets in the plan would be within the transformed files, which
+    // aren't written anywhere.
+    ensure!(
+        options.emit_plan.is_none() || !options.strip_debug && options.preprocess.is_none(),
+        "`--emit-plan` can't be combined with `--strip-debug` or `--preprocess`"
+    );
+    // The filter drops unique items, which an index has to keep for the copies
+    // in later batches and other shards to be counted as duplicates.
+    ensure!(
+        !options.low_memory || options.export_index.is_none() && options.append_to.is_none(),
+        "`--low-memory` can't be combined with `--export-index` or `--append-to`"
+    );
+    match &options.command {
+        None => {
+            let counts = match &options.manifest {
+                Some(manifest) => {
+                    let selection = Selection::new(&options);
+                    let files = remote::download(
+                        manifest,
+                        &options.download_dir,
+                        options.download_jobs,
+                        &selection,
+                    )?;
+                    measure_with(&options, &selection, |f| {
+                        for_each_listed_file(&files, &selection, f)
+                    })?
+                }
+                None => {
+                    let corpus = options
+                        .corpus
+                        .as_deref()
+                        .context("missing the corpus directory to measure")?;
+                    let selection = Selection::new(&options);
+                    measure_with(&options, &selection, |f| {
+                        for_each_selected_wasm_file(corpus, &selection, f)
+                    })?
+                }
+            };
+            match options.format {
+                OutputFormat::JsonV1 => {
+                    json::print_v1(&counts, run::Run::new(&counts, &options))?;
+                    write_outputs(&counts, &options)?;
+                    write_and_check_baseline(&counts, &options)
+                }
+                OutputFormat::Human if options.quiet => report_quiet(&counts, &options),
+                OutputFormat::Human => report(&counts, &options),
+            }
+        }
+        Some(Command::Dedupe {
+            corpus,
+            out_dir,
+            min_size,
+        }) => {
+            let counts = measure(corpus, &options)?;
+            dedupe::run(&counts, corpus, out_dir, *min_size, &options)
+        }
+        Some(Command::MergeFunctions { corpus, out_dir }) => {
+            let features = features::parse(options.features.as_deref().unwrap_or("all"))?;
+            merge::run(corpus, out_dir.as_deref(), features, &options)
+        }
+        Some(Command::Diff { old, new }) => {
+            let old = measure(old, &options)?;
+            let new = measure(new, &options)?;
+            report_diff(&old, &new, &options);
+            Ok(())
+        }
+        Some(Command::Overlap { base, candidate }) => {
+            let base = measure_base(base, &options)?;
+            let against = against::Against::new(&base);
+            let selection = Selection::files(&options);
+            let counts = measure_against(&options, &selection, Some(against), |f| {
+                for_each_selected_wasm_file(candidate, &selection, f)
+            })?;
+            let against = counts.against.as_ref().unwrap();
+            match options.format {
+                OutputFormat::JsonV1 => {
+                    json::print_overlap_v1(&counts, against, run::Run::new(&counts, &options))
+                }
+                OutputFormat::Human => {
+                    print_overlap(&counts, against, &options);
+                    Ok(())
+                }
+            }
+        }
+        Some(Command::Serve { corpus, port }) => {
+            let counts = measure(corpus, &options)?;
+            serve::run(&counts, *port)
+        }
+        Some(Command::MergeIndex { indexes, output }) => {
+            let mut counts = Counts::new(&options)?;
+            for path in indexes {
+                index::read_into(&mut counts, path)?;
+            }
+            if let Some(path) = output {
+                index::write(&counts, path)?;
+            }
+            match options.format {
+                OutputFormat::JsonV1 => json::print_v1(&counts, run::Run::new(&counts, &options)),
+                OutputFormat::Human if options.quiet => report_quiet(&counts, &options),
+                OutputFormat::Human => report(&counts, &options),
+            }
+        }
+        Some(Command::Stats {
+            stats: Stats::Opcodes { corpus, top },
+        }) => stats::opcodes(corpus, *top, &options),
+    }
+}
+
+/// Measure every Wasm file in the `corpus` directory tree.
+fn measure(corpus: &Path, options: &Options) -> Result<Counts> {
+    let selection = Selection::files(options);
+    measure_with(options, &selection, |f| {
+        for_each_selected_wasm_file(corpus, &selection, f)
+    })
+}
+
+/// Measure every Wasm file that `for_each_file` calls its argument with, from
+/// those in `selection`.
+fn measure_with(
+    options: &Options,
+    selection: &Selection,
+    for_each_file: impl Fn(&mut dyn FnMut(&Path, &[u8]) -> Result<()>) -> Result<()>,
+) -> Result<Counts> {
+    let against = match &options.against {
+        Some(path) => Some(against::Against::new(&measure_base(path, options)?)),
+        None => None,
+    };
+    measure_against(options, selection, against, for_each_file)
+}
+
+/// Measure the base modules at `path`, a directory of them or a single one.
+fn measure_base(path: &Path, options: &Options) -> Result<Counts> {
+    log::info!("Measuring the base modules in {}", path.display());
+    let mut base = Counts::base(options)?;
+    if path.is_dir() {
+        for_each_wasm_file(path, options, |path, wasm| base.add_wasm(path, wasm))?;
+    } else {
+        let selection = Selection::files(options);
+        for_each_listed_file(&[path.to_path_buf()], &selection, |path, wasm| {
+            base.add_wasm(path, wasm)
+        })?;
+    }
+    Ok(base)
+}
+
+/// Like `measure_with`, comparing every file with the base modules of
+/// `against`, if any.
+fn measure_against(
+    options: &Options,
+    selection: &Selection,
+    against: Option<against::Against>,
+    for_each_file: impl Fn(&mut dyn FnMut(&Path, &[u8]) -> Result<()>) -> Result<()>,
+) -> Result<Counts> {
+    let mut counts = Counts::new(options)?;
+    counts.against = against;
+    if let Some(path) = &options.append_to {
+        log::info!("Reading the index {}", path.display());
+        index::read_into(&mut counts, path)?;
+    }
+    if options.embedded_modules {
+        counts.embedded = Some(Box::new(Counts::embedded(options)?));
+    }
+    if options.relocatable {
+        counts.objects = Some(Box::new(Counts::objects(options)));
+    }
+    if options.low_memory {
+        log::info!("Finding the items that may be duplicated");
+        let mut filling = Counts::filling_filter(options)?;
+        selection.quietly(|| for_each_file(&mut |path, wasm| filling.add_wasm(path, wasm)))?;
+        counts.filter = filling.filter.take();
+    }
+
+    let mut jsonl: Option<Box<dyn Write>> = match &options.jsonl {
+        Some(path) if path.as_os_str() == "-" => Some(Box::new(std::io::stdout())),
+        Some(path) => {
+            Some(Box::new(File::create(path).with_context(|| {
+                format!("failed to create {}", path.display())
+            })?))
+        }
+        None => None,
+    };
+    let mut timings = options
+        .phases
+        .clone()
+        .map(|phases| timings::Counting::new(options.timings_top, phases));
+    let mut sampled = options.sample.map(|_| sample::Sums::default());
+    for_each_file(&mut |path, wasm| {
+        let start = Instant::now();
+        let (files, total_size) = (counts.files.len(), counts.total_size);
+        counts.add_wasm(path, wasm)?;
+        if let Some(sampled) = &mut sampled {
+            if counts.files.len() > files {
+                sampled.add(counts.total_size - total_size, counts.file_within_modules);
+            }
+        }
+        counts.check_memory();
+        if let Some(timings) = &mut timings {
+            timings.add(path, start.elapsed());
+        }
+
+        if let Some(out) = &mut jsonl {
+            let record = FileRecord {
+                schema_version: 1,
+                path: path.display().to_string(),
+                size: wasm.len() as u64,
+                duplicated: counts
+                    .file_duplicated
+                    .iter()
+                    .map(|(category, size)| (category.name(), *size))
+                    .collect(),
+                parse_time_ms: start.elapsed().as_secs_f64() * 1000.0,
+            };
+            serde_json::to_writer(&mut *out, &record)?;
+            writeln!(out)?;
+            out.flush()?;
+        }
+        Ok(())
+    })?;
+    counts.timings = timings;
+    counts.sampled = sampled;
+    (counts.oversized_files, counts.oversized_bytes) = selection.oversized();
+    if let Some(path) = options
+        .jsonl
+        .as_ref()
+        .filter(|path| path.as_os_str() != "-")
+    {
+        run::Run::new(&counts, options).write_next_to(path)?;
+    }
+
+    Ok(counts)
+}
+
+/// Call `f` with the path and contents of every file in `files`, skipping
+/// those that are too big for `selection`.
+fn for_each_listed_file(
+    files: &[PathBuf],
+    selection: &Selection,
+    mut f: impl FnMut(&Path, &[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut wasm = vec![];
+    for path in files {
+        if selection.max_file_size.is_some() {
+            let metadata =
+                timings::time(selection.phases.as_deref(), timings::Phase::Walking, || {
+                    std::fs::metadata(path)
+                })
+                .with_context(|| format!("failed to read metadata of {}", path.display()))?;
+            if selection.oversized_file(path, metadata.len()) {
+                continue;
+            }
+        }
+        timings::time(selection.phases.as_deref(), timings::Phase::Reading, || {
+            read_file(path, &mut wasm)
+        })?;
+        f(path, &wasm).with_context(|| format!("failed to count {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The number of files read ahead of the one being counted.
+const READ_AHEAD: usize = 16;
+
+/// Call `f` with the path and contents of every Wasm file in the `corpus`
+/// directory tree, in order of their paths.
+///
+/// Directories are listed in parallel, and files are read on another thread
+/// ahead of `f`, so that slow listings and reads, as on network filesystems
+/// with millions of entries, overlap with counting.
+fn for_each_wasm_file(
+    corpus: &Path,
+    options: &Options,
+    f: impl FnMut(&Path, &[u8]) -> Result<()>,
+) -> Result<()> {
+    for_each_selected_wasm_file(corpus, &Selection::files(options), f)
+}
+
+/// The files of a corpus or `--manifest` to measure, with `--shard` and
+/// `--sample`, by their path within the corpus or their URL, and with
+/// `--relocatable` and `--max-file-size`.
+#[derive(Default)]
+struct Selection {
+    shard: Option<shard::Shard>,
+    sample: Option<sample::Sample>,
+    /// Whether to measure `.o` files too, for `--relocatable`.
+    objects: bool,
+    /// The size of the largest file to measure, for `--max-file-size`.
+    max_file_size: Option<u64>,
+    /// The number and total size of the files skipped for being over
+    /// `max_file_size`, which the reading thread tallies.
+    oversized_files: AtomicU64,
+    oversized_bytes: AtomicU64,
+    /// Whether to skip oversized files without warning about or tallying them,
+    /// in the first pass of `--low-memory`, so that they're only counted once.
+    quiet: AtomicBool,
+    /// Where listing and reading's time is added up, with `--timings`.
+    phases: Option<Arc<timings::Phases>>,
+}
+
+impl Selection {
+    fn new(options: &Options) -> Self {
+        Selection {
+            shard: options.shard,
+            sample: options.sample.map(|fraction| sample::Sample {
+                fraction: fraction.0,
+                seed: options.seed,
+            }),
+            ..Selection::files(options)
+        }
+    }
+
+    /// All the files that `--relocatable` and `--max-file-size` let through,
+    /// ignoring `--shard` and `--sample`.
+    fn files(options: &Options) -> Self {
+        Selection {
+            objects: options.relocatable,
+            max_file_size: options.max_file_size,
+            phases: options.phases.clone(),
+            ..Selection::default()
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.shard.map_or(true, |shard| shard.contains(name))
+            && self.sample.map_or(true, |sample| sample.contains(name))
+    }
+
+    /// Whether the file at `path` in the `corpus` directory is selected, going
+    /// by its path relative to the corpus with `/` separators, so that runs
+    /// with the corpus mounted in different places agree.
+    fn contains_path(&self, corpus: &Path, path: &Path) -> bool {
+        if self.shard.is_none() && self.sample.is_none() {
+            return true;
+        }
+        let relative = path.strip_prefix(corpus).unwrap_or(path);
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        self.contains(&name)
+    }
+
+    /// Whether the file at `path` of `len` bytes is over `max_file_size`, in
+    /// which case it's tallied and should be skipped.
+    fn oversized_file(&self, path: &Path, len: u64) -> bool {
+        if len <= self.max_file_size.unwrap_or(u64::MAX) {
+            return false;
+        }
+        if self.quiet.load(Ordering::Relaxed) {
+            return true;
+        }
+        log::warn!(
+            "Skipping {}, which is over `--max-file-size` at {} bytes",
+            path.display(),
+            len
+        );
+        self.oversized_files.fetch_add(1, Ordering::Relaxed);
+        self.oversized_bytes.fetch_add(len, Ordering::Relaxed);
+        true
+    }
+
+    /// Call `f`, skipping oversized files quietly while it runs.
+    fn quietly<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.quiet.store(true, Ordering::Relaxed);
+        let result = f();
+        self.quiet.store(false, Ordering::Relaxed);
+        result
+    }
+
+    /// The number and total size of the files skipped by `oversized_file` so
+    /// far.
+    fn oversized(&self) -> (u64, u64) {
+        (
+            self.oversized_files.load(Ordering::Relaxed),
+            self.oversized_bytes.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Like `for_each_wasm_file`, but only for the files in `selection`.
+fn for_each_selected_wasm_file(
+    corpus: &Path,
+    selection: &Selection,
+    mut f: impl FnMut(&Path, &[u8]) -> Result<()>,
+) -> Result<()> {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(READ_AHEAD);
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            // The walk gets its own thread pool, since one busy hashing the
+            // items of a huge module would starve it.
+            let walk = jwalk::WalkDir::new(corpus)
+                .follow_links(true)
+                .sort(true)
+                .parallelism(jwalk::Parallelism::RayonNewPool(0));
+            let mut walk = walk.into_iter();
+            while let Some(entry) =
+                timings::time(selection.phases.as_deref(), timings::Phase::Walking, || {
+                    walk.next()
+                })
+            {
+                let file = match read_wasm_entry(corpus, selection, entry) {
+                    Ok(None) => continue,
+                    Ok(Some(file)) => Ok(file),
+                    Err(e) => Err(e),
+                };
+                // Stop once counting fails and nothing receives the files.
+                if sender.send(file).is_err() {
+                    break;
+                }
+            }
+        });
+        while let Some(file) =
+            timings::time(selection.phases.as_deref(), timings::Phase::Waiting, || {
+                receiver.recv().ok()
+            })
+        {
+            let (path, wasm) = file?;
+            f(&path, &wasm).with_context(|| format!("failed to count {}", path.display()))?;
+        }
+        Ok(())
+    })
+}
+
+/// The path and contents of the directory entry `entry`, if it's a Wasm file.
+fn read_wasm_entry(
+    corpus: &Path,
+    selection: &Selection,
+    entry: std::result::Result<jwalk::DirEntry<((), ())>, jwalk::Error>,
+) -> Result<Option<(PathBuf, Vec<u8>)>> {
+    let entry = entry.context("failed to read directory entry")?;
+    let path = entry.path();
+
+    // Only consider `.wasm` paths, and `.o` ones with `--relocatable`.
+    let object = path.extension().map_or(false, |ext| ext == "o");
+    if !(path.extension().map_or(false, |ext| ext == "wasm") || object && selection.objects) {
+        log::debug!("Ignoring non-Wasm entry: {}", path.display());
+        return Ok(None);
+    }
+    if !selection.contains_path(corpus, &path) {
+        log::debug!("Ignoring unselected entry: {}", path.display());
+        return Ok(None);
+    }
+
+    // Only consider files.
+    let metadata = timings::time(selection.phases.as_deref(), timings::Phase::Walking, || {
+        entry.metadata()
+    })
+    .with_context(|| format!("failed to read metadata of {}", path.display()))?;
+    if !metadata.is_file() {
+        log::debug!("Ignoring non-file entry: {}", path.display());
+        return Ok(None);
+    }
+    if selection.oversized_file(&path, metadata.len()) {
+        return Ok(None);
+    }
+
+    log::info!("Considering Wasm file: {}", path.display());
+
+    let mut wasm = vec![];
+    timings::time(selection.phases.as_deref(), timings::Phase::Reading, || {
+        read_file(&path, &mut wasm)
+    })?;
+    if object && !wasm.starts_with(b"\0asm") {
+        log::debug!("Ignoring non-Wasm object file: {}", path.display());
+        return Ok(None);
+    }
+    Ok(Some((path, wasm)))
+}
+
+/// Print the totals of the whole corpus estimated from the `--sample` of
+/// `fraction` of its files that was measured.
+fn print_sample_estimates(
+    counts: &Counts,
+    sampled: &sample::Sums,
+    fraction: f64,
+    seed: u64,
+    options: &Options,
+) {
+    let estimate = |(value, error): (f64, f64)| {
+        format!(
+            "{} (95% CI ± {})",
+            size(value as u64, options),
+            size(error as u64, options).trim_start()
+        )
+    };
+    println!(
+        "{:<width$}{} files, {:.02}% with --seed {}",
+        "Sampled:",
+        sampled.files,
+        fraction * 100.0,
+        seed,
+        width = LABEL_WIDTH
+    );
+    let (files, error) = sampled.files(fraction);
+    println!(
+        "{:<width$}{:.0} (95% CI ± {:.0})",
+        "Estimated files:",
+        files,
+        error,
+        width = LABEL_WIDTH
+    );
+    println!(
+        "{:<width$}{}",
+        "Estimated total size:",
+        estimate(sampled.size(fraction)),
+        width = LABEL_WIDTH
+    );
+    println!(
+        "{:<width$}{}",
+        "Estimated duplicated within modules:",
+        estimate(sampled.within_modules(fraction)),
+        width = LABEL_WIDTH
+    );
+    let across: u64 = Category::ALL
+        .iter()
+        .map(|category| counts.duplicated(*category).across_modules)
+        .sum();
+    println!(
+        "{:<width$}at least {}",
+        "Estimated duplicated across modules:",
+        size((across as f64 / fraction) as u64, options).trim_start(),
+        width = LABEL_WIDTH
+    );
+}
+
+/// A line of `--jsonl` output.
+#[derive(serde::Serialize)]
+struct FileRecord {
+    schema_version: u32,
+    path: String,
+    size: u64,
+    /// The bytes of each category's items in this file that were already seen,
+    /// earlier in this file or in a previous one.
+    duplicated: BTreeMap<&'static str, u64>,
+    parse_time_ms: f64,
+}
+
+/// Print the measurements in `counts`.
+fn report(counts: &Counts, options: &Options) -> Result<()> {
+    println!(
+        "{:<width$}{}",
+        "Total size:",
+        size(counts.total_size, options),
+        width = LABEL_WIDTH
+    );
+    if let Some(nested) = counts.category_totals.get(&Category::NestedModules) {
+        println!(
+            "{:<width$}{} ({:.02}%) in {} modules",
+            "Nested modules:",
+            size(nested.size, options),
+            percent(nested.size, counts.total_size),
+            nested.items,
+            width = LABEL_WIDTH
+        );
+    }
+    if counts.indexed_files > 0 {
+        println!(
+            "{:<width$}{} files, plus {} measured now",
+            "Files from indexes:",
+            counts.indexed_files,
+            counts.files.len(),
+            width = LABEL_WIDTH
+        );
+    }
+    if counts.oversized_files > 0 {
+        println!(
+            "{:<width$}{} in {} files, not in the total size",
+            "Skipped over --max-file-size:",
+            size(counts.oversized_bytes, options),
+            counts.oversized_files,
+            width = LABEL_WIDTH
+        );
+    }
+    if counts.features.is_some() {
+        println!(
+            "{:<width$}{}",
+            "Skipped invalid files:",
+            counts.skipped.len(),
+            width = LABEL_WIDTH
+        );
+    }
+    if let (Some(sampled), Some(fraction)) = (&counts.sampled, options.sample) {
+        print_sample_estimates(counts, sampled, fraction.0, options.seed, options);
+    }
+    for (degradation, files) in &counts.degradations {
+        println!(
+            "Over 90% of --max-memory after {} files: {}.",
+            files,
+            degradation.describe()
+        );
+    }
+
+    println!("--------------------------------------------------------------------------------");
+
+    // Rank the categories by how many bytes they waste.
+    let mut ranked: Vec<_> = Category::ALL
+        .iter()
+        .map(|category| (category, counts.duplicated(*category)))
+        .collect();
+    ranked.sort_by_key(|(_, dupe)| std::cmp::Reverse(dupe.total()));
+    let mut dupe_total = Duplication::default();
+    for (_, dupe) in &ranked {
+        dupe_total += *dupe;
+    }
+
+    for (rank, (category, dupe)) in ranked.into_iter().enumerate() {
+        let line = format!(
+            "{:<width$}{} ({:.02}%, {:.02}% of duplicated data)",
+            format!("#{} Duplicated {}:", rank + 1, category.name()),
+            size(dupe.total(), options),
+            percent(dupe.total(), counts.total_size),
+            percent(dupe.total(), dupe_total.total()),
+            width = LABEL_WIDTH
+        );
+        println!(
+            "{}",
+            paint(&line, percent(dupe.total(), counts.total_size), options)
+        );
+        print_row(
+            "  within modules",
+            dupe.within_modules,
+            counts.total_size,
+            options,
+        );
+        print_row(
+            "  across modules",
+            dupe.across_modules,
+            counts.total_size,
+            options,
+        );
+        let totals = counts
+            .category_totals
+            .get(category)
+            .copied()
+            .unwrap_or_default();
+        println!(
+            "{:<width$}{} ({:.02}% duplicated)",
+            format!("  all {}:", category.name()),
+            size(totals.size, options),
+            percent(dupe.total(), totals.size),
+            width = LABEL_WIDTH
+        );
+        // Categories with no items at all average 0 copies, rather than NaN.
+        let average = if totals.unique == 0 {
+            0.0
+        } else {
+            totals.items as f64 / totals.unique as f64
+        };
+        println!(
+            "{:<width$}{:>9} ({} unique, {:.02} copies each on average)",
+            "  items:",
+            totals.items,
+            totals.unique,
+            average,
+            width = LABEL_WIDTH
+        );
+        for kind in category.kinds() {
+            if !counts.items.contains_key(kind) {
+                continue;
+            }
+            let label = format!("  {}", kind.name());
+            let dupe = counts.duplicated(*kind).total();
+            if *kind == Category::ConstOffsetDataSegments {
+                println!(
+                    "{:<width$}{} ({:.02}%) in {} segments that could be shared passive ones",
+                    format!("{}:", label),
+                    size(dupe, options),
+                    percent(dupe, counts.total_size),
+                    counts.duplicated_items(*kind, 0).len(),
+                    width = LABEL_WIDTH
+                );
+            } else {
+                print_row(&label, dupe, counts.total_size, options);
+            }
+        }
+    }
+
+    println!("--------------------------------------------------------------------------------");
+
+    print_duplication(
+        "Total duplicated data",
+        dupe_total,
+        counts.total_size,
+        options,
+    );
+    if counts.min_size > 0 {
+        let ignored = Category::ALL
+            .iter()
+            .map(|category| counts.duplicated_below_min_size(*category))
+            .sum();
+        print_row(
+            &format!("Ignored, under {} bytes", counts.min_size),
+            ignored,
+            counts.total_size,
+            options,
+        );
+    }
+
+    if counts.weights.is_some() {
+        print_weighted(counts);
+    }
+
+    if options.verify {
+        print_collisions(counts);
+    }
+
+    if options.win_curve {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_win_curve(counts, options);
+    }
+
+    if options.histogram {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_histograms(counts, options);
+    }
+
+    let mut normalized: Vec<_> = Category::NORMALIZED
+        .iter()
+        .filter(|category| counts.items.contains_key(category))
+        .collect();
+    normalized.sort_by_key(|category| std::cmp::Reverse(counts.duplicated(**category).total()));
+    if !normalized.is_empty() {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        for category in normalized {
+            print_normalized(counts, *category, options);
+        }
+    }
+
+    let analyses: Vec<_> = Category::ANALYSES
+        .iter()
+        .filter(|category| counts.items.contains_key(category))
+        .collect();
+    if !analyses.is_empty() {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        for category in analyses {
+            print_duplication(
+                &format!("Duplicated {}", category.name()),
+                counts.duplicated(*category),
+                counts.total_size,
+                options,
+            );
+        }
+    }
+
+    if options.compressed {
+        for (i, codec) in compress::Codec::ALL.iter().enumerate() {
+            println!(
+                "--------------------------------------------------------------------------------"
+            );
+            print_compressed(counts, i, *codec, options);
+        }
+    }
+
+    if let Some(samples) = &counts.dictionary_samples {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_shared_dictionary(counts, samples, options)?;
+    }
+
+    if options.delta {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_deltas(counts, options)?;
+    }
+
+    if options.most_similar {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_most_similar(counts, options);
+    }
+
+    if options.relocatable && counts.items.contains_key(&Category::RelocCodeBodies) {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_duplicated_symbols(counts, options.relocatable_top);
+    }
+
+    if options.by_crate {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_by_crate(counts, options);
+    }
+
+    if options.monomorphizations {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_monomorphizations(counts, options.monomorphizations_top, options);
+    }
+
+    if let Some(k) = options.shared_modules {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_shared_modules(counts, k, options);
+    }
+
+    if let Some(files) = &counts.section_sizes {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_section_sizes(counts, files, options);
+    }
+
+    if let Some(within) = &counts.within_module_code {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_within_module_code(counts, within, options);
+    }
+
+    if let Some(against) = &counts.against {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_against(counts, against, options);
+    }
+
+    write_outputs(counts, options)?;
+
+    if let Some(outliner) = &counts.outliner {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_outlining(counts, outliner, options);
+    }
+
+    if let Some(names) = &counts.custom_section_names {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_group_totals("Custom sections by name:", "name", names, options);
+    }
+
+    if let Some(tables) = &counts.elem_tables {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_group_totals("Element segments by table:", "table", tables, options);
+    }
+
+    if let Some(memories) = &counts.data_memories {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_group_totals("Data segments by memory:", "memory", memories, options);
+    }
+
+    if options.http_split {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_http_split(counts, options);
+    }
+
+    if let Some(embedded) = &counts.embedded {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_embedded(embedded, options);
+    }
+
+    if !counts.overlaps.is_empty() {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_overlaps(counts, options);
+    }
+
+    if let Some(memory_init) = &counts.memory_init {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_memory_init(counts, memory_init, options);
+    }
+
+    if let Some(ngrams) = &counts.ngrams {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_ngrams(counts, ngrams, options);
+    }
+
+    if options.fuzzy {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        for (category, index) in &counts.near_duplicates {
+            print_near_duplicates(counts, *category, index, options);
+        }
+    }
+
+    if let (Some(depth), Some(corpus)) = (options.group_by_dir, &options.corpus) {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        let (names, groups) = group_files(counts, |file| {
+            directory_group(corpus, &counts.files[file], depth)
+        });
+        print_groups(counts, "directories", &names, &groups, options);
+        if options.split_suggestions {
+            println!();
+            print_split_suggestions(counts, &names, &groups, options);
+        }
+    }
+
+    if options.by_toolchain {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        let (names, groups) = group_files(counts, |file| counts.toolchains[file].clone());
+        print_groups(counts, "toolchains", &names, &groups, options);
+    }
+
+    if options.by_language {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        let (names, groups) = group_files(counts, |file| counts.languages[file].to_string());
+        print_groups(counts, "languages", &names, &groups, options);
+    }
+
+    println!("--------------------------------------------------------------------------------");
+
+    let identical = counts.identical_modules();
+    let dupe_modules: u64 = identical
+        .iter()
+        .map(|entry| entry.size * (entry.files.len() as u64 - 1))
+        .sum();
+    print_row(
+        "Identical whole modules",
+        dupe_modules,
+        counts.total_size,
+        options,
+    );
+    for entry in identical {
+        println!();
+        println!(
+            "  {} copies of {} bytes ({} redundant bytes):",
+            entry.files.len(),
+            entry.size,
+            entry.size * (entry.files.len() as u64 - 1)
+        );
+        for file in &entry.files {
+            println!("    {}", counts.files[*file].display());
+        }
+    }
+
+    if options.who {
+        for category in counts.items.keys() {
+            print_who(counts, *category, options.who_min_size);
+        }
+    }
+
+    if let Some(timings) = &counts.timings {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        print_timings(timings);
+    }
+
+    if options.check_baseline.is_some()
+        || options.fail_if_duplication_above.is_some()
+        || !options.fail_if_category_duplication_above.is_empty()
+    {
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+    }
+    write_and_check_baseline(counts, options)
+}
+
+/// Print where the time of the run went, for `--timings`.
+///
+/// Files are listed and read on other threads while counting, so the phases
+/// overlap, and hashing is the time of every thread hashing items.
+fn print_timings(timings: &timings::Counting) {
+    use timings::Phase;
+    let phases = &timings.phases;
+    let rows = [
+        ("Wall time", phases.elapsed()),
+        ("  listing directories", phases.total(Phase::Walking)),
+        ("  reading files", phases.total(Phase::Reading)),
+        ("  waiting for reads", phases.total(Phase::Waiting)),
+        ("  parsing and counting", timings.total),
+        ("  hashing, on all threads", phases.total(Phase::Hashing)),
+    ];
+    for (label, time) in rows {
+        println!(
+            "{:<width$}{:>9.2?}",
+            format!("{}:", label),
+            time,
+            width = LABEL_WIDTH
+        );
+    }
+
+    let slowest = timings.slowest();
+    if !slowest.is_empty() {
+        println!();
+        println!("Slowest files to parse and count:");
+        for (time, path) in slowest {
+            println!("  {:>9.2?}  {}", time, path.display());
+        }
+    }
+}
+
+/// Print the one-line summary of `--quiet`, and write the requested output
+/// files.
+fn report_quiet(counts: &Counts, options: &Options) -> Result<()> {
+    if options.check_baseline.is_none() {
+        let mut dupe = Duplication::default();
+        for category in Category::ALL {
+            dupe += counts.duplicated(*category);
+        }
+        println!(
+            "total_size={} duplicated={} duplicated_percent={:.02} within_modules={} \
+             across_modules={}",
+            counts.total_size,
+            dupe.total(),
+            percent(dupe.total(), counts.total_size),
+            dupe.within_modules,
+            dupe.across_modules
+        );
+    }
+    write_outputs(counts, options)?;
+    write_and_check_baseline(counts, options)
+}
+
+/// Write the files requested by `--similarity-matrix`, `--shared-module`,
+/// `--output-db`, `--metrics-out`, `--export-index`, `--append-to`, and
+/// `--emit-plan`.
+fn write_outputs(counts: &Counts, options: &Options) -> Result<()> {
+    if let Some(path) = &options.similarity_matrix {
+        write_similarity_matrix(counts, path, options)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        run::Run::new(counts, options).write_next_to(path)?;
+    }
+
+    if let Some(path) = &options.dot {
+        write_dot(counts, path, options)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    if let Some(path) = &options.shared_module {
+        let wat = shared::wat(counts, options.shared_module_items)?;
+        std::fs::write(path, wat).with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    if let Some(path) = &options.output_db {
+        sqlite::write(counts, path, &run::Run::new(counts, options))?;
+    }
+
+    if let Some(path) = &options.metrics_out {
+        metrics::write(counts, path)?;
+    }
+
+    if let Some(path) = &options.export_index {
+        index::write(counts, path)?;
+    }
+
+    if let Some(path) = &options.append_to {
+        index::write(counts, path)?;
+    }
+
+    if let Some(path) = &options.emit_plan {
+        plan::write(counts, path, run::Run::new(counts, options))?;
+    }
+
+    if let Some(attribution) = &counts.attribution {
+        if let Some(path) = &options.treemap {
+            treemap::write(attribution, counts, path)?;
+        }
+        if let Some(path) = &options.folded {
+            attribution.write_folded(counts, path)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_and_check_baseline(counts: &Counts, options: &Options) -> Result<()> {
+    let current = baseline::Baseline::new(counts, run::Run::new(counts, options));
+    if let Some(path) = &options.write_baseline {
+        current.write(path)?;
+    }
+    if let Some(path) = &options.check_baseline {
+        current.check(
+            &baseline::Baseline::read(path)?,
+            options.max_regression,
+            !options.quiet && options.format == OutputFormat::Human,
+        )?;
+    }
+    baseline::check_limits(
+        counts,
+        options.fail_if_duplication_above,
+        &options.fail_if_category_duplication_above,
+        !options.quiet && options.format == OutputFormat::Human,
+    )
+}
+
+/// Print how the measurements changed from `old` to `new`.
+fn report_diff(old: &Counts, new: &Counts, options: &Options) {
+    print_change("Total size", old.total_size, new.total_size, options);
+
+    println!("--------------------------------------------------------------------------------");
+
+    let mut old_total = Duplication::default();
+    let mut new_total = Duplication::default();
+    for category in Category::ALL {
+        let old_dupe = old.duplicated(*category);
+        let new_dupe = new.duplicated(*category);
+        print_duplication_change(
+            &format!("Duplicated {}", category.name()),
+            old_dupe,
+            new_dupe,
+            options,
+        );
+        old_total += old_dupe;
+        new_total += new_dupe;
+    }
+
+    println!("--------------------------------------------------------------------------------");
+
+    print_duplication_change("Total duplicated data", old_total, new_total, options);
+
+    for category in Category::ALL {
+        let introduced: Vec<_> = new
+            .duplicated_items(*category, options.who_min_size)
+            .into_iter()
+            .filter(|(hash, _)| {
+                old.items
+                    .get(category)
+                    .and_then(|items| items.get(*hash))
+                    .map_or(true, |entry| entry.count < 2)
+            })
+            .collect();
+        if introduced.is_empty() {
+            continue;
+        }
+
+        println!(
+            "--------------------------------------------------------------------------------"
+        );
+        println!(
+            "New duplicated {} of at least {} bytes:",
+            category.name(),
+            options.who_min_size
+        );
+        for (hash, entry) in introduced {
+            println!();
+            println!(
+                "  {} copies of {} bytes ({} redundant bytes), hash {}:",
+                entry.count,
+                entry.size,
+                entry.size * (entry.count - 1),
+                hex(&hash[..8])
+            );
+            for file in &entry.files {
+                println!("    {}", new.files[*file].display());
+            }
+        }
+    }
+}
+
+fn print_change(label: &str, old: u64, new: u64, options: &Options) {
+    if options.human {
+        let (sign, change) = if new >= old {
+            ('+', new - old)
+        } else {
+            ('-', old - new)
+        };
+        println!(
+            "{:<width$}{:>10} -> {:>10} ({}{})",
+            format!("{}:", label),
+            human_size(old),
+            human_size(new),
+            sign,
+            human_size(change),
+            width = LABEL_WIDTH
+        );
+        return;
+    }
+    println!(
+        "{:<width$}{:>9} -> {:>9} bytes ({:+})",
+        format!("{}:", label),
+        old,
+        new,
+        new as i64 - old as i64,
+        width = LABEL_WIDTH
+    );
+}
+
+fn print_duplication_change(label: &str, old: Duplication, new: Duplication, options: &Options) {
+    print_change(label, old.total(), new.total(), options);
+    print_change(
+        "  within modules",
+        old.within_modules,
+        new.within_modules,
+        options,
+    );
+    print_change(
+        "  across modules",
+        old.across_modules,
+        new.across_modules,
+        options,
+    );
+}
+
+/// Replace the contents of `buf` with the contents of the file at `path`.
+fn read_file(path: &Path, buf: &mut Vec<u8>) -> Result<()> {
+    let mut file =
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    buf.clear();
+    file.read_to_end(buf)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(())
+}
+
+/// `part` as a percentage of `whole`, or 0 of nothing, rather than NaN.
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    part as f64 / whole as f64 * 100.0
+}
+
+/// The width of the label column in the summary.
+const LABEL_WIDTH: usize = 40;
+
+/// When to color the summary, with `--color`.
+#[derive(Clone, Copy, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        use std::io::IsTerminal;
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // See https://no-color.org/.
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => anyhow::bail!("unknown color choice: {}", s),
+        }
+    }
+}
+
+/// `line` colored green, yellow, or red by how big the duplicated `percent`
+/// of the corpus it shows is, with `--color`.
+fn paint(line: &str, percent: f64, options: &Options) -> String {
+    if !options.color.enabled() {
+        return line.to_string();
+    }
+    let color = if percent >= options.color_red {
+        31
+    } else if percent >= options.color_yellow {
+        33
+    } else {
+        32
+    };
+    format!("\x1b[{}m{}\x1b[0m", color, line)
+}
+
+/// Format `bytes` for a column of the summary.
+fn size(bytes: u64, options: &Options) -> String {
+    if options.human {
+        format!("{:>15}", human_size(bytes))
+    } else {
+        format!("{:>9} bytes", bytes)
+    }
+}
+
+/// Format `bytes` in the largest binary unit that keeps it at least 1.
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.02} {}", value, UNITS[unit])
+}
+
+fn print_row(label: &str, bytes: u64, total_size: u64, options: &Options) {
+    println!(
+        "{:<width$}{} ({:.02}%)",
+        format!("{}:", label),
+        size(bytes, options),
+        percent(bytes, total_size),
+        width = LABEL_WIDTH
+    );
+}
+
+fn print_duplication(label: &str, dupe: Duplication, total_size: u64, options: &Options) {
+    let line = format!(
+        "{:<width$}{} ({:.02}%)",
+        format!("{}:", label),
+        size(dupe.total(), options),
+        percent(dupe.total(), total_size),
+        width = LABEL_WIDTH
+    );
+    println!(
+        "{}",
+        paint(&line, percent(dupe.total(), total_size), options)
+    );
+    print_row("  within modules", dupe.within_modules, total_size, options);
+    print_row("  across modules", dupe.across_modules, total_size, options);
+}
+
+/// Print the hash collisions that `--verify` found.
+fn print_collisions(counts: &Counts) {
+    let mut total = 0;
+    for category in counts.items.keys() {
+        for (hash, entry) in counts.sorted_items(*category) {
+            if entry.collisions > 0 {
+                println!(
+                    "Hash collision: {} of {} bytes with hash {}, {} times",
+                    category.name(),
+                    entry.size,
+                    hex(hash),
+                    entry.collisions
+                );
+                total += entry.collisions;
+            }
+        }
+    }
+    println!(
+        "{:<width$}{:>9} (every other duplicate was verified byte for byte)",
+        "Hash collisions:",
+        total,
+        width = LABEL_WIDTH
+    );
+}
+
+/// Print the cumulative savings of deduplicating only the most redundant items
+/// of the strict categories, for increasing powers of ten.
+fn print_win_curve(counts: &Counts, options: &Options) {
+    let mut redundant: Vec<u64> = Category::ALL
+        .iter()
+        .filter_map(|category| counts.items.get(category))
+        .flat_map(|m| m.values())
+        .filter(|entry| entry.count > 1 && entry.size >= counts.min_size)
+        .map(|entry| entry.size * (entry.count - 1))
+        .collect();
+    redundant.sort_unstable_by(|a, b| b.cmp(a));
+
+    println!("Deduplicating only the most redundant items:");
+    let mut saved = 0;
+    let mut next = 1;
+    for (i, bytes) in redundant.iter().enumerate() {
+        saved += bytes;
+        if i + 1 == next {
+            print_row(
+                &format!("  top {} items", next),
+                saved,
+                counts.total_size,
+                options,
+            );
+            next *= 10;
+        }
+    }
+    print_row(
+        &format!("  all {} items", redundant.len()),
+        saved,
+        counts.total_size,
+        options,
+    );
+}
+
+/// Print how the redundant bytes of each strict category are distributed
+/// between duplicated items of increasing sizes, in power-of-four buckets.
+fn print_histograms(counts: &Counts, options: &Options) {
+    for category in Category::ALL {
+        let dupes = counts.duplicated_items(*category, 0);
+        if dupes.is_empty() {
+            continue;
+        }
+        // The number of items and the redundant bytes in each bucket. Bucket
+        // `i` holds the items of less than `16 << (2 * i)` bytes.
+        let mut buckets = vec![];
+        for (_, entry) in &dupes {
+            let mut i = 0;
+            while entry.size >= 16 << (2 * i) {
+                i += 1;
+            }
+            if buckets.len() <= i {
+                buckets.resize(i + 1, (0, 0));
+            }
+            buckets[i].0 += 1;
+            buckets[i].1 += entry.size * (entry.count - 1);
+        }
+
+        println!("Duplicated {} by size:", category.name());
+        for (i, (items, redundant)) in buckets.into_iter().enumerate() {
+            if items == 0 {
+                continue;
+            }
+            let below = 16u64 << (2 * i);
+            let label = if i == 0 {
+                format!("  < {} bytes ({} items)", below, items)
+            } else {
+                format!("  {}..{} bytes ({} items)", below / 4, below, items)
+            };
+            print_row(&label, redundant, counts.total_size, options);
+        }
+    }
+}
+
+/// Assign every file of `counts` to a group named by `name(file index)`.
+///
+/// Returns the names of the groups, and the group index of each file.
+fn group_files(
+    counts: &Counts,
+    mut name: impl FnMut(usize) -> String,
+) -> (Vec<String>, Vec<usize>) {
+    let mut names = vec![];
+    let mut indices = HashMap::new();
+    let groups = (0..counts.files.len())
+        .map(|file| {
+            let name = name(file);
+            *indices.entry(name.clone()).or_insert_with(|| {
+                names.push(name);
+                names.len() - 1
+            })
+        })
+        .collect();
+    (names, groups)
+}
+
+/// The name of the directory `depth` levels below `corpus` that contains
+/// `path`.
+fn directory_group(corpus: &Path, path: &Path, depth: usize) -> String {
+    let relative = path.strip_prefix(corpus).unwrap_or(path);
+    let dir: PathBuf = relative
+        .parent()
+        .map(|parent| parent.components().take(depth).collect())
+        .unwrap_or_default();
+    if dir.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        dir.display().to_string()
+    }
+}
+
+/// Print how much of the duplication of the strict categories is within and
+/// across groups of files, and how much of each group is shared with others.
+/// `groups` has the index into `names` of each file's group, and `kind` is
+/// what the groups are, in the plural.
+///
+/// Requires the files of every item to be tracked.
+fn print_groups(
+    counts: &Counts,
+    kind: &str,
+    names: &[String],
+    groups: &[usize],
+    options: &Options,
+) {
+    let mut dupe = Duplication::default();
+    // The bytes of the copies of items that the indexes also contain, which
+    // aren't in any group.
+    let mut in_indexes = 0;
+    // The bytes of each group's items that other groups also contain.
+    let mut shared = vec![0; names.len()];
+    let mut in_groups = HashMap::new();
+    for category in Category::ALL {
+        // Leave out the items whose files stopped being tracked with
+        // `--max-memory`, and those only in the indexes.
+        for entry in counts
+            .sized_items(*category)
+            .filter(|entry| entry.count > 1 && !entry.files.is_empty())
+        {
+            let copies = entry.count - entry.indexed;
+            if entry.indexed > 0 {
+                in_indexes += entry.size * copies;
+            }
+            // The number of modules in each group containing the item.
+            in_groups.clear();
+            for file in &entry.files {
+                *in_groups.entry(groups[*file]).or_insert(0) += 1;
+            }
+            let across = entry.size * (in_groups.len() as u64 - 1);
+            dupe.across_modules += across;
+            dupe.within_modules += entry.size * (copies - 1) - across;
+            if in_groups.len() > 1 {
+                for (group, modules) in &in_groups {
+                    shared[*group] += entry.size * modules;
+                }
+            }
+        }
+    }
+
+    print_row(
+        &format!("Duplicated within {}", kind),
+        dupe.within_modules,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        &format!("Duplicated across {}", kind),
+        dupe.across_modules,
+        counts.total_size,
+        options,
+    );
+    if counts.indexed_files > 0 {
+        print_row(
+            "Copies of items in the indexes",
+            in_indexes,
+            counts.total_size,
+            options,
+        );
+    }
+
+    let mut sizes = vec![(0, 0); names.len()];
+    for (group, size) in groups.iter().zip(&counts.file_sizes) {
+        sizes[*group].0 += 1;
+        sizes[*group].1 += size;
+    }
+    let mut order: Vec<usize> = (0..names.len()).collect();
+    order.sort_by_key(|group| std::cmp::Reverse(shared[*group]));
+    for group in order {
+        let (files, size) = sizes[group];
+        println!();
+        println!("  {} ({} files, {} bytes):", names[group], files, size);
+        print_row(
+            &format!("    shared with other {}", kind),
+            shared[group],
+            size,
+            options,
+        );
+    }
+}
+
+/// Print what splitting the modules of each of the directories `names` (with
+/// the index into `names` of each file's directory in `groups`) into an
+/// app-specific half and a half of the items shared with other directories
+/// would win, if the shared halves were deduplicated across directories.
+fn print_split_suggestions(counts: &Counts, names: &[String], groups: &[usize], options: &Options) {
+    // The bytes of each directory's items that other directories also contain,
+    // and of the redundant copies of items only in that directory.
+    let mut shared = vec![0; names.len()];
+    let mut internal = vec![0; names.len()];
+    // The size of one copy of every item in the shared halves, and the bytes
+    // that deduplicating them saves.
+    let mut shared_size = 0;
+    let mut savings = 0;
+    let mut in_groups = HashMap::new();
+    for category in Category::ALL {
+        for entry in counts
+            .sized_items(*category)
+            .filter(|entry| entry.count > 1)
+        {
+            in_groups.clear();
+            for file in &entry.files {
+                *in_groups.entry(groups[*file]).or_insert(0) += 1;
+            }
+            if in_groups.len() > 1 {
+                for (group, modules) in &in_groups {
+                    shared[*group] += entry.size * modules;
+                }
+                shared_size += entry.size;
+                savings += entry.size * (entry.count - 1);
+            } else if let Some(group) = in_groups.keys().next() {
+                internal[*group] += entry.size * (entry.count - 1);
+            }
+        }
+    }
+
+    println!("Splitting each directory into app-specific and shared halves:");
+    print_row(
+        "  the deduplicated shared halves",
+        shared_size,
+        counts.total_size,
+        options,
+    );
+    print_row("  savings", savings, counts.total_size, options);
+
+    let mut sizes = vec![0; names.len()];
+    for (group, size) in groups.iter().zip(&counts.file_sizes) {
+        sizes[*group] += size;
+    }
+    let mut order: Vec<usize> = (0..names.len()).collect();
+    order.sort_by_key(|group| (std::cmp::Reverse(shared[*group]), *group));
+    for group in order {
+        let size = sizes[group];
+        println!();
+        println!("  {} ({} bytes):", names[group], size);
+        print_row(
+            "    app-specific half",
+            size.saturating_sub(shared[group]),
+            size,
+            options,
+        );
+        print_row(
+            "      duplicated only within it",
+            internal[group],
+            size,
+            options,
+        );
+        print_row("    shared half", shared[group], size, options);
+    }
+}
+
+/// Print the duplication of a normalized category, along with how much more
+/// it finds than the strict category it normalizes.
+fn print_normalized(counts: &Counts, category: Category, options: &Options) {
+    let strict = category
+        .normalizes()
+        .expect("only normalized categories are printed as normalized");
+    // Only object files have relocations to mask, so compare with them alone.
+    let baseline = match (category, &counts.objects) {
+        (Category::RelocCodeBodies | Category::RelocDataSegments, Some(objects)) => objects,
+        _ => counts,
+    };
+    let dupe = counts.duplicated(category);
+    print_duplication(
+        &format!("Duplicated {}", category.name()),
+        dupe,
+        baseline.total_size,
+        options,
+    );
+    print_row(
+        &format!("  beyond {}", strict.name()),
+        dupe.total()
+            .saturating_sub(baseline.duplicated(strict).total()),
+        baseline.total_size,
+        options,
+    );
+}
+
+/// Print the corpus size and the total duplication of the strict categories
+/// after compressing with `codec`, the `i`th entry of `compress::Codec::ALL`.
+///
+/// Each duplicated item is compressed on its own, which overestimates its
+/// compressed size a little since it doesn't benefit from the context around
+/// it.
+fn print_compressed(counts: &Counts, i: usize, codec: compress::Codec, options: &Options) {
+    let compressed_size = counts.compressed_size[i];
+    print_row(
+        &format!("Total size ({})", codec.name()),
+        compressed_size,
+        counts.total_size,
+        options,
+    );
+    let mut dupe_total = Duplication::default();
+    for category in Category::ALL {
+        dupe_total += counts.duplicated_compressed(*category, i);
+    }
+    print_duplication(
+        &format!("Total duplicated data ({})", codec.name()),
+        dupe_total,
+        compressed_size,
+        options,
+    );
+}
+
+/// Train a shared zstd dictionary on `samples`, then compress every file again
+/// with and without it and print the difference.
+fn print_shared_dictionary(
+    counts: &Counts,
+    samples: &compress::DictionarySamples,
+    options: &Options,
+) -> Result<()> {
+    let dictionary = samples.train().context("failed to train zstd dictionary")?;
+
+    let mut wasm = vec![];
+    let mut sizes = vec![];
+    for path in &counts.files {
+        counts.load(path, &mut wasm)?;
+        sizes.push(compress::zstd_sizes(&wasm, &dictionary)?);
+    }
+
+    let standalone: u64 = sizes.iter().map(|(standalone, _)| standalone).sum();
+    let with_dictionary: u64 = sizes.iter().map(|(_, with)| with).sum();
+    print_row(
+        "Total size (zstd -19)",
+        standalone,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "Total size (zstd -19, shared dictionary)",
+        with_dictionary,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "  including the dictionary itself",
+        with_dictionary + dictionary.len() as u64,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "Saved by the shared dictionary",
+        standalone.saturating_sub(with_dictionary + dictionary.len() as u64),
+        standalone,
+        options,
+    );
+
+    if options.who {
+        println!();
+        for (path, (standalone, with_dictionary)) in counts.files.iter().zip(&sizes) {
+            println!(
+                "  {:>9} -> {:>9} bytes: {}",
+                standalone,
+                with_dictionary,
+                path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Pair up modules that share at least `--delta-min-shared` of their bytes,
+/// then print how much smaller shipping each pair's base in full plus a delta
+/// for its target is than shipping both in full.
+///
+/// Pairs are picked greedily, most shared bytes first. Every module is the
+/// target of at most one pair, and bases are never targets themselves, so
+/// that every delta can be applied to a module that is shipped in full.
+fn print_deltas(counts: &Counts, options: &Options) -> Result<()> {
+    let mut pairs: Vec<_> = counts
+        .shared_bytes(options.max_pair_files)
+        .into_iter()
+        .collect();
+    pairs.sort_by_key(|(pair, shared)| (std::cmp::Reverse(*shared), *pair));
+
+    let mut is_target = vec![false; counts.files.len()];
+    let mut is_base = vec![false; counts.files.len()];
+    let mut deltas = vec![];
+    for ((a, b), shared) in pairs {
+        // Prefer the larger module as the base; it's more likely to contain
+        // everything that the smaller one needs.
+        let (base, target) = if counts.file_sizes[a] >= counts.file_sizes[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        if (shared as f64) < options.delta_min_shared * counts.file_sizes[target] as f64 {
+            continue;
+        }
+        if is_target[target] || is_target[base] || is_base[target] {
+            continue;
+        }
+        is_target[target] = true;
+        is_base[base] = true;
+        deltas.push((base, target));
+    }
+
+    let mut base_wasm = vec![];
+    let mut target_wasm = vec![];
+    let mut full = 0;
+    let mut compressed = 0;
+    let mut delta = 0;
+    let mut sizes = vec![];
+    for (base, target) in &deltas {
+        counts.load(&counts.files[*base], &mut base_wasm)?;
+        counts.load(&counts.files[*target], &mut target_wasm)?;
+        let (standalone, with_base) = compress::zstd_sizes(&target_wasm, &base_wasm)?;
+        full += target_wasm.len() as u64;
+        compressed += standalone;
+        delta += with_base;
+        sizes.push((standalone, with_base));
+    }
+
+    println!("Delta-encoded modules: {}", deltas.len());
+    print_row(
+        "Full size of delta-encoded modules",
+        full,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "  compressed (zstd -19)",
+        compressed,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "  as deltas (zstd -19 --patch-from)",
+        delta,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "Saved by delta encoding",
+        full.saturating_sub(delta),
+        counts.total_size,
+        options,
+    );
+    print_row(
+        "  beyond compression",
+        compressed.saturating_sub(delta),
+        counts.total_size,
+        options,
+    );
+
+    if options.who {
+        for ((base, target), (standalone, with_base)) in deltas.iter().zip(&sizes) {
+            println!();
+            println!(
+                "  {} ({} bytes, {} compressed, {} as a delta)",
+                counts.files[*target].display(),
+                counts.file_sizes[*target],
+                standalone,
+                with_base
+            );
+            println!("    against {}", counts.files[*base].display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the module that each module shares the most bytes with, the modules
+/// sharing the largest part of themselves first.
+fn print_most_similar(counts: &Counts, options: &Options) {
+    let mut most_similar: Vec<Option<(usize, u64)>> = vec![None; counts.files.len()];
+    for ((a, b), shared) in counts.shared_bytes(options.max_pair_files) {
+        for (file, other) in [(a, b), (b, a)] {
+            let best = &mut most_similar[file];
+            if best.map_or(true, |(best, best_shared)| {
+                (shared, std::cmp::Reverse(other)) > (best_shared, std::cmp::Reverse(best))
+            }) {
+                *best = Some((other, shared));
+            }
+        }
+    }
+    let mut files: Vec<_> = most_similar
+        .into_iter()
+        .enumerate()
+        .filter_map(|(file, best)| best.map(|(other, shared)| (file, other, shared)))
+        .collect();
+    files.sort_by(|(a, _, a_shared), (b, _, b_shared)| {
+        percent(*b_shared, counts.file_sizes[*b])
+            .partial_cmp(&percent(*a_shared, counts.file_sizes[*a]))
+            .unwrap()
+            .then(a.cmp(b))
+    });
+
+    println!("Most similar other module:");
+    for (file, other, shared) in files {
+        println!();
+        println!(
+            "  {} ({} bytes):",
+            counts.files[file].display(),
+            counts.file_sizes[file]
+        );
+        println!("    most similar to {}", counts.files[other].display());
+        print_row("    shared", shared, counts.file_sizes[file], options);
+    }
+}
+
+/// Print the `top` relocation-masked code bodies of object files with the most
+/// redundant bytes, along with the function symbol naming each.
+fn print_duplicated_symbols(counts: &Counts, top: usize) {
+    println!("Most duplicated function symbols of relocatable objects:");
+    for (hash, entry) in counts
+        .duplicated_items(Category::RelocCodeBodies, 0)
+        .into_iter()
+        .take(top)
+    {
+        println!();
+        println!(
+            "  {} copies of {} bytes ({} redundant bytes), hash {}:",
+            entry.count,
+            entry.size,
+            entry.size * (entry.count - 1),
+            hex(&hash[..8])
+        );
+        println!(
+            "    {}",
+            counts
+                .name(Category::RelocCodeBodies, hash)
+                .unwrap_or("(unnamed)")
+        );
+    }
+}
+
+fn print_by_crate(counts: &Counts, options: &Options) {
+    let mut crates: BTreeMap<String, GroupTotals> = BTreeMap::new();
+    for (hash, entry) in counts
+        .items
+        .get(&Category::CodeBodies)
+        .into_iter()
+        .flatten()
+        .filter(|(_, entry)| entry.size >= counts.min_size)
+    {
+        let name = match counts.name(Category::CodeBodies, hash) {
+            Some(name) => names::namespace(name).unwrap_or("(no namespace)"),
+            None => "(unnamed)",
+        };
+        let totals = crates.entry(name.to_string()).or_default();
+        totals.occurrences += entry.count;
+        totals.size += entry.size * entry.count;
+        totals.duplicated += entry.size * (entry.count - 1);
+    }
+    print_group_totals(
+        "Code bodies by crate or namespace:",
+        "crate",
+        &crates,
+        options,
+    );
+}
+
+/// Print up to `k` proposed shared modules, with the `--shared-modules-top` items
+/// saving the most bytes in each.
+fn print_shared_modules(counts: &Counts, k: usize, options: &Options) {
+    let modules = clusters::propose(counts, k, options.shared_module_budget);
+    println!(
+        "Proposed shared modules of at most {} bytes each:",
+        options.shared_module_budget
+    );
+    print_row(
+        &format!("  savings of {} shared modules", modules.len()),
+        modules.iter().map(|module| module.savings).sum(),
+        counts.total_size,
+        options,
+    );
+    for (i, module) in modules.iter().enumerate() {
+        println!();
+        println!(
+            "  Shared module #{}: {} items, {} bytes, imported by {} modules",
+            i + 1,
+            module.items.len(),
+            module.size,
+            module.users.len()
+        );
+        print_row("    savings", module.savings, counts.total_size, options);
+        for (category, hash, entry) in module.items.iter().take(options.shared_modules_top) {
+            let name = counts
+                .name(*category, hash)
+                .map_or_else(String::new, |name| format!(", {}", name));
+            println!(
+                "    {} bytes ({} redundant bytes) of {}, in {} modules, hash {}{}",
+                entry.size,
+                clusters::savings(entry),
+                category.name(),
+                entry.files.len(),
+                hex(&hash[..8]),
+                name
+            );
+        }
+        if module.items.len() > options.shared_modules_top {
+            println!(
+                "    ... and {} more items",
+                module.items.len() - options.shared_modules_top
+            );
+        }
+    }
+}
+
+/// Print the bytes of the code bodies of the functions whose names only differ
+/// in their generic arguments, on top of the largest body of each generic
+/// function, along with the `top` generic functions with the most such bytes.
+fn print_monomorphizations(counts: &Counts, top: usize, options: &Options) {
+    // The size of the largest body of each function, by the function's name
+    // without generic arguments. Bodies of functions with the same full name,
+    // such as different versions of a crate, aren't monomorphizations.
+    let mut generics: HashMap<String, HashMap<&str, u64>> = HashMap::new();
+    for (hash, entry) in counts
+        .items
+        .get(&Category::CodeBodies)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(name) = counts.name(Category::CodeBodies, hash) {
+            let size = generics
+                .entry(names::without_generics(name))
+                .or_default()
+                .entry(name)
+                .or_default();
+            *size = (*size).max(entry.size);
+        }
+    }
+    let mut ranked: Vec<_> = generics
+        .into_iter()
+        .filter(|(_, functions)| functions.len() > 1)
+        .map(|(generic, functions)| {
+            let sizes = functions.values();
+            let extra = sizes.clone().sum::<u64>() - sizes.max().unwrap();
+            (generic, functions.len(), extra)
+        })
+        .collect();
+    ranked.sort_by(|(a, _, a_extra), (b, _, b_extra)| b_extra.cmp(a_extra).then(a.cmp(b)));
+
+    println!("Monomorphizations of generic functions:");
+    print_row(
+        &format!(
+            "  {} functions of {} generics",
+            ranked
+                .iter()
+                .map(|(_, functions, _)| functions)
+                .sum::<usize>(),
+            ranked.len()
+        ),
+        ranked.iter().map(|(_, _, extra)| extra).sum(),
+        counts.total_size,
+        options,
+    );
+    println!();
+    for (generic, functions, extra) in ranked.into_iter().take(top) {
+        println!(
+            "  {:>9} bytes in {} functions: {}",
+            extra, functions, generic
+        );
+    }
+}
+
+fn print_section_sizes(counts: &Counts, files: &[FileSections], options: &Options) {
+    println!("Sections by file:");
+    for (file, sections) in files.iter().enumerate() {
+        let file_size = counts.file_sizes[file];
+        println!();
+        println!("  {} ({} bytes):", counts.files[file].display(), file_size);
+        for (name, size) in &sections.sections {
+            print_row(&format!("    {}", name), *size, file_size, options);
+        }
+        for category in Category::ALL {
+            if let Some(size) = sections.duplicated.get(category) {
+                print_row(
+                    &format!("    duplicated {}", category.name()),
+                    *size,
+                    file_size,
+                    options,
+                );
+            }
+        }
+    }
+}
+
+fn print_within_module_code(counts: &Counts, within: &[WithinModuleCode], options: &Options) {
+    let mut ranked: Vec<_> = within.iter().collect();
+    ranked.sort_by_key(|module| (std::cmp::Reverse(module.size), module.file));
+    println!("Code bodies duplicated within their module:");
+    print_row(
+        &format!(
+            "  {} bodies in {} modules",
+            within.iter().map(|module| module.bodies).sum::<u64>(),
+            within.len()
+        ),
+        within.iter().map(|module| module.size).sum(),
+        counts.total_size,
+        options,
+    );
+    for module in ranked {
+        println!();
+        println!(
+            "  {} ({} bytes):",
+            counts.files[module.file].display(),
+            counts.file_sizes[module.file]
+        );
+        print_row(
+            &format!("    {} duplicate bodies", module.bodies),
+            module.size,
+            counts.file_sizes[module.file],
+            options,
+        );
+    }
+}
+
+/// Print how many bytes of the distinct items of the corpus, by category, are
+/// already in the base corpus of `overlap`, and then how many bytes of each of
+/// its modules are.
+fn print_overlap(counts: &Counts, against: &against::Against, options: &Options) {
+    let categories = against.overlap(counts);
+    let total: u64 = categories.iter().map(|(_, _, size)| size).sum();
+    let in_base: u64 = categories.iter().map(|(_, covered, _)| covered).sum();
+    println!(
+        "{:<width$}{} files, {}",
+        "Base corpus:",
+        against.base_files,
+        size(against.base_size, options).trim_start(),
+        width = LABEL_WIDTH
+    );
+    println!(
+        "{:<width$}{} files, {}",
+        "Candidate corpus:",
+        counts.files.len(),
+        size(counts.total_size, options).trim_start(),
+        width = LABEL_WIDTH
+    );
+    println!();
+    println!("Distinct items of the candidate already in the base:");
+    print_row("  all categories", in_base, total, options);
+    for (category, covered, size) in categories {
+        print_row(&format!("    {}", category.name()), covered, size, options);
+    }
+    println!("--------------------------------------------------------------------------------");
+    print_against(counts, against, options);
+}
+
+/// Print how many bytes of each module are already in the `--against` base
+/// modules, the most covered modules first.
+fn print_against(counts: &Counts, against: &against::Against, options: &Options) {
+    let covered = |file: usize| -> u64 {
+        against
+            .covered
+            .get(file)
+            .map_or(0, |categories| categories.values().sum())
+    };
+    println!(
+        "Copies of items of the {} base modules ({} bytes):",
+        against.base_files, against.base_size
+    );
+    print_row(
+        "  all modules",
+        (0..counts.files.len()).map(covered).sum(),
+        counts.total_size,
+        options,
+    );
+    let mut ranked: Vec<_> = (0..counts.files.len())
+        .filter(|file| covered(*file) > 0)
+        .collect();
+    ranked.sort_by_key(|file| (std::cmp::Reverse(covered(*file)), *file));
+    for file in ranked {
+        println!();
+        println!(
+            "  {} ({} bytes):",
+            counts.files[file].display(),
+            counts.file_sizes[file]
+        );
+        print_row(
+            "    in the base",
+            covered(file),
+            counts.file_sizes[file],
+            options,
+        );
+        for (category, size) in &against.covered[file] {
+            print_row(
+                &format!("      {}", category.name()),
+                *size,
+                counts.file_sizes[file],
+                options,
+            );
+        }
+    }
+}
+
+/// Write the bytes that each pair of modules shares to a CSV file at `path`,
+/// most shared bytes first.
+fn write_similarity_matrix(counts: &Counts, path: &Path, options: &Options) -> Result<()> {
+    let mut pairs: Vec<_> = counts
+        .shared_bytes(options.max_pair_files)
+        .into_iter()
+        .collect();
+    pairs.sort_by_key(|(pair, shared)| (std::cmp::Reverse(*shared), *pair));
+    pairs.truncate(options.similarity_top.unwrap_or(usize::MAX));
+
+    let mut out = std::io::BufWriter::new(File::create(path)?);
+    writeln!(out, "module_a,module_b,size_a,size_b,shared_bytes")?;
+    for ((a, b), shared) in pairs {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            csv_field(&counts.files[a].display().to_string()),
+            csv_field(&counts.files[b].display().to_string()),
+            counts.file_sizes[a],
+            counts.file_sizes[b],
+            shared
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Write a Graphviz graph of the modules sharing at least `--dot-min-shared`
+/// bytes with each other to `path`. Edges are labeled with the shared bytes, and
+/// drawn thicker the more bytes they share.
+fn write_dot(counts: &Counts, path: &Path, options: &Options) -> Result<()> {
+    let mut edges: Vec<_> = counts
+        .shared_bytes(options.max_pair_files)
+        .into_iter()
+        .filter(|(_, shared)| *shared >= options.dot_min_shared)
+        .collect();
+    edges.sort();
+    let max = edges.iter().map(|(_, shared)| *shared).max().unwrap_or(1);
+    let nodes: BTreeSet<usize> = edges.iter().flat_map(|((a, b), _)| [*a, *b]).collect();
+
+    let mut out = std::io::BufWriter::new(File::create(path)?);
+    writeln!(out, "graph sharing {{")?;
+    writeln!(out, "  node [shape=box];")?;
+    for file in nodes {
+        writeln!(
+            out,
+            "  m{} [label=\"{} ({} bytes)\"];",
+            file,
+            dot_escape(&counts.files[file].display().to_string()),
+            counts.file_sizes[file]
+        )?;
+    }
+    for ((a, b), shared) in edges {
+        writeln!(
+            out,
+            "  m{} -- m{} [label=\"{}\", weight={}, penwidth={:.02}];",
+            a,
+            b,
+            shared,
+            shared,
+            1.0 + 9.0 * shared as f64 / max as f64
+        )?;
+    }
+    writeln!(out, "}}")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Escape `label` for a quoted Graphviz string.
+fn dot_escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Quote `field` for CSV if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn print_near_duplicates(
+    counts: &Counts,
+    category: Category,
+    index: &fuzzy::Index,
+    options: &Options,
+) {
+    let clusters = index.clusters(options.fuzzy_threshold);
+    let shared = clusters.iter().map(|c| c.shared).sum();
+    let items: usize = clusters.iter().map(|c| c.members.len()).sum();
+    print_row(
+        &format!("Near-duplicate {}", category.name()),
+        shared,
+        counts.total_size,
+        options,
+    );
+    println!("  in {} clusters of {} items", clusters.len(), items);
+
+    if !options.who {
+        return;
+    }
+    for cluster in clusters.iter().filter(|c| c.shared >= options.who_min_size) {
+        println!();
+        println!(
+            "  {} items totalling {} bytes (~{} shared bytes):",
+            cluster.members.len(),
+            cluster.size,
+            cluster.shared
+        );
+        for member in &cluster.members {
+            let item = &index.items[*member];
+            println!(
+                "    {:>9} bytes in {}",
+                item.size,
+                counts.files[item.file].display()
+            );
+        }
+    }
+    println!();
+}
+
+fn print_outlining(counts: &Counts, outliner: &outline::Outliner, options: &Options) {
+    let sequences = outliner.sequences();
+    let saved = sequences.iter().map(|s| s.saved).sum();
+    print_row(
+        "Outlinable instruction sequences",
+        saved,
+        counts.total_size,
+        options,
+    );
+    println!("  into {} helper functions", sequences.len());
+
+    if !options.who {
+        return;
+    }
+    let large: Vec<_> = sequences
+        .iter()
+        .filter(|s| s.saved >= options.who_min_size)
+        .collect();
+    if large.is_empty() {
+        return;
+    }
+    println!();
+    for sequence in large {
+        println!(
+            "    {:>9} bytes saved by outlining {} instructions ({} bytes) from {} places",
+            sequence.saved, sequence.instructions, sequence.size, sequence.occurrences
+        );
+    }
+    println!();
+}
+
+/// Print a table of `groups` of items, ranked by how many of their bytes are
+/// duplicated.
+fn print_group_totals(
+    title: &str,
+    column: &str,
+    groups: &BTreeMap<String, GroupTotals>,
+    options: &Options,
+) {
+    let mut ranked: Vec<_> = groups.iter().collect();
+    ranked.sort_by_key(|(_, totals)| std::cmp::Reverse((totals.duplicated, totals.size)));
+    println!("{}", title);
+    println!();
+    println!(
+        "  {:<32}{:>12}{:>16}{:>16}",
+        column, "occurrences", "total", "duplicated"
+    );
+    for (name, totals) in ranked {
+        println!(
+            "  {:<32}{:>12}{:>16}{:>16}",
+            name,
+            totals.occurrences,
+            size(totals.size, options),
+            size(totals.duplicated, options)
+        );
+    }
+}
+
+fn print_weighted(counts: &Counts) {
+    let weighted = &counts.weighted;
+    let duplicated = weighted.duplicated();
+    println!(
+        "{:<width$}{:>9} byte-downloads",
+        "Weighted by downloads:",
+        weighted.size,
+        width = LABEL_WIDTH
+    );
+    println!(
+        "{:<width$}{:>9} byte-downloads ({:.02}%)",
+        "  duplicated:",
+        duplicated,
+        percent(duplicated, weighted.size),
+        width = LABEL_WIDTH
+    );
+    if weighted.unweighted_files > 0 {
+        println!("  {} files have no weight", weighted.unweighted_files);
+    }
+}
+
+/// Print how many bytes of each resource that `--http-split` splits files into
+/// are duplicates, which a shared HTTP cache could serve instead of fetching
+/// them again.
+fn print_http_split(counts: &Counts, options: &Options) {
+    println!("Servable from a shared HTTP cache, with files split into:");
+    let mut servable = 0;
+    for category in Category::RESOURCES {
+        let total = counts
+            .category_totals
+            .get(category)
+            .map_or(0, |totals| totals.size);
+        let dupe = counts.duplicated(*category).total();
+        servable += dupe;
+        print_row(
+            &format!("  {} ({} bytes)", category.name(), total),
+            dupe,
+            total,
+            options,
+        );
+    }
+    print_row("  all resources", servable, counts.total_size, options);
+}
+
+/// Print the duplication among the modules embedded in custom sections.
+fn print_embedded(embedded: &Counts, options: &Options) {
+    println!(
+        "{:<width$}{} in {} modules",
+        "Embedded modules:",
+        size(embedded.total_size, options),
+        embedded.files.len(),
+        width = LABEL_WIDTH
+    );
+    for category in Category::ALL {
+        print_duplication(
+            &format!("Duplicated embedded {}", category.name()),
+            embedded.duplicated(*category),
+            embedded.total_size,
+            options,
+        );
+    }
+}
+
+fn print_overlaps(counts: &Counts, options: &Options) {
+    println!("Overlap wins, on top of exact duplication:");
+    for (category, overlaps) in &counts.overlaps {
+        let wins = overlaps.wins();
+        print_row(
+            &format!("  {} sharing prefixes", category.name()),
+            wins.prefixes,
+            counts.total_size,
+            options,
+        );
+        print_row(
+            &format!("  {} sharing suffixes", category.name()),
+            wins.suffixes,
+            counts.total_size,
+            options,
+        );
+    }
+}
+
+fn print_memory_init(counts: &Counts, memory_init: &memory_init::MemoryInit, options: &Options) {
+    let items = counts.items.get(&Category::DataSegments);
+    let savings = memory_init.savings(|hash| {
+        items
+            .and_then(|items| items.get(hash))
+            .filter(|entry| entry.size >= counts.min_size)
+            .map_or(0, |entry| entry.count)
+    });
+    println!("Sharing data segments through `memory.init`:");
+    print_row(
+        "  Duplicated active data segments",
+        savings.duplicated,
+        counts.total_size,
+        options,
+    );
+    print_row(
+        &format!("  Net savings ({} segments)", savings.segments),
+        savings.net,
+        counts.total_size,
+        options,
+    );
+}
+
+fn print_ngrams(counts: &Counts, ngrams: &ngrams::Ngrams, options: &Options) {
+    println!(
+        "Most common {}-instruction sequences:",
+        options.ngrams.unwrap()
+    );
+    println!();
+    for (opcodes, tally) in ngrams.top(options.ngrams_top) {
+        println!(
+            "  {:>9} bytes ({:.02}%) in {} occurrences: {}",
+            tally.size,
+            percent(tally.size, counts.total_size),
+            tally.count,
+            opcodes.join(" ")
+        );
+    }
+}
+
+fn print_who(counts: &Counts, category: Category, min_size: u64) {
+    let dupes = counts.duplicated_items(category, min_size);
+    if dupes.is_empty() {
+        return;
+    }
+
+    println!("--------------------------------------------------------------------------------");
+    println!(
+        "Duplicated {} of at least {} bytes:",
+        category.name(),
+        min_size
+    );
+    for (hash, entry) in dupes {
+        println!();
+        let name = counts
+            .name(category, hash)
+            .map_or_else(String::new, |name| format!(", {}", name));
+        println!(
+            "  {} copies of {} bytes ({} redundant bytes), hash {}{}:",
+            entry.count,
+            entry.size,
+            entry.size * (entry.count - 1),
+            hex(&hash[..8]),
+            name
+        );
+        for file in &entry.files {
+            println!("    {}", counts.files[*file].display());
+        }
+    }
+}
+
+/// Order items by their redundant bytes, most first, and then by hash.
+fn most_redundant_first(
+    (a_hash, a): &(&WideHash, &SizeEntry),
+    (b_hash, b): &(&WideHash, &SizeEntry),
+) -> std::cmp::Ordering {
+    (b.size * (b.count - 1))
+        .cmp(&(a.size * (a.count - 1)))
+        .then_with(|| a_hash.cmp(b_hash))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Modules of at least this many bytes have their items hashed in parallel
+/// before they're added, which would otherwise bottleneck runs on a single
+/// huge module.
+const PREHASH_MIN_SIZE: usize = 16 << 20;
+
+/// A `--hash-bytes` long hash of an item.
+type WideHash = Box<[u8]>;
+
+/// Details about items that only some runs need, kept aside from their
+/// `SizeEntry`s so that other runs don't pay for them.
+type ItemDetails<T> = HashMap<(Category, WideHash), T>;
+
+/// The shortest hashes that `--max-memory` shortens every hash to.
+const SHORT_HASH_BYTES: usize = 8;
+
+/// Replace every key of `map` with `shorten(key)`, merging the values of the
+/// keys that then collide with `merge`.
+fn shorten_keys<K: Eq + std::hash::Hash, V>(
+    map: &mut HashMap<K, V>,
+    shorten: impl Fn(K) -> K,
+    mut merge: impl FnMut(&mut V, V),
+) {
+    let old = std::mem::take(map);
+    map.reserve(old.len());
+    for (key, value) in old {
+        match map.entry(shorten(key)) {
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            std::collections::hash_map::Entry::Occupied(mut entry) => merge(entry.get_mut(), value),
+        }
+    }
+}
+
+/// The first `bytes` bytes of `hash`. Every hasher's shorter hashes are
+/// prefixes of its longer ones.
+fn shorten_hash(hash: WideHash, bytes: usize) -> WideHash {
+    if hash.len() <= bytes {
+        return hash;
+    }
+    hash[..bytes].into()
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Xxh3,
+    Sha256,
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "xxh3" => Ok(HashAlgorithm::Xxh3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => anyhow::bail!("unknown hasher: {}", s),
+        }
+    }
+}
+
+/// How to print the summary, with `--format`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    #[default]
+    Human,
+    /// Version 1 of the JSON schema.
+    JsonV1,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "human" => Ok(OutputFormat::Human),
+            "json=v1" => Ok(OutputFormat::JsonV1),
+            "json" => anyhow::bail!("`json` needs a schema version, like `json=v1`"),
+            _ => anyhow::bail!("unknown format: {}", s),
+        }
+    }
+}
+
+/// What identifies a custom section, with `--custom-key`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum CustomKey {
+    #[default]
+    Data,
+    NameAndData,
+}
+
+impl std::str::FromStr for CustomKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "data" => Ok(CustomKey::Data),
+            "name+data" => Ok(CustomKey::NameAndData),
+            _ => anyhow::bail!("unknown custom section key: {}", s),
+        }
+    }
+}
+
+impl CustomKey {
+    /// The bytes identifying the custom section with `data`, whose contents,
+    /// its encoded name followed by `data`, are at `range` in `wasm`.
+    fn key<'a>(self, wasm: &'a [u8], range: &wasmparser::Range, data: &'a [u8]) -> &'a [u8] {
+        match self {
+            CustomKey::Data => data,
+            CustomKey::NameAndData => &wasm[range.start..range.end],
+        }
+    }
+}
+
+/// How to hash items.
+#[derive(Clone, Default)]
+struct Hasher {
+    algorithm: HashAlgorithm,
+    /// The length of every hash.
+    bytes: usize,
+    /// Where hashing's time is added up, with `--timings`.
+    phases: Option<Arc<timings::Phases>>,
+}
+
+impl Hasher {
+    fn new(options: &Options) -> Self {
+        let max_bytes = match options.hasher {
+            HashAlgorithm::Blake3 => usize::MAX,
+            HashAlgorithm::Xxh3 => 16,
+            HashAlgorithm::Sha256 => 32,
+        };
+        if options.hash_bytes > max_bytes {
+            log::warn!(
+                "The {:?} hasher has {}-byte hashes, so using those instead of `--hash-bytes {}`",
+                options.hasher,
+                max_bytes,
+                options.hash_bytes
+            );
+        }
+        Hasher {
+            algorithm: options.hasher,
+            bytes: options.hash_bytes.min(max_bytes),
+            phases: options.phases.clone(),
+        }
+    }
+}
+
+fn hash(data: &[u8], hasher: &Hasher) -> WideHash {
+    let phases = hasher.phases.as_deref();
+    timings::time(phases, timings::Phase::Hashing, || match hasher.algorithm {
+        HashAlgorithm::Blake3 => {
+            let mut blake3 = blake3::Hasher::new();
+            blake3.update(data);
+            let mut reader = blake3.finalize_xof();
+            let mut hash = vec![0; hasher.bytes].into_boxed_slice();
+            reader.fill(&mut hash);
+            hash
+        }
+        HashAlgorithm::Xxh3 => {
+            xxhash_rust::xxh3::xxh3_128(data).to_le_bytes()[..hasher.bytes].into()
+        }
+        HashAlgorithm::Sha256 => {
+            use sha2::Digest;
+            sha2::Sha256::digest(data)[..hasher.bytes].into()
+        }
+    })
+}
+
+/// Whether the constant expression `expr` is a lone `i32.const` or `i64.const`,
+/// rather than depending on an imported global.
+fn is_constant(expr: &wasmparser::InitExpr) -> Result<bool> {
+    let ops = expr
+        .get_operators_reader()
+        .into_iter()
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(matches!(
+        ops.as_slice(),
+        [
+            wasmparser::Operator::I32Const { .. } | wasmparser::Operator::I64Const { .. },
+            wasmparser::Operator::End
+        ]
+    ))
+}
+
+/// The kinds of items whose duplication we measure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Category {
+    DataSegments,
+    ElemSegments,
+    CodeBodies,
+    CustomSections,
+    /// Entries of the exception-handling tag section.
+    Tags,
+    /// GC recursion groups and lone GC types of type sections.
+    GcRecGroups,
+    /// Entries of table sections: their element types and limits.
+    Tables,
+    /// Data segments keyed only by their payload bytes.
+    DataPayloads,
+    /// Element segments keyed with their function indices renumbered.
+    RemappedElemSegments,
+    /// Code bodies keyed without their index-space immediates.
+    StructuralCodeBodies,
+    /// Code bodies keyed with their local declarations canonicalized.
+    SortedLocalsCodeBodies,
+    /// Data segments keyed by their decoded contents.
+    CanonicalLebDataSegments,
+    /// Element segments keyed by their decoded contents.
+    CanonicalLebElemSegments,
+    /// Code bodies keyed by their decoded contents.
+    CanonicalLebCodeBodies,
+    /// Code bodies keyed without their constant immediates.
+    ConstMaskedCodeBodies,
+    /// Content-defined chunks of whole files.
+    FileChunks,
+    /// Content-defined chunks of the payloads of data segments that aren't
+    /// exact duplicates.
+    DataSubSegments,
+    /// UTF-8 strings in the payloads of data segments that aren't exact
+    /// duplicates.
+    DataStrings,
+    /// Custom sections holding source maps or their URLs.
+    SourceMaps,
+    /// The module names of imports.
+    ImportModuleNames,
+    /// The field names of imports.
+    ImportFieldNames,
+    /// The names of exports.
+    ExportNames,
+    /// Whole modules nested within other modules by the module linking
+    /// proposal, whose items are also counted on their own.
+    NestedModules,
+    /// The code sections of files, with `--http-split`.
+    CodeResources,
+    /// The data sections of files, with `--http-split`.
+    DataResources,
+    /// The debug info custom sections of files, with `--http-split`.
+    DebugResources,
+    /// The rest of the sections of files, with `--http-split`.
+    OtherResources,
+    /// Active element segments, which initialize a table on instantiation.
+    ActiveElemSegments,
+    /// Passive element segments, which `table.init` copies into tables.
+    PassiveElemSegments,
+    /// Declared element segments, which only declare functions for `ref.func`.
+    DeclaredElemSegments,
+    /// Active data segments, which initialize a memory on instantiation.
+    ActiveDataSegments,
+    /// Passive data segments, which `memory.init` copies into memories.
+    PassiveDataSegments,
+    /// Active data segments at constant offsets, which can be turned into
+    /// passive segments copied to the same offset by the start function.
+    ConstOffsetDataSegments,
+    /// Code bodies of object files keyed with their relocated immediates
+    /// zeroed.
+    RelocCodeBodies,
+    /// Data segments of object files keyed with their relocated immediates
+    /// zeroed.
+    RelocDataSegments,
+    /// The core and component type sections of components.
+    ComponentTypes,
+    /// The core and component instance sections of components.
+    ComponentInstances,
+    /// The alias sections of components.
+    ComponentAliases,
+    /// The canonical function sections of components, which lift and lower
+    /// functions between core modules and component interfaces.
+    CanonicalFunctions,
+    /// The code bodies of adapter modules and of other glue functions that
+    /// bindings generators emit for the canonical ABI.
+    CanonicalAbiGlue,
+}
+
+impl Category {
+    const ALL: &'static [Category] = &[
+        Category::DataSegments,
+        Category::ElemSegments,
+        Category::CodeBodies,
+        Category::CustomSections,
+        Category::Tags,
+        Category::GcRecGroups,
+        Category::Tables,
+    ];
+
+    /// Alternative, more forgiving keys for the items of one of the categories
+    /// above. These are reported next to the category they normalize rather
+    /// than summed into the totals, since they count the same bytes again.
+    const NORMALIZED: &'static [Category] = &[
+        Category::DataPayloads,
+        Category::RemappedElemSegments,
+        Category::StructuralCodeBodies,
+        Category::SortedLocalsCodeBodies,
+        Category::CanonicalLebDataSegments,
+        Category::CanonicalLebElemSegments,
+        Category::CanonicalLebCodeBodies,
+        Category::ConstMaskedCodeBodies,
+        Category::RelocCodeBodies,
+        Category::RelocDataSegments,
+    ];
+
+    /// Items that slice the corpus up independently of the categories above.
+    /// These are reported on their own rather than summed into the totals.
+    const ANALYSES: &'static [Category] = &[
+        Category::FileChunks,
+        Category::DataSubSegments,
+        Category::DataStrings,
+        Category::SourceMaps,
+        Category::ImportModuleNames,
+        Category::ImportFieldNames,
+        Category::ExportNames,
+        Category::NestedModules,
+        Category::ComponentTypes,
+        Category::ComponentInstances,
+        Category::ComponentAliases,
+        Category::CanonicalFunctions,
+        Category::CanonicalAbiGlue,
+    ];
+
+    /// The element segments of each kind, since passive ones can already be
+    /// shared through `table.init` while active ones can't. These are reported
+    /// under element segments rather than summed into the totals.
+    const ELEM_KINDS: &'static [Category] = &[
+        Category::ActiveElemSegments,
+        Category::PassiveElemSegments,
+        Category::DeclaredElemSegments,
+    ];
+
+    /// The data segments of each kind, for the same reason, along with the
+    /// active ones that could trivially become passive.
+    const DATA_KINDS: &'static [Category] = &[
+        Category::ActiveDataSegments,
+        Category::PassiveDataSegments,
+        Category::ConstOffsetDataSegments,
+    ];
+
+    /// The resources that `--http-split` splits every file into. These are
+    /// reported on their own rather than summed into the totals.
+    const RESOURCES: &'static [Category] = &[
+        Category::CodeResources,
+        Category::DataResources,
+        Category::DebugResources,
+        Category::OtherResources,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Category::DataSegments => "data segments",
+            Category::ElemSegments => "elem segments",
+            Category::CodeBodies => "code bodies",
+            Category::CustomSections => "custom sections",
+            Category::Tags => "tags",
+            Category::GcRecGroups => "GC rec groups",
+            Category::Tables => "tables",
+            Category::DataPayloads => "data payloads",
+            Category::RemappedElemSegments => "remapped elem segments",
+            Category::StructuralCodeBodies => "structural code bodies",
+            Category::SortedLocalsCodeBodies => "sorted-locals code bodies",
+            Category::CanonicalLebDataSegments => "canonical-LEB data segments",
+            Category::CanonicalLebElemSegments => "canonical-LEB elem segments",
+            Category::CanonicalLebCodeBodies => "canonical-LEB code bodies",
+            Category::ConstMaskedCodeBodies => "constant-masked code bodies",
+            Category::RelocCodeBodies => "relocation-masked code bodies",
+            Category::RelocDataSegments => "relocation-masked data segments",
+            Category::FileChunks => "file chunks",
+            Category::DataSubSegments => "data sub-segments",
+            Category::DataStrings => "data strings",
+            Category::SourceMaps => "source maps",
+            Category::ImportModuleNames => "import module names",
+            Category::ImportFieldNames => "import field names",
+            Category::ExportNames => "export names",
+            Category::NestedModules => "nested modules",
+            Category::ComponentTypes => "component type sections",
+            Category::ComponentInstances => "component instance sections",
+            Category::ComponentAliases => "component alias sections",
+            Category::CanonicalFunctions => "canonical function sections",
+            Category::CanonicalAbiGlue => "canonical ABI glue",
+            Category::CodeResources => "code",
+            Category::DataResources => "data",
+            Category::DebugResources => "debug info",
+            Category::OtherResources => "other sections",
+            Category::ActiveElemSegments => "active elem segments",
+            Category::PassiveElemSegments => "passive elem segments",
+            Category::DeclaredElemSegments => "declared elem segments",
+            Category::ActiveDataSegments => "active data segments",
+            Category::PassiveDataSegments => "passive data segments",
+            Category::ConstOffsetDataSegments => "constant-offset data segments",
+        }
+    }
+
+    /// The category named `name`.
+    fn from_name(name: &str) -> Option<Category> {
+        Category::ALL
+            .iter()
+            .chain(Category::NORMALIZED)
+            .chain(Category::ANALYSES)
+            .chain(Category::RESOURCES)
+            .chain(Category::ELEM_KINDS)
+            .chain(Category::DATA_KINDS)
+            .copied()
+            .find(|category| category.name() == name)
+    }
+
+    /// The categories that break this category down by kind.
+    fn kinds(self) -> &'static [Category] {
+        match self {
+            Category::DataSegments => Category::DATA_KINDS,
+            Category::ElemSegments => Category::ELEM_KINDS,
+            _ => &[],
+        }
+    }
+
+    /// The strict category that this category is a normalization of, if any.
+    fn normalizes(self) -> Option<Category> {
+        match self {
+            Category::DataPayloads
+            | Category::CanonicalLebDataSegments
+            | Category::RelocDataSegments => Some(Category::DataSegments),
+            Category::RemappedElemSegments | Category::CanonicalLebElemSegments => {
+                Some(Category::ElemSegments)
+            }
+            Category::StructuralCodeBodies
+            | Category::SortedLocalsCodeBodies
+            | Category::CanonicalLebCodeBodies
+            | Category::ConstMaskedCodeBodies
+            | Category::RelocCodeBodies => Some(Category::CodeBodies),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SizeEntry {
+    size: u64,
+    count: u64,
+    /// The number of distinct modules this item appears in.
+    modules: u64,
+    /// The occurrences in the files of the indexes read by `--append-to` or
+    /// `merge-index`, out of `count`.
+    indexed: u64,
+    /// The index (into `Counts::files`) of the last module this item appeared
+    /// in.
+    last_module: usize,
+    /// The indices (into `Counts::files`) of every module this item appeared
+    /// in. Only recorded for items of at least `Counts::track_files_min_size`
+    /// bytes.
+    files: Vec<usize>,
+    /// The item's compressed sizes. Only recorded for duplicated items of
+    /// strict categories, with `--compressed`.
+    compressed: Option<compress::Sizes>,
+    /// The key of the item's first occurrence, with `--verify`.
+    key: Option<Box<[u8]>>,
+    /// The number of occurrences whose key has the same hash as, but differs
+    /// from, `key`.
+    collisions: u64,
+}
+
+/// The totals of a group of items, such as the custom sections with some name
+/// for `--custom-section-names`.
+#[derive(Default)]
+struct GroupTotals {
+    occurrences: u64,
+    size: u64,
+    /// The size of every occurrence but the first of each distinct item.
+    duplicated: u64,
+}
+
+impl GroupTotals {
+    fn add(&mut self, size: u64, first: bool) {
+        self.occurrences += 1;
+        self.size += size;
+        if !first {
+            self.duplicated += size;
+        }
+    }
+}
+
+/// The sections of a file and the bytes of its items that were already seen,
+/// for `--section-sizes`.
+struct FileSections {
+    /// The name and size of each section.
+    sections: Vec<(String, u64)>,
+    duplicated: BTreeMap<Category, u64>,
+}
+
+/// The code bodies of a file that are copies of an earlier body of the same
+/// file, for `--within-module-code`.
+struct WithinModuleCode {
+    /// The index of the file in `Counts::files`.
+    file: usize,
+    bodies: u64,
+    size: u64,
+}
+
+/// The totals of all of a category's items, duplicated or not.
+#[derive(Clone, Copy, Default)]
+struct CategoryTotals {
+    /// The total size of every occurrence of every item.
+    size: u64,
+    /// The number of occurrences of every item.
+    items: u64,
+    /// The number of distinct items.
+    unique: u64,
+}
+
+/// Sizes weighted by the download counts of the files they're in, for
+/// `--weights`.
+#[derive(Default)]
+struct Weighted {
+    /// The size of every file times its downloads.
+    size: u64,
+    /// The size of every strict item, and the total and the largest download
+    /// counts of the files of its copies, counting a file once per copy.
+    copies: ItemDetails<Copies>,
+    /// The number of files without a weight.
+    unweighted_files: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Copies {
+    size: u64,
+    total: u64,
+    max: u64,
+}
+
+impl Weighted {
+    /// The size of every copy of every strict item but the copy in its most
+    /// downloaded file, times the downloads of its file. Unlike counting the
+    /// copies after the first, this doesn't depend on the order of the files.
+    fn duplicated(&self) -> u64 {
+        self.copies
+            .values()
+            .map(|copies| copies.size * (copies.total - copies.max))
+            .sum()
+    }
+}
+
+/// The bytes that deduplicating some set of items would save.
+#[derive(Clone, Copy, Default)]
+struct Duplication {
+    /// Redundant copies inside a module that already contains the item. This is
+    /// what intra-module function/segment merging can remove.
+    within_modules: u64,
+    /// Copies of the item in each additional module that contains it. This is
+    /// what cross-module content addressing can remove.
+    across_modules: u64,
+}
+
+impl Duplication {
+    fn total(&self) -> u64 {
+        self.within_modules + self.across_modules
+    }
+}
+
+impl std::ops::AddAssign for Duplication {
+    fn add_assign(&mut self, other: Duplication) {
+        self.within_modules += other.within_modules;
+        self.across_modules += other.across_modules;
+    }
+}
+
+/// A group of byte-identical Wasm files, identified by their indices into
+/// `Counts::files`.
+struct ModuleEntry {
+    size: u64,
+    files: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Counts {
+    total_size: u64,
+    files: Vec<PathBuf>,
+    /// The size of each file in `files`.
+    file_sizes: Vec<u64>,
+    modules: HashMap<WideHash, ModuleEntry>,
+    items: BTreeMap<Category, HashMap<WideHash, SizeEntry>>,
+    hasher: Hasher,
+    verify: bool,
+    /// Leave items smaller than this out of the duplication.
+    min_size: u64,
+    /// The download counts of files, with `--weights`.
+    weights: Option<weights::Weights>,
+    /// The download count of the file being added.
+    weight: u64,
+    weighted: Weighted,
+    custom_sections: CustomSectionFilter,
+    custom_key: CustomKey,
+    /// What finds the items of each strict category.
+    extractors: extract::Extractors,
+    /// The totals of each custom section name, with `--custom-section-names`.
+    custom_section_names: Option<BTreeMap<String, GroupTotals>>,
+    /// The totals of the element segments of each table, with
+    /// `--elem-by-table`.
+    elem_tables: Option<BTreeMap<String, GroupTotals>>,
+    /// The data segments by the memory they initialize, with
+    /// `--data-by-memory`.
+    data_memories: Option<BTreeMap<String, GroupTotals>>,
+    /// Whether to look for modules embedded in custom sections, with
+    /// `--embedded-modules`.
+    find_embedded: bool,
+    /// The index (into `files`) of the module and the offset within it of
+    /// every occurrence of every item of the strict categories, with
+    /// `--emit-plan`.
+    offsets: Option<ItemDetails<Vec<(usize, usize)>>>,
+    /// The demangled name of the first function with each code body that has
+    /// a name, with `--function-names` or `--relocatable`.
+    body_names: Option<ItemDetails<Box<str>>>,
+    /// The address of the first byte of the file being added.
+    module_start: usize,
+    /// The hashes of the items of the module being added, when it's large
+    /// enough to hash them in parallel up front, keyed by the address and
+    /// length of their bytes.
+    prehashed: HashMap<(usize, usize), WideHash>,
+    /// The ranges of the modules embedded in the custom sections of the file
+    /// being added.
+    embedded_ranges: Vec<std::ops::Range<usize>>,
+    /// The counts of the modules embedded in custom sections, if they're kept
+    /// separately from these ones.
+    embedded: Option<Box<Counts>>,
+    /// The strict code bodies and data segments of just the object files, with
+    /// `--relocatable`, which their relocation-masked ones are compared with.
+    objects: Option<Box<Counts>>,
+    /// The toolchain that produced each file, with `--by-toolchain`.
+    toolchains: Vec<String>,
+    by_toolchain: bool,
+    /// The source language of each file, with `--by-language`.
+    languages: Vec<&'static str>,
+    by_language: bool,
+    /// Whether to count the data and element segments of each kind, with
+    /// `--by-kind`.
+    by_kind: bool,
+    strip_debug: bool,
+    preprocessor: Option<preprocess::Preprocessor>,
+    /// The proposals to validate files against, with `--features`.
+    features: Option<wasmparser::WasmFeatures>,
+    /// The files that failed validation.
+    skipped: Vec<PathBuf>,
+    /// With `--low-memory`, the items that may be duplicated.
+    filter: Option<filter::CountingFilter>,
+    /// Whether this is the first pass of `--low-memory`, which only adds items
+    /// to `filter`.
+    filling_filter: bool,
+    /// The entry returned for items that aren't being kept track of.
+    untracked: SizeEntry,
+    /// Record which files contain each item of at least this many bytes.
+    track_files_min_size: Option<u64>,
+    offset_independent_data: bool,
+    normalize_elem: bool,
+    /// The normalized categories to key code bodies into, in addition to
+    /// `Category::CodeBodies`.
+    code_normalizations: Vec<(Category, normalize::CodeOptions)>,
+    /// Whether we need per-module type information to normalize code bodies.
+    need_types: bool,
+    canonical_lebs: bool,
+    /// Whether to key the items of object files with their relocations
+    /// zeroed, with `--relocatable`.
+    relocatable: bool,
+    /// Index the first occurrence of each item of at least this many bytes for
+    /// `--fuzzy`.
+    fuzzy_min_size: Option<u64>,
+    near_duplicates: BTreeMap<Category, fuzzy::Index>,
+    chunker: Option<cdc::Chunker>,
+    sub_segment_chunker: Option<cdc::Chunker>,
+    /// The minimum size of the strings to find in data segments, with
+    /// `--strings`.
+    strings_min_size: Option<usize>,
+    /// The first occurrences of data segment payloads and code bodies, for
+    /// `--overlap`.
+    overlaps: BTreeMap<Category, overlap::Overlaps>,
+    /// The first occurrences of active data segments, for `--memory-init`.
+    memory_init: Option<memory_init::MemoryInit>,
+    /// Whether to count import and export names, with `--names`.
+    names: bool,
+    /// Whether to name code bodies after their functions, with
+    /// `--function-names`.
+    function_names: bool,
+    /// Whether to count the canonical ABI glue, with `--canonical-abi-glue`.
+    canonical_abi_glue: bool,
+    http_split: bool,
+    /// The first occurrence of each code body, for `--outline`.
+    outliner: Option<outline::Outliner>,
+    ngrams: Option<ngrams::Ngrams>,
+    compress: bool,
+    /// The sum of every file's compressed sizes, with `--compressed`.
+    compressed_size: compress::Sizes,
+    /// The first occurrences of strict items to train `--zstd-dict` on.
+    dictionary_samples: Option<compress::DictionarySamples>,
+    cache: Option<cache::Cache>,
+    /// Every item added for the current file, while it is being cached.
+    recording: Option<Vec<cache::Entry>>,
+    /// The bytes of each category's items in the current file that were
+    /// already seen.
+    file_duplicated: BTreeMap<Category, u64>,
+    /// The bytes of strict items in the current file that were already seen
+    /// earlier in it.
+    file_within_modules: u64,
+    /// The files with code bodies duplicated within them, with
+    /// `--within-module-code`.
+    within_module_code: Option<Vec<WithinModuleCode>>,
+    /// The sections of every file, with `--section-sizes`.
+    section_sizes: Option<Vec<FileSections>>,
+    /// The redundant bytes of every duplicated item, with `--treemap` or
+    /// `--folded`.
+    attribution: Option<attribution::Attribution>,
+    /// The items of the base modules, with `--against`.
+    against: Option<against::Against>,
+    /// The budget for the item maps, with `--max-memory`.
+    max_memory: Option<u64>,
+    /// The bytes of the keys of items kept for `--verify`, and of the files
+    /// and offsets of items.
+    key_bytes: u64,
+    tracking_bytes: u64,
+    /// The details dropped to stay under `max_memory`, along with the number
+    /// of files counted when they were.
+    degradations: Vec<(budget::Degradation, usize)>,
+    /// The time spent counting files, with `--timings`.
+    timings: Option<timings::Counting>,
+    /// The sums over the files of `--sample` to extrapolate from.
+    sampled: Option<sample::Sums>,
+    /// The number of files measured into the indexes read by `--append-to`
+    /// or `merge-index`, which aren't in `files`.
+    indexed_files: u64,
+    /// The number and total size of the files skipped for being over
+    /// `--max-file-size`.
+    oversized_files: u64,
+    oversized_bytes: u64,
+    category_totals: BTreeMap<Category, CategoryTotals>,
+}
+
+/// Which custom sections to measure, with `--ignore-custom-section` and
+/// `--only-custom-section`.
+#[derive(Default)]
+struct CustomSectionFilter {
+    ignore: Vec<String>,
+    only: Vec<String>,
+}
+
+impl CustomSectionFilter {
+    fn includes(&self, name: &str) -> bool {
+        let matches = |pattern: &String| glob_matches(pattern.as_bytes(), name.as_bytes());
+        (self.only.is_empty() || self.only.iter().any(matches)) && !self.ignore.iter().any(matches)
+    }
+}
+
+/// Whether `name` matches `pattern`, in which `*` matches any run of bytes and
+/// `?` matches any one byte.
+fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| glob_matches(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_matches(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_matches(rest, &name[1..]),
+    }
+}
+
+/// Per-module state that we need to keep while walking a module's payloads.
+#[derive(Default)]
+struct ModuleState {
+    /// The number of parameters of each entry in the type section (zero for
+    /// non-function types).
+    type_params: Vec<u32>,
+    /// The type index of each function defined (not imported) by the module.
+    func_types: Vec<u32>,
+    /// The number of code section entries seen so far.
+    code_entries: usize,
+    /// The number of imported functions, with `--function-names`,
+    /// `--relocatable`, or `--canonical-abi-glue`.
+    imported_funcs: u32,
+    /// The hash and size of each code body seen so far, with
+    /// `--function-names` or `--canonical-abi-glue`.
+    body_hashes: Vec<WideHash>,
+    body_sizes: Vec<u64>,
+    /// Whether this is one of `wit-component`'s adapter modules, all of whose
+    /// functions are glue, with `--canonical-abi-glue`.
+    adapter: bool,
+    /// The indices of the functions exported under glue names, with
+    /// `--canonical-abi-glue`.
+    glue_funcs: HashSet<u32>,
+}
+
+impl ModuleState {
+    /// The number of parameters of the function whose body is the next code
+    /// section entry.
+    fn next_body_params(&self) -> u32 {
+        self.func_types
+            .get(self.code_entries)
+            .and_then(|ty| self.type_params.get(*ty as usize))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl Counts {
+    fn new(options: &Options) -> Result<Self> {
+        let hasher = Hasher::new(options);
+        Ok(Counts {
+            track_files_min_size: if options.delta
+                || options.similarity_matrix.is_some()
+                || options.dot.is_some()
+                || options.most_similar
+                || options.shared_module.is_some()
+                || options.shared_modules.is_some()
+                || options.output_db.is_some()
+                || options.group_by_dir.is_some()
+                || options.by_toolchain
+                || options.by_language
+                || matches!(options.command, Some(Command::Serve { .. }))
+            {
+                Some(0)
+            } else if options.who || matches!(options.command, Some(Command::Diff { .. })) {
+                Some(options.who_min_size)
+            } else {
+                None
+            },
+            offset_independent_data: options.offset_independent_data,
+            normalize_elem: options.normalize_elem,
+            code_normalizations: Self::code_normalizations(options),
+            need_types: options.sort_locals,
+            canonical_lebs: options.canonical_lebs,
+            relocatable: options.relocatable,
+            fuzzy_min_size: if options.fuzzy {
+                Some(options.fuzzy_min_size)
+            } else {
+                None
+            },
+            chunker: if options.cdc {
+                Some(cdc::Chunker::new(options.cdc_avg_size))
+            } else {
+                None
+            },
+            sub_segment_chunker: if options.sub_segments {
+                Some(cdc::Chunker::new(options.sub_segment_avg_size))
+            } else {
+                None
+            },
+            strings_min_size: options.strings.then_some(options.strings_min_size),
+            overlaps: if options.overlap {
+                [Category::DataSegments, Category::CodeBodies]
+                    .iter()
+                    .map(|category| (*category, overlap::Overlaps::new(options.overlap_min_size)))
+                    .collect()
+            } else {
+                BTreeMap::new()
+            },
+            memory_init: options.memory_init.then(memory_init::MemoryInit::default),
+            names: options.names,
+            function_names: options.function_names || options.by_crate || options.monomorphizations,
+            canonical_abi_glue: options.canonical_abi_glue,
+            http_split: options.http_split,
+            outliner: if options.outline {
+                Some(outline::Outliner::default())
+            } else {
+                None
+            },
+            ngrams: options.ngrams.map(ngrams::Ngrams::new),
+            compress: options.compressed,
+            dictionary_samples: if options.zstd_dict {
+                Some(compress::DictionarySamples::new(options.zstd_dict_size))
+            } else {
+                None
+            },
+            cache: Self::cache(options, &hasher),
+            hasher,
+            verify: options.verify,
+            min_size: options.min_size,
+            weights: options
+                .weights
+                .as_deref()
+                .map(weights::Weights::read)
+                .transpose()?,
+            by_toolchain: options.by_toolchain,
+            by_language: options.by_language,
+            by_kind: options.by_kind,
+            strip_debug: options.strip_debug,
+            preprocessor: options
+                .preprocess
+                .as_deref()
+                .map(preprocess::Preprocessor::new)
+                .transpose()?,
+            features: options
+                .features
+                .as_deref()
+                .map(features::parse)
+                .transpose()?,
+            custom_section_names: options.custom_section_names.then(BTreeMap::new),
+            elem_tables: options.elem_by_table.then(BTreeMap::new),
+            data_memories: options.data_by_memory.then(BTreeMap::new),
+            within_module_code: options.within_module_code.then(Vec::new),
+            section_sizes: options.section_sizes.then(Vec::new),
+            attribution: (options.treemap.is_some() || options.folded.is_some())
+                .then(attribution::Attribution::default),
+            find_embedded: options.embedded_modules,
+            offsets: options.emit_plan.is_some().then(HashMap::new),
+            body_names: (options.function_names
+                || options.by_crate
+                || options.monomorphizations
+                || options.relocatable)
+                .then(HashMap::new),
+            max_memory: options.max_memory,
+            custom_sections: CustomSectionFilter {
+                ignore: options.ignore_custom_section.clone(),
+                only: options.only_custom_section.clone(),
+            },
+            custom_key: options.custom_key,
+            extractors: Self::extractors(options),
+            ..Counts::default()
+        })
+    }
+
+    /// Counts for the first pass of `--low-memory`, which only adds every item
+    /// to the filter.
+    fn filling_filter(options: &Options) -> Result<Self> {
+        Ok(Counts {
+            track_files_min_size: None,
+            fuzzy_min_size: None,
+            custom_section_names: None,
+            elem_tables: None,
+            data_memories: None,
+            within_module_code: None,
+            section_sizes: None,
+            attribution: None,
+            against: None,
+            find_embedded: false,
+            offsets: None,
+            overlaps: BTreeMap::new(),
+            memory_init: None,
+            outliner: None,
+            ngrams: None,
+            compress: false,
+            dictionary_samples: None,
+            cache: None,
+            verify: false,
+            by_toolchain: false,
+            by_language: false,
+            filter: Some(filter::CountingFilter::new(options.filter_size)),
+            filling_filter: true,
+            ..Counts::new(options)?
+        })
+    }
+
+    /// Counts for the modules embedded in custom sections, with
+    /// `--embedded-modules`, which also count the modules embedded in those.
+    fn embedded(options: &Options) -> Result<Self> {
+        Ok(Counts {
+            track_files_min_size: None,
+            fuzzy_min_size: None,
+            custom_section_names: None,
+            elem_tables: None,
+            data_memories: None,
+            within_module_code: None,
+            section_sizes: None,
+            attribution: None,
+            against: None,
+            overlaps: BTreeMap::new(),
+            memory_init: None,
+            outliner: None,
+            ngrams: None,
+            compress: false,
+            dictionary_samples: None,
+            cache: None,
+            by_toolchain: false,
+            by_language: false,
+            preprocessor: None,
+            features: None,
+            offsets: None,
+            ..Counts::new(options)?
+        })
+    }
+
+    /// Counts for the base modules of `--against`, which only need the hash of
+    /// every strict item.
+    fn base(options: &Options) -> Result<Self> {
+        Ok(Counts {
+            hasher: Hasher::new(options),
+            strip_debug: options.strip_debug,
+            preprocessor: options
+                .preprocess
+                .as_deref()
+                .map(preprocess::Preprocessor::new)
+                .transpose()?,
+            custom_sections: CustomSectionFilter {
+                ignore: options.ignore_custom_section.clone(),
+                only: options.only_custom_section.clone(),
+            },
+            custom_key: options.custom_key,
+            extractors: Self::extractors(options),
+            ..Counts::default()
+        })
+    }
+
+    /// The extractors of the strict categories, with the custom sections and
+    /// key that the options pick.
+    fn extractors(options: &Options) -> extract::Extractors {
+        extract::builtin_with(extract::CustomSections {
+            key: options.custom_key,
+            filter: CustomSectionFilter {
+                ignore: options.ignore_custom_section.clone(),
+                only: options.only_custom_section.clone(),
+            },
+        })
+    }
+
+    /// Counts for the strict items of object files, for `--relocatable`.
+    fn objects(options: &Options) -> Self {
+        Counts {
+            hasher: Hasher::new(options),
+            min_size: options.min_size,
+            ..Counts::default()
+        }
+    }
+
+    /// The cache to use for `--cache`, unless an enabled analysis needs more
+    /// than the hash and size of every item, such as its contents.
+    fn cache(options: &Options, hasher: &Hasher) -> Option<cache::Cache> {
+        let dir = options.cache.as_ref()?;
+        if options.fuzzy
+            || options.outline
+            || options.overlap
+            || options.memory_init
+            || options.custom_section_names
+            || options.by_kind
+            || options.elem_by_table
+            || options.data_by_memory
+            || options.relocatable
+            || options.function_names
+            || options.canonical_abi_glue
+            || options.by_crate
+            || options.monomorphizations
+            || options.embedded_modules
+            || options.emit_plan.is_some()
+            || options.treemap.is_some()
+            || options.folded.is_some()
+            || options.ngrams.is_some()
+            || options.compressed
+            || options.zstd_dict
+            || options.verify
+        {
+            log::warn!(
+                "Not using the cache, since an enabled analysis needs more than item hashes"
+            );
+            return None;
+        }
+        // Everything that changes which items get added.
+        let fingerprint = format!(
+            "{} {} {} {} {} {} {:?} {:?} {:?} {} {} {:?} {:?} {:?} {:?}",
+            options.offset_independent_data,
+            options.normalize_elem,
+            options.structural_code,
+            options.sort_locals,
+            options.canonical_lebs,
+            options.mask_constants,
+            options.cdc.then_some(options.cdc_avg_size),
+            options.sub_segments.then_some(options.sub_segment_avg_size),
+            options.strings.then_some(options.strings_min_size),
+            options.names,
+            options.http_split,
+            options.hasher,
+            options.ignore_custom_section,
+            options.only_custom_section,
+            options.custom_key,
+        );
+        Some(cache::Cache::new(dir.clone(), fingerprint, hasher.bytes))
+    }
+
+    fn code_normalizations(options: &Options) -> Vec<(Category, normalize::CodeOptions)> {
+        let mut code_normalizations = vec![];
+        if options.structural_code {
+            code_normalizations.push((
+                Category::StructuralCodeBodies,
+                normalize::CodeOptions {
+                    indices: true,
+                    ..Default::default()
+                },
+            ));
+        }
+        if options.sort_locals {
+            code_normalizations.push((
+                Category::SortedLocalsCodeBodies,
+                normalize::CodeOptions {
+                    locals: true,
+                    ..Default::default()
+                },
+            ));
+        }
+        if options.canonical_lebs {
+            code_normalizations.push((
+                Category::CanonicalLebCodeBodies,
+                normalize::CodeOptions {
+                    lebs: true,
+                    ..Default::default()
+                },
+            ));
+        }
+        if options.mask_constants {
+            code_normalizations.push((
+                Category::ConstMaskedCodeBodies,
+                normalize::CodeOptions {
+                    constants: true,
+                    ..Default::default()
+                },
+            ));
+        }
+        code_normalizations
+    }
+
+    /// Add an item that is identified by its own bytes.
+    ///
+    /// Returns whether this is the first time the item was seen.
+    fn add_entry(&mut self, category: Category, data: &[u8]) -> Result<bool> {
+        let strict = Category::ALL.contains(&category);
+        let compress = self.compress && strict;
+        let hash = self.key_hash(data);
+        if let (Some(offsets), true) = (&mut self.offsets, strict) {
+            let offset = data.as_ptr() as usize - self.module_start;
+            offsets
+                .entry((category, hash.clone()))
+                .or_default()
+                .push((self.files.len() - 1, offset));
+            self.tracking_bytes += std::mem::size_of::<(usize, usize)>() as u64;
+        }
+        let entry = self.add_hashed_key(category, hash, data, data.len() as u64);
+        // Compress each duplicated item once, when its second copy shows up.
+        if compress && entry.count == 2 {
+            entry.compressed = Some(compress::sizes(data)?);
+        }
+        let first = entry.count == 1;
+        if first && strict {
+            if let Some(samples) = &mut self.dictionary_samples {
+                samples.add(data);
+            }
+        }
+        Ok(first)
+    }
+
+    /// Add the first occurrence of an item to the `--fuzzy` index of its
+    /// category.
+    fn add_near_duplicate(&mut self, category: Category, data: &[u8]) {
+        if self
+            .fuzzy_min_size
+            .map_or(false, |min| data.len() as u64 >= min)
+        {
+            let file = self.files.len() - 1;
+            self.near_duplicates
+                .entry(category)
+                .or_default()
+                .add(data, file);
+        }
+    }
+
+    /// Use the `name` section `data` of the module with `state` to name code
+    /// bodies and find canonical ABI glue, when enabled.
+    fn add_names(&mut self, state: &ModuleState, data: &[u8]) -> Result<()> {
+        if self.function_names {
+            self.add_function_names(state, data)?;
+        }
+        if self.canonical_abi_glue && !state.adapter {
+            self.add_glue_names(state, data)?;
+        }
+        Ok(())
+    }
+
+    /// Add the code bodies of the functions that the `name` section `data`
+    /// names like glue, and that weren't already exported under glue names,
+    /// as canonical ABI glue.
+    fn add_glue_names(&mut self, state: &ModuleState, data: &[u8]) -> Result<()> {
+        for (index, name) in names::function_names(data)? {
+            if state.glue_funcs.contains(&index) || !names::is_canonical_abi_glue(name) {
+                continue;
+            }
+            let body = match index.checked_sub(state.imported_funcs) {
+                Some(body) => body as usize,
+                None => continue,
+            };
+            if let (Some(hash), Some(size)) =
+                (state.body_hashes.get(body), state.body_sizes.get(body))
+            {
+                self.add_hashed_entry(Category::CanonicalAbiGlue, hash.clone(), *size);
+            }
+        }
+        Ok(())
+    }
+
+    /// Name the code bodies of the module whose state is `state` after the
+    /// functions that the `name` section `data` names.
+    fn add_function_names(&mut self, state: &ModuleState, data: &[u8]) -> Result<()> {
+        let (body_names, bodies) =
+            match (&mut self.body_names, self.items.get(&Category::CodeBodies)) {
+                (Some(body_names), Some(bodies)) => (body_names, bodies),
+                _ => return Ok(()),
+            };
+        for (index, name) in names::function_names(data)? {
+            let hash = index
+                .checked_sub(state.imported_funcs)
+                .and_then(|body| state.body_hashes.get(body as usize));
+            // Only name the bodies that are tracked, and not the unique ones
+            // that `--low-memory` leaves out.
+            if let Some(hash) = hash.filter(|hash| bodies.contains_key(*hash)) {
+                body_names
+                    .entry((Category::CodeBodies, hash.clone()))
+                    .or_insert_with(|| names::demangle(name).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The name of the code body of `category` with `hash`, if it has one.
+    fn name(&self, category: Category, hash: &WideHash) -> Option<&str> {
+        self.body_names
+            .as_ref()?
+            .get(&(category, hash.clone()))
+            .map(|name| &**name)
+    }
+
+    /// Add an item of `size` bytes that is identified by `key` rather than by
+    /// its own bytes.
+    ///
+    /// Returns the item's entry.
+    fn add_keyed_entry(&mut self, category: Category, key: &[u8], size: u64) -> &mut SizeEntry {
+        let hash = self.key_hash(key);
+        self.add_hashed_key(category, hash, key, size)
+    }
+
+    /// The hash of `key`, unless it was already hashed in parallel.
+    fn key_hash(&self, key: &[u8]) -> WideHash {
+        self.prehashed
+            .get(&(key.as_ptr() as usize, key.len()))
+            .cloned()
+            .unwrap_or_else(|| hash(key, &self.hasher))
+    }
+
+    /// Like `add_keyed_entry`, with `key` already hashed to `hash`.
+    fn add_hashed_key(
+        &mut self,
+        category: Category,
+        hash: WideHash,
+        key: &[u8],
+        size: u64,
+    ) -> &mut SizeEntry {
+        let verify = self.verify;
+        if verify && self.stores_new_key(category, &hash) {
+            self.key_bytes += key.len() as u64;
+        }
+        let entry = self.add_hashed_entry(category, hash, size);
+        if verify {
+            match &entry.key {
+                None => entry.key = Some(key.into()),
+                Some(first) if **first != *key => {
+                    log::warn!(
+                        "Hash collision between two {} of {} bytes",
+                        category.name(),
+                        size
+                    );
+                    entry.collisions += 1;
+                }
+                Some(_) => {}
+            }
+        }
+        entry
+    }
+
+    /// Whether adding an item whose key hashes to `hash` keeps its key: it's
+    /// the first one with that hash, and `--low-memory` doesn't leave it
+    /// untracked.
+    fn stores_new_key(&self, category: Category, hash: &WideHash) -> bool {
+        let hash = shorten_hash(hash.clone(), self.hasher.bytes);
+        let untracked = self.filter.as_ref().map_or(false, |filter| {
+            self.filling_filter || !filter.maybe_duplicated(category, &hash)
+        });
+        !untracked
+            && self
+                .items
+                .get(&category)
+                .and_then(|items| items.get(&hash))
+                .map_or(true, |entry| entry.key.is_none())
+    }
+
+    /// Add an item of `size` bytes whose key hashes to `hash`.
+    ///
+    /// Returns the item's entry.
+    fn add_hashed_entry(
+        &mut self,
+        category: Category,
+        hash: WideHash,
+        size: u64,
+    ) -> &mut SizeEntry {
+        // Replayed from the cache, or hashed before `--max-memory` shortened
+        // the hashes.
+        let hash = shorten_hash(hash, self.hasher.bytes);
+        if let Some(recording) = &mut self.recording {
+            recording.push((category, hash.clone(), size));
+        }
+        let totals = self.category_totals.entry(category).or_default();
+        totals.size += size;
+        totals.items += 1;
+        let module = self.files.len() - 1;
+        if let Some(against) = &mut self.against {
+            if Category::ALL.contains(&category) && size >= self.min_size {
+                against.add(module, category, &hash, size);
+            }
+        }
+        if let Some(filter) = &mut self.filter {
+            if self.filling_filter {
+                filter.add(category, &hash);
+            }
+            if self.filling_filter || !filter.maybe_duplicated(category, &hash) {
+                // Make it look like the first and only occurrence of a unique
+                // item.
+                totals.unique += 1;
+                self.untracked = SizeEntry {
+                    size,
+                    count: 1,
+                    modules: 1,
+                    last_module: module,
+                    ..SizeEntry::default()
+                };
+                return &mut self.untracked;
+            }
+        }
+        let track_files = self.track_files_min_size.map_or(false, |min| size >= min);
+        // Custom sections are attributed by name instead.
+        let attributed_hash = (self.attribution.is_some()
+            && Category::ALL.contains(&category)
+            && category != Category::CustomSections
+            && size >= self.min_size)
+            .then(|| hash.clone());
+        if self.weights.is_some() && Category::ALL.contains(&category) && size >= self.min_size {
+            let copies = self
+                .weighted
+                .copies
+                .entry((category, hash.clone()))
+                .or_insert(Copies {
+                    size,
+                    total: 0,
+                    max: 0,
+                });
+            copies.total += self.weight;
+            copies.max = copies.max.max(self.weight);
+        }
+        let entry = self
+            .items
+            .entry(category)
+            .or_default()
+            .entry(hash)
+            .or_insert_with(|| SizeEntry {
+                size,
+                count: 0,
+                modules: 0,
+                indexed: 0,
+                last_module: module,
+                files: vec![],
+                compressed: None,
+                key: None,
+                collisions: 0,
+            });
+        entry.count += 1;
+        if entry.count > 1 {
+            *self.file_duplicated.entry(category).or_insert(0) += size;
+            if Category::ALL.contains(&category)
+                && size >= self.min_size
+                && entry.last_module == module
+            {
+                self.file_within_modules += size;
+            }
+            if let Some(files) = &mut self.section_sizes {
+                if Category::ALL.contains(&category) && size >= self.min_size {
+                    *files[module].duplicated.entry(category).or_insert(0) += size;
+                }
+            }
+            if let Some(within) = &mut self.within_module_code {
+                if category == Category::CodeBodies
+                    && entry.last_module == module
+                    && size >= self.min_size
+                {
+                    match within.last_mut() {
+                        Some(last) if last.file == module => {
+                            last.bodies += 1;
+                            last.size += size;
+                        }
+                        _ => within.push(WithinModuleCode {
+                            file: module,
+                            bodies: 1,
+                            size,
+                        }),
+                    }
+                }
+            }
+            if let (Some(attribution), Some(hash)) = (&mut self.attribution, attributed_hash) {
+                let group = attribution::Group::File(module);
+                attribution.add(category, group, attribution::Item::Hash(hash), size);
+            }
+        } else {
+            totals.unique += 1;
+        }
+        if entry.modules == 0 || entry.last_module != module {
+            entry.modules += 1;
+            entry.last_module = module;
+            if track_files {
+                entry.files.push(module);
+                self.tracking_bytes += std::mem::size_of::<usize>() as u64;
+            }
+        }
+        entry
+    }
+
+    fn add_module(&mut self, path: &Path, full_wasm: &[u8]) {
+        let file = self.files.len();
+        self.file_duplicated.clear();
+        self.file_within_modules = 0;
+        self.files.push(path.to_path_buf());
+        self.file_sizes.push(full_wasm.len() as u64);
+        self.modules
+            .entry(hash(full_wasm, &self.hasher))
+            .or_insert_with(|| ModuleEntry {
+                size: full_wasm.len() as u64,
+                files: vec![],
+            })
+            .files
+            .push(file);
+    }
+
+    /// Apply `--preprocess` and then `--strip-debug` to `wasm`, as every file
+    /// is before it's measured.
+    fn transform<'a>(&self, wasm: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let mut wasm = Cow::Borrowed(wasm);
+        if let Some(preprocessor) = &self.preprocessor {
+            wasm = Cow::Owned(preprocessor.run(&wasm)?);
+        }
+        if self.strip_debug {
+            wasm = Cow::Owned(strip::debug(&wasm)?);
+        }
+        Ok(wasm)
+    }
+
+    /// Read the file at `path` into `buf` again, as it was measured, for the
+    /// analyses that go back to the files after measuring them.
+    fn load(&self, path: &Path, buf: &mut Vec<u8>) -> Result<()> {
+        read_file(path, buf)?;
+        if let Cow::Owned(transformed) = self.transform(buf)? {
+            *buf = transformed;
+        }
+        Ok(())
+    }
+
+    fn add_wasm(&mut self, path: &Path, full_wasm: &[u8]) -> Result<()> {
+        let transformed = self.transform(full_wasm)?;
+        let full_wasm = &transformed[..];
+        if let Some(features) = self.features {
+            let mut validator = wasmparser::Validator::new();
+            validator.wasm_features(features);
+            if let Err(e) = validator.validate_all(full_wasm) {
+                log::warn!("Skipping invalid file {}: {}", path.display(), e);
+                self.skipped.push(path.to_path_buf());
+                return Ok(());
+            }
+        }
+        self.total_size += full_wasm.len() as u64;
+        // Offsets in the plan are within the whole file, even for the items of
+        // the core modules nested in a component.
+        self.module_start = full_wasm.as_ptr() as usize;
+        if let Some(weights) = &self.weights {
+            self.weight = weights.weight(path).unwrap_or_else(|| {
+                self.weighted.unweighted_files += 1;
+                0
+            });
+            self.weighted.size += full_wasm.len() as u64 * self.weight;
+        }
+        self.add_module(path, full_wasm);
+        if let Some(files) = &mut self.section_sizes {
+            files.push(FileSections {
+                sections: sections::sizes(full_wasm)?,
+                duplicated: BTreeMap::new(),
+            });
+        }
+        if self.by_toolchain {
+            self.toolchains.push(producers::toolchain(full_wasm));
+        }
+        if self.by_language {
+            self.languages.push(language::language(full_wasm));
+        }
+
+        if self.compress {
+            let sizes = compress::sizes(full_wasm)?;
+            for (total, size) in self.compressed_size.iter_mut().zip(sizes) {
+                *total += size;
+            }
+        }
+
+        let cache_key = self.cache.as_ref().map(|cache| cache.key(full_wasm));
+        if let (Some(cache), Some(cache_key)) = (&self.cache, &cache_key) {
+            if let Some(entries) = cache.get(cache_key)? {
+                for (category, hash, size) in entries {
+                    self.add_hashed_entry(category, hash, size);
+                }
+                return Ok(());
+            }
+            self.recording = Some(vec![]);
+        }
+
+        if let Some(chunker) = &self.chunker {
+            for chunk in chunker.split(full_wasm) {
+                self.add_entry(Category::FileChunks, chunk)?;
+            }
+        }
+        if self.http_split {
+            let resources = split::resources(full_wasm)?;
+            let split = [
+                (Category::CodeResources, resources.code),
+                (Category::DataResources, resources.data),
+                (Category::DebugResources, resources.debug),
+                (Category::OtherResources, resources.rest),
+            ];
+            for (category, bytes) in split {
+                if !bytes.is_empty() {
+                    self.add_entry(category, &bytes)?;
+                }
+            }
+        }
+        self.add_items(full_wasm)?;
+
+        if let (Some(cache), Some(cache_key)) = (&self.cache, &cache_key) {
+            let entries = self.recording.take().unwrap_or_default();
+            cache.put(cache_key, &entries)?;
+        }
+
+        for range in std::mem::take(&mut self.embedded_ranges) {
+            let module = &full_wasm[range];
+            match &mut self.embedded {
+                Some(embedded) => embedded.add_wasm(path, module)?,
+                None => {
+                    // The module's bytes are already part of the total size,
+                    // as those of the custom section containing it.
+                    let total_size = self.total_size;
+                    self.add_wasm(path, module)?;
+                    self.total_size = total_size;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hash the strict items that `extractors` find in the module `full_wasm`
+    /// in parallel, into `prehashed`.
+    fn prehash(&mut self, full_wasm: &[u8], extractors: &mut extract::Extractors) -> Result<()> {
+        let mut items = vec![];
+        for payload in wasmparser::Parser::new(0).parse_all(full_wasm) {
+            let payload = payload?;
+            for (_, extractor) in extractors.iter_mut() {
+                extractor.extract(full_wasm, &payload, &mut items)?;
+            }
+        }
+        let hasher = &self.hasher;
+        self.prehashed = items
+            .par_iter()
+            .filter_map(|item| match item.key {
+                Cow::Borrowed(key) => Some(key),
+                Cow::Owned(_) => None,
+            })
+            .map(|key| ((key.as_ptr() as usize, key.len()), hash(key, hasher)))
+            .collect();
+        Ok(())
+    }
+
+    /// Add the items of the module `full_wasm`, and of any modules nested
+    /// within it.
+    fn add_items(&mut self, full_wasm: &[u8]) -> Result<()> {
+        if component::is_component(full_wasm) {
+            return self.add_component(full_wasm);
+        }
+        // The extractors are taken out while they run, since adding the items
+        // they find needs the rest of `self`.
+        let mut extractors = std::mem::take(&mut self.extractors);
+        let mut result = Ok(());
+        if full_wasm.len() >= PREHASH_MIN_SIZE {
+            result = self.prehash(full_wasm, &mut extractors);
+        }
+        if result.is_ok() {
+            result = self.add_items_sequentially(full_wasm, &mut extractors);
+        }
+        self.extractors = extractors;
+        self.prehashed.clear();
+        result
+    }
+
+    /// Add the items of the component `wasm`: those of its core modules and
+    /// nested components, and its interface definitions.
+    fn add_component(&mut self, wasm: &[u8]) -> Result<()> {
+        for (id, range) in component::sections(wasm)? {
+            let contents = &wasm[range];
+            match id {
+                component::CORE_MODULE => self.add_items(contents)?,
+                component::COMPONENT => self.add_component(contents)?,
+                component::CUSTOM => {
+                    let (name, data) = component::custom_section(contents)?;
+                    if self.custom_sections.includes(name) {
+                        self.add_entry(Category::CustomSections, data)?;
+                    }
+                }
+                component::CORE_TYPE | component::TYPE => {
+                    self.add_entry(Category::ComponentTypes, contents)?;
+                }
+                component::CORE_INSTANCE | component::INSTANCE => {
+                    self.add_entry(Category::ComponentInstances, contents)?;
+                }
+                component::ALIAS => {
+                    self.add_entry(Category::ComponentAliases, contents)?;
+                }
+                component::CANON => {
+                    self.add_entry(Category::CanonicalFunctions, contents)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn add_items_sequentially(
+        &mut self,
+        full_wasm: &[u8],
+        extractors: &mut extract::Extractors,
+    ) -> Result<()> {
+        let object = if self.relocatable {
+            reloc::Object::parse(full_wasm).unwrap_or_else(|e| {
+                log::warn!(
+                    "Measuring {} without masking relocations, which failed to parse: {:#}",
+                    self.files.last().unwrap().display(),
+                    e
+                );
+                None
+            })
+        } else {
+            None
+        };
+        if let (Some(_), Some(objects)) = (&object, &mut self.objects) {
+            objects.total_size += full_wasm.len() as u64;
+            objects.add_module(self.files.last().unwrap(), full_wasm);
+        }
+        let mut key = vec![];
+        let mut items = vec![];
+        let mut firsts = vec![];
+        let mut input = full_wasm;
+        let mut parsers = vec![wasmparser::Parser::new(0)];
+        let mut states = vec![ModuleState::default()];
+        while !parsers.is_empty() {
+            let (payload, consumed) = match parsers
+                .last_mut()
+                .unwrap()
+                .parse(input, true)
+                .context("failed to parse Wasm")?
+            {
+                wasmparser::Chunk::NeedMoreData(_) => unreachable!(),
+                wasmparser::Chunk::Parsed { consumed, payload } => (payload, consumed),
+            };
+            input = &input[consumed..];
+
+            // The strict items of the payload, and whether each was the first
+            // of its kind, for the analyses of first occurrences below, which
+            // visit them in the same order.
+            firsts.clear();
+            for (category, extractor) in extractors.iter_mut() {
+                extractor.extract(full_wasm, &payload, &mut items)?;
+                for item in items.drain(..) {
+                    firsts.push(self.add_entry(*category, &item.key)?);
+                }
+            }
+            let mut firsts = firsts.iter().copied();
+
+            match payload {
+                wasmparser::Payload::TypeSection(mut reader) if self.need_types => {
+                    let state = states.last_mut().unwrap();
+                    for _ in 0..reader.get_count() {
+                        state.type_params.push(match reader.read()? {
+                            wasmparser::TypeDef::Func(ty) => ty.params.len() as u32,
+                            _ => 0,
+                        });
+                    }
+                }
+                wasmparser::Payload::ImportSection(mut reader)
+                    if self.names
+                        || self.function_names
+                        || self.canonical_abi_glue
+                        || object.is_some() =>
+                {
+                    for _ in 0..reader.get_count() {
+                        let import = reader.read()?;
+                        let state = states.last_mut().unwrap();
+                        match import.ty {
+                            wasmparser::ImportSectionEntryType::Function(_) => {
+                                state.imported_funcs += 1;
+                            }
+                            wasmparser::ImportSectionEntryType::Table(_)
+                                if import.field == Some("$imports") =>
+                            {
+                                state.adapter = true;
+                            }
+                            _ => {}
+                        }
+                        if self.names {
+                            self.add_entry(Category::ImportModuleNames, import.module.as_bytes())?;
+                            if let Some(field) = import.field {
+                                self.add_entry(Category::ImportFieldNames, field.as_bytes())?;
+                            }
+                        }
+                    }
+                }
+                wasmparser::Payload::ExportSection(mut reader)
+                    if self.names || self.canonical_abi_glue =>
+                {
+                    for _ in 0..reader.get_count() {
+                        let export = reader.read()?;
+                        if self.canonical_abi_glue {
+                            let state = states.last_mut().unwrap();
+                            if export.field == "$imports" || export.field == "cabi_import_realloc" {
+                                state.adapter = true;
+                            } else if export.kind == wasmparser::ExternalKind::Function
+                                && names::is_canonical_abi_glue(export.field)
+                            {
+                                state.glue_funcs.insert(export.index);
+                            }
+                        }
+                        if self.names {
+                            self.add_entry(Category::ExportNames, export.field.as_bytes())?;
+                        }
+                    }
+                }
+                wasmparser::Payload::FunctionSection(mut reader) if self.need_types => {
+                    let state = states.last_mut().unwrap();
+                    for _ in 0..reader.get_count() {
+                        state.func_types.push(reader.read()?);
+                    }
+                }
+                wasmparser::Payload::DataSection(mut reader) => {
+                    for _ in 0..reader.get_count() {
+                        let data = reader.read()?;
+                        let bytes = &full_wasm[data.range.start..data.range.end];
+                        if self.by_kind {
+                            match &data.kind {
+                                wasmparser::DataKind::Active { init_expr, .. } => {
+                                    self.add_entry(Category::ActiveDataSegments, bytes)?;
+                                    if is_constant(init_expr)? {
+                                        self.add_entry(Category::ConstOffsetDataSegments, bytes)?;
+                                    }
+                                }
+                                wasmparser::DataKind::Passive => {
+                                    self.add_entry(Category::PassiveDataSegments, bytes)?;
+                                }
+                            }
+                        }
+                        let first = firsts.next() == Some(true);
+                        if first {
+                            self.add_near_duplicate(Category::DataSegments, bytes);
+                            if let Some(chunker) = &self.sub_segment_chunker {
+                                for chunk in chunker.split(data.data) {
+                                    self.add_entry(Category::DataSubSegments, chunk)?;
+                                }
+                            }
+                            if let Some(overlaps) = self.overlaps.get_mut(&Category::DataSegments) {
+                                overlaps.add(data.data);
+                            }
+                            if let Some(memory_init) = &mut self.memory_init {
+                                memory_init.add(
+                                    hash(bytes, &self.hasher),
+                                    bytes,
+                                    data.data.len(),
+                                )?;
+                            }
+                            if let Some(min_size) = self.strings_min_size {
+                                for string in strings::split(data.data, min_size) {
+                                    self.add_entry(Category::DataStrings, string)?;
+                                }
+                            }
+                        }
+                        if let Some(memories) = &mut self.data_memories {
+                            let memory = match data.kind {
+                                wasmparser::DataKind::Active { memory_index, .. } => {
+                                    format!("memory {}", memory_index)
+                                }
+                                wasmparser::DataKind::Passive => "passive".to_string(),
+                            };
+                            memories
+                                .entry(memory)
+                                .or_default()
+                                .add(bytes.len() as u64, first);
+                        }
+                        if let Some(object) = &object {
+                            if let Some(objects) = &mut self.objects {
+                                objects.add_entry(Category::DataSegments, bytes)?;
+                            }
+                            key.clear();
+                            object.key(full_wasm, data.range.start..data.range.end, &mut key);
+                            self.add_keyed_entry(
+                                Category::RelocDataSegments,
+                                &key,
+                                bytes.len() as u64,
+                            );
+                        }
+                        if self.offset_independent_data {
+                            self.add_entry(Category::DataPayloads, data.data)?;
+                        }
+                        if self.canonical_lebs {
+                            key.clear();
+                            normalize::data_segment(&data, &mut key)?;
+                            self.add_keyed_entry(
+                                Category::CanonicalLebDataSegments,
+                                &key,
+                                (data.range.end - data.range.start) as u64,
+                            );
+                        }
+                    }
+                }
+                wasmparser::Payload::ElementSection(mut reader) => {
+                    for _ in 0..reader.get_count() {
+                        let elem = reader.read()?;
+                        let bytes = &full_wasm[elem.range.start..elem.range.end];
+                        let first = firsts.next() == Some(true);
+                        if first {
+                            self.add_near_duplicate(Category::ElemSegments, bytes);
+                        }
+                        if self.by_kind {
+                            let kind = match elem.kind {
+                                wasmparser::ElementKind::Active { .. } => {
+                                    Category::ActiveElemSegments
+                                }
+                                wasmparser::ElementKind::Passive => Category::PassiveElemSegments,
+                                wasmparser::ElementKind::Declared => Category::DeclaredElemSegments,
+                            };
+                            self.add_entry(kind, bytes)?;
+                        }
+                        if let Some(tables) = &mut self.elem_tables {
+                            let table = match elem.kind {
+                                wasmparser::ElementKind::Active { table_index, .. } => {
+                                    format!("table {}", table_index)
+                                }
+                                wasmparser::ElementKind::Passive => "passive".to_string(),
+                                wasmparser::ElementKind::Declared => "declared".to_string(),
+                            };
+                            tables
+                                .entry(table)
+                                .or_default()
+                                .add(bytes.len() as u64, first);
+                        }
+                        if self.normalize_elem {
+                            key.clear();
+                            normalize::elem_segment(&elem, true, &mut key)?;
+                            self.add_keyed_entry(
+                                Category::RemappedElemSegments,
+                                &key,
+                                (elem.range.end - elem.range.start) as u64,
+                            );
+                        }
+                        if self.canonical_lebs {
+                            key.clear();
+                            normalize::elem_segment(&elem, false, &mut key)?;
+                            self.add_keyed_entry(
+                                Category::CanonicalLebElemSegments,
+                                &key,
+                                (elem.range.end - elem.range.start) as u64,
+                            );
+                        }
+                    }
+                }
+                wasmparser::Payload::CodeSectionEntry(body) => {
+                    let range = body.range();
+                    let bytes = &full_wasm[range.start..range.end];
+                    if firsts.next() == Some(true) {
+                        self.add_near_duplicate(Category::CodeBodies, bytes);
+                        if let Some(outliner) = &mut self.outliner {
+                            outliner.add_body(full_wasm, &body)?;
+                        }
+                        if let Some(overlaps) = self.overlaps.get_mut(&Category::CodeBodies) {
+                            overlaps.add(bytes);
+                        }
+                    }
+                    if let Some(ngrams) = &mut self.ngrams {
+                        ngrams.add_body(full_wasm, &body)?;
+                    }
+                    let params = states.last().unwrap().next_body_params();
+                    for i in 0..self.code_normalizations.len() {
+                        let (category, options) = self.code_normalizations[i];
+                        key.clear();
+                        normalize::code_body(full_wasm, &body, params, options, &mut key)?;
+                        self.add_keyed_entry(category, &key, (range.end - range.start) as u64);
+                    }
+                    if let Some(object) = &object {
+                        if let Some(objects) = &mut self.objects {
+                            objects.add_entry(Category::CodeBodies, bytes)?;
+                        }
+                        key.clear();
+                        object.key(full_wasm, range.start..range.end, &mut key);
+                        let state = states.last().unwrap();
+                        let index = state.imported_funcs + state.code_entries as u32;
+                        let hash = self.key_hash(&key);
+                        if let (Some(body_names), Some(name)) =
+                            (&mut self.body_names, object.function_name(index))
+                        {
+                            body_names
+                                .entry((Category::RelocCodeBodies, hash.clone()))
+                                .or_insert_with(|| names::demangle(name).into());
+                        }
+                        self.add_hashed_key(
+                            Category::RelocCodeBodies,
+                            hash,
+                            &key,
+                            (range.end - range.start) as u64,
+                        );
+                    }
+                    if self.canonical_abi_glue {
+                        let state = states.last().unwrap();
+                        let index = state.imported_funcs + state.code_entries as u32;
+                        if state.adapter || state.glue_funcs.contains(&index) {
+                            self.add_entry(Category::CanonicalAbiGlue, bytes)?;
+                        }
+                    }
+                    let state = states.last_mut().unwrap();
+                    state.code_entries += 1;
+                    if self.function_names || self.canonical_abi_glue {
+                        state.body_hashes.push(hash(bytes, &self.hasher));
+                        state.body_sizes.push(bytes.len() as u64);
+                    }
+                }
+                wasmparser::Payload::CustomSection {
+                    name,
+                    data_offset,
+                    data,
+                    range,
+                } if self.custom_sections.includes(name) => {
+                    let key = self.custom_key.key(full_wasm, &range, data);
+                    let first = firsts.next() == Some(true);
+                    if self.find_embedded {
+                        self.embedded_ranges.extend(
+                            embedded::modules(data)
+                                .into_iter()
+                                .map(|range| data_offset + range.start..data_offset + range.end),
+                        );
+                    }
+                    if let Some(names) = &mut self.custom_section_names {
+                        names
+                            .entry(name.to_string())
+                            .or_default()
+                            .add(key.len() as u64, first);
+                    }
+                    if let Some(attribution) = &mut self.attribution {
+                        let size = key.len() as u64;
+                        if !first && size >= self.min_size {
+                            let group = attribution::Group::CustomSection(name.to_string());
+                            let item = attribution::Item::File(self.files.len() - 1);
+                            attribution.add(Category::CustomSections, group, item, size);
+                        }
+                    }
+                    if source_map::is_source_map(name, data) {
+                        self.add_entry(Category::SourceMaps, data)?;
+                    }
+                    if name == "name" {
+                        self.add_names(states.last().unwrap(), data)?;
+                    }
+                }
+                // The names of functions are still wanted when the `name`
+                // section itself isn't measured.
+                wasmparser::Payload::CustomSection { name, data, .. }
+                    if name == "name" && (self.function_names || self.canonical_abi_glue) =>
+                {
+                    self.add_names(states.last().unwrap(), data)?;
+                }
+                wasmparser::Payload::ModuleSectionEntry { parser, range } => {
+                    self.add_entry(Category::NestedModules, &full_wasm[range.start..range.end])?;
+                    parsers.push(parser);
+                    states.push(ModuleState::default());
+                }
+                wasmparser::Payload::End => {
+                    parsers.pop();
+                    states.pop();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The items of `category` of at least `--min-size` bytes.
+    fn sized_items(&self, category: Category) -> impl Iterator<Item = &SizeEntry> {
+        self.items
+            .get(&category)
+            .into_iter()
+            .flat_map(|m| m.values())
+            .filter(move |entry| entry.size >= self.min_size)
+    }
+
+    /// The redundant bytes of the items of `category` that are too small for
+    /// `--min-size`, and so are left out of `duplicated`.
+    fn duplicated_below_min_size(&self, category: Category) -> u64 {
+        self.items
+            .get(&category)
+            .into_iter()
+            .flat_map(|m| m.values())
+            .filter(|entry| entry.size < self.min_size)
+            .map(|entry| entry.size * (entry.count - 1))
+            .sum()
+    }
+
+    fn duplicated(&self, category: Category) -> Duplication {
+        let mut dupe = Duplication::default();
+        for entry in self.sized_items(category) {
+            dupe.within_modules += entry.size * (entry.count - entry.modules);
+            dupe.across_modules += entry.size * (entry.modules - 1);
+        }
+        dupe
+    }
+
+    /// Like `duplicated`, but counting the size of each duplicated item after
+    /// compressing it with the `codec`th entry of `compress::Codec::ALL`.
+    fn duplicated_compressed(&self, category: Category, codec: usize) -> Duplication {
+        let mut dupe = Duplication::default();
+        for entry in self.sized_items(category) {
+            if let Some(compressed) = entry.compressed {
+                let size = compressed[codec];
+                dupe.within_modules += size * (entry.count - entry.modules);
+                dupe.across_modules += size * (entry.modules - 1);
+            }
+        }
+        dupe
+    }
+
+    /// Get the duplicated items of the given category that are at least
+    /// `min_size` bytes large, most redundant bytes first.
+    fn duplicated_items(&self, category: Category, min_size: u64) -> Vec<(&WideHash, &SizeEntry)> {
+        let mut dupes: Vec<_> = self
+            .items
+            .get(&category)
+            .into_iter()
+            .flat_map(|m| m.iter())
+            .filter(|(_, entry)| entry.count > 1 && entry.size >= min_size.max(self.min_size))
+            .collect();
+        dupes.sort_by(most_redundant_first);
+        dupes
+    }
+
+    /// Get every item of `category`, in the same order as `duplicated_items`,
+    /// so that whatever lists items is the same from one run to the next.
+    fn sorted_items(&self, category: Category) -> Vec<(&WideHash, &SizeEntry)> {
+        let mut items: Vec<_> = self
+            .items
+            .get(&category)
+            .into_iter()
+            .flat_map(|m| m.iter())
+            .collect();
+        items.sort_by(most_redundant_first);
+        items
+    }
+
+    /// Get the number of bytes of strict items that each pair of modules has
+    /// in common, keyed by the modules' indices into `files`, smaller first.
+    ///
+    /// Only counts items whose files are tracked.
+    fn shared_bytes(&self, max_files: usize) -> HashMap<(usize, usize), u64> {
+        let mut shared = HashMap::new();
+        for category in Category::ALL {
+            for entry in self
+                .items
+                .get(category)
+                .into_iter()
+                .flat_map(|m| m.values())
+            {
+                if entry.files.len() > max_files {
+                    continue;
+                }
+                for (i, a) in entry.files.iter().enumerate() {
+                    for b in &entry.files[i + 1..] {
+                        *shared.entry((*a, *b)).or_insert(0) += entry.size;
+                    }
+                }
+            }
+        }
+        shared
+    }
+
+    /// Estimate the memory used by the item maps.
+    fn estimated_memory(&self) -> u64 {
+        // Each entry of a hash map also has a control byte, and the maps are
+        // at most 7/8 full.
+        let per_item = (std::mem::size_of::<WideHash>() + std::mem::size_of::<SizeEntry>() + 1) * 8
+            / 7
+            + self.hasher.bytes;
+        let items: usize = self.items.values().map(HashMap::len).sum();
+        items as u64 * per_item as u64 + self.key_bytes + self.tracking_bytes
+    }
+
+    /// Drop the next detail kept about every item, if the item maps use over
+    /// 90% of `--max-memory`, leaving some room for everything else.
+    fn check_memory(&mut self) {
+        let max = match self.max_memory {
+            Some(max) => max,
+            None => return,
+        };
+        if self.estimated_memory() < max / 10 * 9 {
+            return;
+        }
+        let entries = self.items.values_mut().flat_map(|items| items.values_mut());
+        let degradation = if self.verify {
+            self.verify = false;
+            entries.for_each(|entry| entry.key = None);
+            self.key_bytes = 0;
+            budget::Degradation::VerifyKeys
+        } else if self.track_files_min_size.is_some() || self.offsets.is_some() {
+            self.track_files_min_size = None;
+            self.offsets = None;
+            entries.for_each(|entry| entry.files = vec![]);
+            self.tracking_bytes = 0;
+            budget::Degradation::ItemFiles
+        } else if self.hasher.bytes > SHORT_HASH_BYTES {
+            self.shorten_hashes(SHORT_HASH_BYTES);
+            budget::Degradation::ShortHashes
+        } else if self.degradations.last().map(|(last, _)| *last)
+            != Some(budget::Degradation::OverBudget)
+        {
+            budget::Degradation::OverBudget
+        } else {
+            return;
+        };
+        log::warn!("Over 90% of --max-memory: {}", degradation.describe());
+        self.degradations.push((degradation, self.files.len()));
+    }
+
+    /// Shorten every hash to `bytes` bytes, and hash everything added from now
+    /// on to as many. Items whose hashes then collide are merged, as if they
+    /// were copies of the same item.
+    fn shorten_hashes(&mut self, bytes: usize) {
+        self.hasher.bytes = bytes;
+        // Cached entries would have the shorter hashes in later runs too.
+        self.cache = None;
+        self.recording = None;
+        for (category, items) in &mut self.items {
+            let mut merged = 0;
+            shorten_keys(
+                items,
+                |hash| shorten_hash(hash, bytes),
+                |entry, other| {
+                    merged += 1;
+                    entry.count += other.count;
+                    entry.modules += other.modules;
+                    entry.indexed += other.indexed;
+                    entry.last_module = entry.last_module.max(other.last_module);
+                    entry.files.extend(other.files);
+                    entry.files.sort_unstable();
+                    entry.files.dedup();
+                    entry.collisions += 1 + other.collisions;
+                },
+            );
+            if let Some(totals) = self.category_totals.get_mut(category) {
+                totals.unique -= merged;
+            }
+        }
+        shorten_keys(
+            &mut self.modules,
+            |hash| shorten_hash(hash, bytes),
+            |module, other| module.files.extend(other.files),
+        );
+        let shorten = |(category, hash)| (category, shorten_hash(hash, bytes));
+        shorten_keys(&mut self.weighted.copies, shorten, |copies, other| {
+            copies.total += other.total;
+            copies.max = copies.max.max(other.max);
+        });
+        if let Some(offsets) = &mut self.offsets {
+            shorten_keys(offsets, shorten, |offsets, other| offsets.extend(other));
+        }
+        if let Some(body_names) = &mut self.body_names {
+            shorten_keys(body_names, shorten, |_, _| {});
+        }
+        if let Some(against) = &mut self.against {
+            against.shorten_hashes(bytes);
+        }
+        if let Some(memory_init) = &mut self.memory_init {
+            memory_init.shorten_hashes(bytes);
+        }
+        if let Some(attribution) = &mut self.attribution {
+            attribution.shorten_hashes(bytes);
+        }
+    }
+
+    /// Get the groups of byte-identical modules, largest redundancy first.
+    fn identical_modules(&self) -> Vec<&ModuleEntry> {
+        let mut identical: Vec<_> = self
+            .modules
+            .values()
+            .filter(|entry| entry.files.len() > 1)
+            .collect();
+        identical.sort_by_key(|entry| {
+            (
+                std::cmp::Reverse(entry.size * (entry.files.len() as u64 - 1)),
+                entry.files[0],
+            )
+        });
+        identical
+    }
+}
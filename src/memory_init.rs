@@ -0,0 +1,138 @@
+//! Estimating the wins from sharing duplicated data segments through
+//! `memory.init`, for `--memory-init`.
+//!
+//! The model moves every duplicated active data segment into a shared module
+//! as a passive segment, along with an exported function that copies it to the
+//! address it is given with `memory.init`. Each module that had the segment
+//! imports that function instead, and calls it from its start function with
+//! the segment's offset expression as the address. A segment is only shared
+//! when that saves more than the import, call, and shared function cost. The
+//! one-off costs per module, such as a start function and a function type, are
+//! left out.
+
+use crate::{dedupe::read_u32, shorten_hash, shorten_keys, WideHash};
+use anyhow::{ensure, Result};
+use std::collections::HashMap;
+
+/// The bytes of the import of a segment's function, with a short module name
+/// such as `"shared"`, a field name of up to three bytes, its kind, and its
+/// type.
+const IMPORT_SIZE: u64 = 7 + 4 + 1 + 1;
+
+/// The bytes of a `call` to the imported function, assuming a two-byte index.
+const CALL_SIZE: u64 = 3;
+
+/// The bytes of the shared function's entry in the function section, and of
+/// its export, with a field name of up to three bytes and a two-byte index.
+const FUNCTION_SIZE: u64 = 1 + 4 + 1 + 2;
+
+/// The estimated wins for the data segments.
+#[derive(Clone, Copy, Default)]
+pub struct Savings {
+    /// The number of distinct segments worth sharing.
+    pub segments: u64,
+    /// The bytes of the copies of every active segment after the first, which
+    /// is what exact duplication counts.
+    pub duplicated: u64,
+    /// The bytes saved after adding back the shared module's segments and
+    /// functions, and each module's imports and calls.
+    pub net: u64,
+}
+
+/// The first occurrence of each active data segment.
+#[derive(Default)]
+pub struct MemoryInit {
+    segments: HashMap<WideHash, Segment>,
+}
+
+struct Segment {
+    size: u64,
+    payload: u64,
+    /// The bytes of the segment's offset expression, without its `end`.
+    offset: u64,
+}
+
+impl MemoryInit {
+    /// Shorten the hashes of the segments to `bytes` bytes, keeping the first
+    /// of those that then collide.
+    pub fn shorten_hashes(&mut self, bytes: usize) {
+        shorten_keys(
+            &mut self.segments,
+            |hash| shorten_hash(hash, bytes),
+            |_, _| {},
+        );
+    }
+
+    /// Add the first occurrence of the data `segment`, whose payload is the
+    /// last `payload` bytes, and whose item hash is `hash`. Passive segments
+    /// are skipped, since their modules already copy them explicitly.
+    pub fn add(&mut self, hash: WideHash, segment: &[u8], payload: usize) -> Result<()> {
+        ensure!(payload < segment.len(), "data segment has no header");
+        let mut pos = 0;
+        match read_u32(segment, &mut pos)? {
+            0 => {}
+            2 => {
+                read_u32(segment, &mut pos)?;
+            }
+            _ => return Ok(()),
+        }
+
+        // The offset expression ends where the payload's length starts.
+        let payload_start = segment.len() - payload;
+        let mut len_start = payload_start - 1;
+        while len_start > pos && segment[len_start - 1] & 0x80 != 0 {
+            len_start -= 1;
+        }
+        self.segments.insert(
+            hash,
+            Segment {
+                size: segment.len() as u64,
+                payload: payload as u64,
+                offset: len_start.saturating_sub(pos + 1) as u64,
+            },
+        );
+        Ok(())
+    }
+
+    /// The wins, given the `count` of occurrences of each segment.
+    pub fn savings(&self, count: impl Fn(&WideHash) -> u64) -> Savings {
+        let mut savings = Savings::default();
+        for (hash, segment) in &self.segments {
+            let count = count(hash);
+            if count < 2 {
+                continue;
+            }
+            savings.duplicated += (count - 1) * segment.size;
+            let shared = 1
+                + leb_size(segment.payload)
+                + segment.payload
+                + FUNCTION_SIZE
+                + init_body_size(segment.payload);
+            let per_use = segment.offset + CALL_SIZE + IMPORT_SIZE;
+            let cost = shared + count * per_use;
+            let removed = count * segment.size;
+            if removed > cost {
+                savings.segments += 1;
+                savings.net += removed - cost;
+            }
+        }
+        savings
+    }
+}
+
+/// The size of the body of the shared function that copies a segment of
+/// `payload` bytes, assuming a two-byte segment index, including the size of
+/// the body.
+fn init_body_size(payload: u64) -> u64 {
+    let body = 1 // No locals.
+        + 2 // `local.get 0`, the address.
+        + 2 // `i32.const 0`, the offset in the segment.
+        + 1 + leb_size(payload) // `i32.const <len>`. Close enough for a signed LEB.
+        + 2 + 2 + 1 // `memory.init <segment> 0`.
+        + 1; // `end`.
+    leb_size(body) + body
+}
+
+fn leb_size(n: u64) -> u64 {
+    (64 - u64::from((n | 1).leading_zeros())).div_ceil(7)
+}
@@ -0,0 +1,582 @@
+//! The `merge-functions` subcommand, which merges the byte-identical function
+//! bodies within each module, to check how much of the predicted win is
+//! actually realizable.
+//!
+//! Defined functions with the same type and the same body are merged into the
+//! first of them: its duplicates are removed from the function and code
+//! sections, and every reference to them is redirected to it. References are
+//! the operands of `call`, `return_call`, and `ref.func` instructions, function
+//! exports, the start function, element segments, global initializers, and the
+//! function entries of the `name` section. The other functions are renumbered
+//! to fill the gaps.
+//!
+//! Debug info that refers to code offsets, such as DWARF, is left as it is and
+//! goes stale. Relocatable object files and modules using module linking are
+//! skipped, since they refer to functions in ways this doesn't rewrite.
+
+use crate::{
+    dedupe::{read_u32, relative_path, write_u32},
+    for_each_wasm_file, normalize, print_row, Options,
+};
+use anyhow::{bail, ensure, Context, Result};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Merge the functions of every module of `corpus`, validating them with
+/// `features` before and after, write the merged modules to `out_dir` if
+/// given, and print the achieved savings next to the predicted ones.
+pub fn run(
+    corpus: &Path,
+    out_dir: Option<&Path>,
+    features: wasmparser::WasmFeatures,
+    options: &Options,
+) -> Result<()> {
+    let mut total_size = 0;
+    let mut predicted = 0;
+    let mut achieved = 0;
+    let mut merged_functions = 0;
+    let mut merged_modules = 0;
+    let mut skipped = 0;
+    for_each_wasm_file(corpus, options, |path, wasm| {
+        total_size += wasm.len() as u64;
+
+        let mut validator = wasmparser::Validator::new();
+        validator.wasm_features(features);
+        if let Err(e) = validator.validate_all(wasm) {
+            log::warn!("Skipping invalid file {}: {}", path.display(), e);
+            skipped += 1;
+            return Ok(());
+        }
+
+        let merged = match merge(wasm) {
+            Ok(merged) => merged,
+            Err(e) => {
+                log::warn!("Skipping {}: {:#}", path.display(), e);
+                skipped += 1;
+                return Ok(());
+            }
+        };
+
+        let mut validator = wasmparser::Validator::new();
+        validator.wasm_features(features);
+        if let Err(e) = validator.validate_all(&merged.wasm) {
+            log::warn!(
+                "Skipping {}, whose merged module is invalid: {}",
+                path.display(),
+                e
+            );
+            skipped += 1;
+            return Ok(());
+        }
+
+        log::info!(
+            "Merged {} functions of {}, saving {} bytes ({} predicted)",
+            merged.functions,
+            path.display(),
+            wasm.len().saturating_sub(merged.wasm.len()),
+            merged.predicted
+        );
+        if merged.functions > 0 {
+            merged_modules += 1;
+        }
+        merged_functions += merged.functions;
+        predicted += merged.predicted;
+        achieved += (wasm.len() as u64).saturating_sub(merged.wasm.len() as u64);
+
+        if let Some(out_dir) = out_dir {
+            let out = out_dir.join(relative_path(corpus, path));
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("failed to create {}", parent.display()))?;
+            }
+            fs::write(&out, &merged.wasm)
+                .with_context(|| format!("failed to write {}", out.display()))?;
+        }
+        Ok(())
+    })?;
+
+    print_row("Total size", total_size, total_size, options);
+    println!(
+        "Merged functions: {} in {} modules",
+        merged_functions, merged_modules
+    );
+    print_row("Predicted savings", predicted, total_size, options);
+    print_row("Achieved savings", achieved, total_size, options);
+    if skipped > 0 {
+        println!("Skipped modules: {}", skipped);
+    }
+    Ok(())
+}
+
+/// A module with its duplicate functions merged.
+struct Merged {
+    wasm: Vec<u8>,
+    /// The number of functions that were merged into another one.
+    functions: u64,
+    /// The bytes of the removed code bodies, which is what measuring code
+    /// bodies within the module predicts merging saves.
+    predicted: u64,
+}
+
+/// How the functions of a module are renumbered.
+struct Remap {
+    /// The new index of each function of the original module.
+    indices: Vec<u32>,
+    /// Whether each function of the original module was merged into another
+    /// one.
+    merged: Vec<bool>,
+}
+
+impl Remap {
+    fn index(&self, index: u32) -> Result<u32> {
+        self.indices
+            .get(index as usize)
+            .copied()
+            .context("function index out of bounds")
+    }
+
+    /// Whether the function `index` is still in the module, under a new index.
+    fn kept(&self, index: u32) -> bool {
+        !self.merged.get(index as usize).copied().unwrap_or(true)
+    }
+}
+
+fn merge(wasm: &[u8]) -> Result<Merged> {
+    let mut imported = 0;
+    let mut types = vec![];
+    let mut bodies = vec![];
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        match payload? {
+            wasmparser::Payload::ImportSection(mut reader) => {
+                for _ in 0..reader.get_count() {
+                    if let wasmparser::ImportSectionEntryType::Function(_) = reader.read()?.ty {
+                        imported += 1;
+                    }
+                }
+            }
+            wasmparser::Payload::FunctionSection(mut reader) => {
+                for _ in 0..reader.get_count() {
+                    types.push(reader.read()?);
+                }
+            }
+            wasmparser::Payload::CodeSectionEntry(body) => bodies.push(body),
+            wasmparser::Payload::ModuleSectionStart { .. }
+            | wasmparser::Payload::InstanceSection(_)
+            | wasmparser::Payload::AliasSection(_) => bail!("module linking is not supported"),
+            wasmparser::Payload::CustomSection { name, .. }
+                if name == "linking" || name.starts_with("reloc.") =>
+            {
+                bail!("relocatable object files are not supported")
+            }
+            _ => {}
+        }
+    }
+    ensure!(
+        types.len() == bodies.len(),
+        "function and code section counts differ"
+    );
+
+    // Merge each function into the first one with the same type and body.
+    let mut first: HashMap<(u32, &[u8]), usize> = HashMap::new();
+    let mut remap = Remap {
+        indices: (0..imported).collect(),
+        merged: vec![false; imported as usize],
+    };
+    let mut kept = vec![];
+    let mut predicted = 0;
+    for (i, (ty, body)) in types.iter().zip(&bodies).enumerate() {
+        let range = body.range();
+        let bytes = &wasm[range.start..range.end];
+        match first.get(&(*ty, bytes)) {
+            Some(canonical) => {
+                let index = remap.indices[imported as usize + canonical];
+                remap.indices.push(index);
+                remap.merged.push(true);
+                predicted += bytes.len() as u64;
+            }
+            None => {
+                first.insert((*ty, bytes), i);
+                remap.indices.push(imported + kept.len() as u32);
+                remap.merged.push(false);
+                kept.push(i);
+            }
+        }
+    }
+    let functions = (bodies.len() - kept.len()) as u64;
+    if functions == 0 {
+        return Ok(Merged {
+            wasm: wasm.to_vec(),
+            functions,
+            predicted,
+        });
+    }
+
+    let mut module = wasm_encoder::Module::new();
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_u32(wasm, &mut pos)? as usize;
+        let start = pos;
+        let end = start
+            .checked_add(size)
+            .filter(|end| *end <= wasm.len())
+            .context("section extends past the end of the module")?;
+        let contents = &wasm[start..end];
+        pos = end;
+
+        let mut section = vec![];
+        match id {
+            // Custom section.
+            0 => {
+                let mut name_pos = 0;
+                let name_len = read_u32(contents, &mut name_pos)? as usize;
+                ensure!(
+                    name_pos + name_len <= contents.len(),
+                    "custom section name is too long"
+                );
+                let name = std::str::from_utf8(&contents[name_pos..name_pos + name_len])
+                    .context("custom section name is not UTF-8")?;
+                if name == "name" {
+                    names(&contents[name_pos + name_len..], &remap, &mut section)?;
+                    module.section(&wasm_encoder::CustomSection {
+                        name,
+                        data: &section,
+                    });
+                    continue;
+                }
+                section.extend_from_slice(contents);
+            }
+            // Function section.
+            3 => {
+                write_u32(&mut section, kept.len() as u32);
+                for i in &kept {
+                    write_u32(&mut section, types[*i]);
+                }
+            }
+            // Global section.
+            6 => globals(contents, &remap, &mut section)?,
+            // Export section.
+            7 => exports(contents, &remap, &mut section)?,
+            // Start section.
+            8 => {
+                let mut start_pos = 0;
+                let func = read_u32(contents, &mut start_pos)?;
+                write_u32(&mut section, remap.index(func)?);
+            }
+            // Element section.
+            9 => elements(contents, &remap, &mut section)?,
+            // Code section.
+            10 => {
+                write_u32(&mut section, kept.len() as u32);
+                let mut body = vec![];
+                for i in &kept {
+                    body.clear();
+                    code_body(wasm, &bodies[*i], &remap, &mut body)?;
+                    write_u32(&mut section, body.len() as u32);
+                    section.extend_from_slice(&body);
+                }
+            }
+            _ => section.extend_from_slice(contents),
+        }
+        module.section(&wasm_encoder::RawSection { id, data: &section });
+    }
+
+    Ok(Merged {
+        wasm: module.finish(),
+        functions,
+        predicted,
+    })
+}
+
+/// Copy the code `body` of the module `wasm` to `out`, renumbering the
+/// functions it refers to.
+fn code_body(
+    wasm: &[u8],
+    body: &wasmparser::FunctionBody,
+    remap: &Remap,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let range = body.range();
+    let mut copied = range.start;
+    normalize::for_each_instruction(wasm, body, |op, bytes| {
+        let index = match op {
+            wasmparser::Operator::Call { function_index }
+            | wasmparser::Operator::ReturnCall { function_index }
+            | wasmparser::Operator::RefFunc { function_index } => *function_index,
+            _ => return Ok(()),
+        };
+        // All three have a single-byte opcode followed by the index.
+        let start = bytes.as_ptr() as usize - wasm.as_ptr() as usize;
+        out.extend_from_slice(&wasm[copied..start + 1]);
+        write_u32(out, remap.index(index)?);
+        copied = start + bytes.len();
+        Ok(())
+    })?;
+    out.extend_from_slice(&wasm[copied..range.end]);
+    Ok(())
+}
+
+fn globals(section: &[u8], remap: &Remap, out: &mut Vec<u8>) -> Result<()> {
+    let mut pos = 0;
+    let count = read_u32(section, &mut pos)?;
+    write_u32(out, count);
+    for _ in 0..count {
+        // The value type, which may be a reference type with a heap type, and
+        // the mutability.
+        let ty = byte(section, &mut pos)?;
+        out.push(ty);
+        if let 0x63 | 0x64 | 0x6b | 0x6c = ty {
+            copy_leb(section, &mut pos, out)?;
+        }
+        out.push(byte(section, &mut pos)?);
+        const_expr(section, &mut pos, remap, out)?;
+    }
+    Ok(())
+}
+
+fn exports(section: &[u8], remap: &Remap, out: &mut Vec<u8>) -> Result<()> {
+    let mut pos = 0;
+    let count = read_u32(section, &mut pos)?;
+    write_u32(out, count);
+    for _ in 0..count {
+        let start = pos;
+        let name_len = read_u32(section, &mut pos)? as usize;
+        pos += name_len;
+        let kind = byte(section, &mut pos)?;
+        out.extend_from_slice(&section[start..pos]);
+        let index = read_u32(section, &mut pos)?;
+        write_u32(
+            out,
+            if kind == 0 {
+                remap.index(index)?
+            } else {
+                index
+            },
+        );
+    }
+    Ok(())
+}
+
+fn elements(section: &[u8], remap: &Remap, out: &mut Vec<u8>) -> Result<()> {
+    let mut pos = 0;
+    let count = read_u32(section, &mut pos)?;
+    write_u32(out, count);
+    for _ in 0..count {
+        let flags = read_u32(section, &mut pos)?;
+        ensure!(flags < 8, "unknown element segment flags {}", flags);
+        write_u32(out, flags);
+        let active = flags & 1 == 0;
+        let exprs = flags & 4 != 0;
+        if active {
+            if flags & 2 != 0 {
+                let table = read_u32(section, &mut pos)?;
+                write_u32(out, table);
+            }
+            const_expr(section, &mut pos, remap, out)?;
+        }
+        // The element kind or reference type, unless it is implied.
+        if flags & 3 != 0 {
+            out.push(byte(section, &mut pos)?);
+        }
+        let items = read_u32(section, &mut pos)?;
+        write_u32(out, items);
+        for _ in 0..items {
+            if exprs {
+                const_expr(section, &mut pos, remap, out)?;
+            } else {
+                let index = read_u32(section, &mut pos)?;
+                write_u32(out, remap.index(index)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy the constant expression at `*pos` in `bytes` to `out`, renumbering the
+/// functions of its `ref.func`s.
+fn const_expr(bytes: &[u8], pos: &mut usize, remap: &Remap, out: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let op = byte(bytes, pos)?;
+        out.push(op);
+        match op {
+            // `end`.
+            0x0b => return Ok(()),
+            // `ref.func`.
+            0xd2 => {
+                let index = read_u32(bytes, pos)?;
+                write_u32(out, remap.index(index)?);
+            }
+            // `i32.const`, `i64.const`, `global.get`, and `ref.null`.
+            0x41 | 0x42 | 0x23 | 0xd0 => copy_leb(bytes, pos, out)?,
+            // `f32.const` and `f64.const`.
+            0x43 | 0x44 => {
+                let len = if op == 0x43 { 4 } else { 8 };
+                let immediate = bytes
+                    .get(*pos..*pos + len)
+                    .context("unexpected end of constant expression")?;
+                out.extend_from_slice(immediate);
+                *pos += len;
+            }
+            // The arithmetic of the extended constant expressions proposal.
+            0x6a | 0x6b | 0x6c | 0x7c | 0x7d | 0x7e => {}
+            _ => bail!(
+                "unsupported instruction 0x{:02x} in constant expression",
+                op
+            ),
+        }
+    }
+}
+
+/// Copy the `name` section `data` to `out`, dropping the names of merged
+/// functions and renumbering the others.
+fn names(data: &[u8], remap: &Remap, out: &mut Vec<u8>) -> Result<()> {
+    let mut pos = 0;
+    let mut subsection = vec![];
+    while pos < data.len() {
+        let id = byte(data, &mut pos)?;
+        let size = read_u32(data, &mut pos)? as usize;
+        let contents = data
+            .get(pos..pos + size)
+            .context("name subsection extends past the end of the section")?;
+        pos += size;
+
+        subsection.clear();
+        match id {
+            // Function names, and the names of the locals and labels of each
+            // function.
+            1..=3 => {
+                let mut entry_pos = 0;
+                let count = read_u32(contents, &mut entry_pos)?;
+                let mut entries = vec![];
+                let mut kept = 0;
+                for _ in 0..count {
+                    let index = read_u32(contents, &mut entry_pos)?;
+                    let start = entry_pos;
+                    if id == 1 {
+                        skip_name(contents, &mut entry_pos)?;
+                    } else {
+                        for _ in 0..read_u32(contents, &mut entry_pos)? {
+                            read_u32(contents, &mut entry_pos)?;
+                            skip_name(contents, &mut entry_pos)?;
+                        }
+                    }
+                    if remap.kept(index) {
+                        write_u32(&mut entries, remap.index(index)?);
+                        entries.extend_from_slice(&contents[start..entry_pos]);
+                        kept += 1;
+                    }
+                }
+                write_u32(&mut subsection, kept);
+                subsection.extend_from_slice(&entries);
+            }
+            _ => subsection.extend_from_slice(contents),
+        }
+        out.push(id);
+        write_u32(out, subsection.len() as u32);
+        out.extend_from_slice(&subsection);
+    }
+    Ok(())
+}
+
+fn skip_name(bytes: &[u8], pos: &mut usize) -> Result<()> {
+    let len = read_u32(bytes, pos)? as usize;
+    ensure!(*pos + len <= bytes.len(), "name is too long");
+    *pos += len;
+    Ok(())
+}
+
+/// Copy the signed or unsigned LEB128 at `*pos` in `bytes` to `out`.
+fn copy_leb(bytes: &[u8], pos: &mut usize, out: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let byte = byte(bytes, pos)?;
+        out.push(byte);
+        if byte & 0x80 == 0 {
+            return Ok(());
+        }
+    }
+}
+
+fn byte(bytes: &[u8], pos: &mut usize) -> Result<u8> {
+    let byte = *bytes.get(*pos).context("unexpected end of section")?;
+    *pos += 1;
+    Ok(byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_encoder::{
+        CodeSection, Export, ExportSection, Function, FunctionSection, Instruction, Module,
+        TypeSection, ValType,
+    };
+
+    /// A module whose functions have the given bodies, all of type `[] ->
+    /// [i32]`, and which exports the last one.
+    fn module(bodies: &[&[Instruction]]) -> Vec<u8> {
+        let mut types = TypeSection::new();
+        types.function(vec![], vec![ValType::I32]);
+        let mut functions = FunctionSection::new();
+        let mut code = CodeSection::new();
+        for body in bodies {
+            functions.function(0);
+            let mut function = Function::new(vec![]);
+            for instruction in body.iter() {
+                function.instruction(instruction.clone());
+            }
+            function.instruction(Instruction::End);
+            code.function(&function);
+        }
+        let mut exports = ExportSection::new();
+        exports.export("main", Export::Function(bodies.len() as u32 - 1));
+        let mut module = Module::new();
+        module
+            .section(&types)
+            .section(&functions)
+            .section(&exports)
+            .section(&code);
+        module.finish()
+    }
+
+    #[test]
+    fn merged_calls_are_redirected() {
+        let wasm = module(&[
+            &[Instruction::I32Const(1)],
+            &[Instruction::I32Const(1)],
+            &[
+                Instruction::Call(0),
+                Instruction::Call(1),
+                Instruction::I32Add,
+            ],
+        ]);
+        wasmparser::validate(&wasm).unwrap();
+        let merged = merge(&wasm).unwrap();
+        assert_eq!(merged.functions, 1);
+        assert!(merged.wasm.len() < wasm.len());
+        wasmparser::validate(&merged.wasm).unwrap();
+    }
+
+    #[test]
+    fn functions_after_a_merged_one_are_renumbered() {
+        let wasm = module(&[
+            &[Instruction::I32Const(1)],
+            &[Instruction::I32Const(1)],
+            &[Instruction::I32Const(2)],
+            &[
+                Instruction::Call(1),
+                Instruction::Call(2),
+                Instruction::I32Add,
+            ],
+        ]);
+        let merged = merge(&wasm).unwrap();
+        assert_eq!(merged.functions, 1);
+        wasmparser::validate(&merged.wasm).unwrap();
+    }
+
+    #[test]
+    fn modules_without_duplicates_are_unchanged() {
+        let wasm = module(&[&[Instruction::I32Const(1)], &[Instruction::I32Const(2)]]);
+        let merged = merge(&wasm).unwrap();
+        assert_eq!(merged.functions, 0);
+        assert_eq!(merged.wasm, wasm);
+    }
+}
@@ -0,0 +1,58 @@
+//! Exporting the summary as metrics in the Prometheus text format, e.g. for the
+//! node exporter's textfile collector.
+
+use crate::{Category, Counts};
+use anyhow::{Context, Result};
+use std::{fmt::Write as _, path::Path};
+
+/// Write the total size, file count, and duplication of each category in
+/// `counts` to `path`.
+pub fn write(counts: &Counts, path: &Path) -> Result<()> {
+    let mut out = String::new();
+    metric(
+        &mut out,
+        "wasm_dedupe_total_size_bytes",
+        "The total size of the corpus.",
+    )?;
+    writeln!(out, "wasm_dedupe_total_size_bytes {}", counts.total_size)?;
+
+    metric(
+        &mut out,
+        "wasm_dedupe_files",
+        "The number of Wasm files in the corpus.",
+    )?;
+    writeln!(out, "wasm_dedupe_files {}", counts.files.len())?;
+
+    metric(
+        &mut out,
+        "wasm_dedupe_duplicated_bytes",
+        "The bytes of each kind of item that are redundant copies, within or \
+         across modules.",
+    )?;
+    for category in counts.items.keys() {
+        let dupe = counts.duplicated(*category);
+        let scopes = [
+            ("within_modules", dupe.within_modules),
+            ("across_modules", dupe.across_modules),
+        ];
+        for (scope, value) in scopes {
+            writeln!(
+                out,
+                "wasm_dedupe_duplicated_bytes{{category=\"{}\",scope=\"{}\",strict=\"{}\"}} {}",
+                category.name(),
+                scope,
+                Category::ALL.contains(category),
+                value
+            )?;
+        }
+    }
+
+    std::fs::write(path, out).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Write the `HELP` and `TYPE` lines of the gauge `name`.
+fn metric(out: &mut String, name: &str, help: &str) -> Result<()> {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} gauge", name)?;
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Function names from the `name` section, demangled, for attributing
+//! duplicated code bodies with `--function-names`.
+
+use crate::dedupe::read_u32;
+use anyhow::{ensure, Context, Result};
+
+/// The function index and raw name of each entry of the function names
+/// subsection of the `name` section `data`.
+pub fn function_names(data: &[u8]) -> Result<Vec<(u32, &str)>> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let id = data[pos];
+        pos += 1;
+        let size = read_u32(data, &mut pos)? as usize;
+        let contents = data
+            .get(pos..pos + size)
+            .context("name subsection extends past the end of the section")?;
+        pos += size;
+        if id != 1 {
+            continue;
+        }
+
+        let mut pos = 0;
+        let count = read_u32(contents, &mut pos)?;
+        let mut names = Vec::with_capacity(count.min(1 << 16) as usize);
+        for _ in 0..count {
+            let index = read_u32(contents, &mut pos)?;
+            let len = read_u32(contents, &mut pos)? as usize;
+            ensure!(pos + len <= contents.len(), "function name is too long");
+            let name = std::str::from_utf8(&contents[pos..pos + len])
+                .context("function name is not UTF-8")?;
+            pos += len;
+            names.push((index, name));
+        }
+        return Ok(names);
+    }
+    Ok(vec![])
+}
+
+/// Whether the function `name`, raw or demangled, looks like the canonical ABI
+/// glue that bindings generators emit: `cabi_realloc` and the other `cabi_*`
+/// helpers, exports named `<interface>#<function>`, and `wit-bindgen`'s
+/// runtime.
+pub fn is_canonical_abi_glue(name: &str) -> bool {
+    name.starts_with("cabi_")
+        || name.contains('#')
+        || name.contains("wit_bindgen")
+        || name.contains("wit-bindgen")
+}
+
+/// Demangle the Rust or C++ symbol `name`, or return it as it is if it isn't
+/// mangled.
+pub fn demangle(name: &str) -> String {
+    // `{:#}` leaves out the hash suffix of legacy Rust symbols.
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        return format!("{:#}", demangled);
+    }
+    if name.starts_with("_Z") {
+        if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+            return symbol.to_string();
+        }
+    }
+    name.to_string()
+}
+
+/// The crate or namespace that the demangled `name` starts with, like `core`
+/// for `core::fmt::Formatter::pad` and `alloc` for
+/// `<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop`.
+pub fn namespace(name: &str) -> Option<&str> {
+    let name = name.trim_start_matches('<');
+    let end = name.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))?;
+    if end > 0 && name[end..].starts_with("::") {
+        Some(&name[..end])
+    } else {
+        None
+    }
+}
+
+/// The demangled `name` with the generic arguments of its paths left out, such
+/// as `alloc::vec::Vec<>::push` for `alloc::vec::Vec<u8>::push`, so that the
+/// monomorphizations of a generic function have the same name. The brackets
+/// of qualified paths like `<T as Trait>` are kept, along with the paths
+/// inside them.
+pub fn without_generics(name: &str) -> String {
+    let mut stripped = String::with_capacity(name.len());
+    // How many brackets of generic arguments deep we are.
+    let mut depth = 0;
+    let mut prev = None;
+    for c in name.chars() {
+        if depth > 0 {
+            match c {
+                '<' => depth += 1,
+                // The arrow of a function type, like `Fn() -> u8`.
+                '>' if prev == Some('-') => {}
+                '>' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                stripped.push('>');
+            }
+        } else {
+            stripped.push(c);
+            let generic = prev.map_or(false, |p: char| p.is_alphanumeric() || p == '_' || p == ':');
+            if c == '<' && generic {
+                depth = 1;
+            }
+        }
+        prev = Some(c);
+    }
+    stripped
+}
@@ -0,0 +1,87 @@
+//! Tallying sequences of `n` consecutive instructions by opcode.
+
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+
+/// How often a sequence of opcodes occurs.
+#[derive(Default)]
+pub struct Tally {
+    pub count: u64,
+    /// The total size of every occurrence, in bytes.
+    pub size: u64,
+}
+
+/// The n-grams of every code body added so far.
+pub struct Ngrams {
+    n: usize,
+    opcodes: Vec<String>,
+    interned: HashMap<String, u16>,
+    tallies: HashMap<Vec<u16>, Tally>,
+}
+
+impl Ngrams {
+    pub fn new(n: usize) -> Self {
+        Ngrams {
+            n,
+            opcodes: vec![],
+            interned: HashMap::new(),
+            tallies: HashMap::new(),
+        }
+    }
+
+    /// Add the n-grams of the code `body`, located within `wasm`. N-grams don't
+    /// span code bodies.
+    pub fn add_body(&mut self, wasm: &[u8], body: &wasmparser::FunctionBody) -> Result<()> {
+        let mut window = VecDeque::with_capacity(self.n);
+        let mut key = Vec::with_capacity(self.n);
+        crate::normalize::for_each_instruction(wasm, body, |op, bytes| {
+            if window.len() == self.n {
+                window.pop_front();
+            }
+            window.push_back((self.intern(op), bytes.len() as u64));
+            if window.len() == self.n {
+                key.clear();
+                key.extend(window.iter().map(|(opcode, _)| *opcode));
+                let tally = self.tallies.entry(key.clone()).or_default();
+                tally.count += 1;
+                tally.size += window.iter().map(|(_, size)| size).sum::<u64>();
+            }
+            Ok(())
+        })
+    }
+
+    /// Intern the opcode of `op`, which is its name without its immediates.
+    fn intern(&mut self, op: &wasmparser::Operator) -> u16 {
+        let debug = format!("{:?}", op);
+        let name = debug.split(' ').next().unwrap();
+        if let Some(opcode) = self.interned.get(name) {
+            return *opcode;
+        }
+        let opcode = self.opcodes.len() as u16;
+        self.opcodes.push(name.to_string());
+        self.interned.insert(name.to_string(), opcode);
+        opcode
+    }
+
+    /// Get the `limit` n-grams with the largest total size, largest first, as
+    /// their opcode names and tallies.
+    pub fn top(&self, limit: usize) -> Vec<(Vec<&str>, &Tally)> {
+        let mut top: Vec<_> = self.tallies.iter().collect();
+        top.sort_by(|(a_key, a), (b_key, b)| {
+            b.size
+                .cmp(&a.size)
+                .then_with(|| b.count.cmp(&a.count))
+                .then_with(|| a_key.cmp(b_key))
+        });
+        top.into_iter()
+            .take(limit)
+            .map(|(key, tally)| {
+                let names = key
+                    .iter()
+                    .map(|opcode| self.opcodes[*opcode as usize].as_str())
+                    .collect();
+                (names, tally)
+            })
+            .collect()
+    }
+}
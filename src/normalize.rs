@@ -0,0 +1,537 @@
+//! Normalizations that map items which differ only in module-local details to
+//! the same hash key.
+//!
+//! Keys that are built from decoded instructions rather than their bytes
+//! re-encode them with `wasm-encoder`, so that they only depend on the binary
+//! format, and not on how any version of `wasmparser` happens to print them.
+
+use crate::dedupe::{read_u32, write_u32};
+use anyhow::{bail, ensure, Context, Result};
+use std::{collections::HashMap, io::Write};
+use wasm_encoder::{BlockType, Instruction, MemArg, ValType};
+use wasmparser::{Operator, Type, TypeOrFuncType};
+
+/// Write a key for `data` into `key` that is built from the decoded segment
+/// rather than its encoding, so that it doesn't depend on how its LEB128s were
+/// encoded.
+pub fn data_segment(data: &wasmparser::Data, key: &mut Vec<u8>) -> Result<()> {
+    match &data.kind {
+        wasmparser::DataKind::Passive => write!(key, "passive;")?,
+        wasmparser::DataKind::Active {
+            memory_index,
+            init_expr,
+        } => {
+            write!(key, "active {};", memory_index)?;
+            key.extend(expression(init_expr.get_operators_reader())?);
+        }
+    }
+    key.extend_from_slice(data.data);
+    Ok(())
+}
+
+/// Write a key for `elem` into `key` that is built from the decoded segment
+/// rather than its encoding, so that it doesn't depend on how its LEB128s were
+/// encoded.
+///
+/// If `remap_functions` is set, every referenced function index is replaced by
+/// the order in which the segment first references that function, so that two
+/// segments referencing the same pattern of functions through different
+/// function indices get the same key.
+pub fn elem_segment(
+    elem: &wasmparser::Element,
+    remap_functions: bool,
+    key: &mut Vec<u8>,
+) -> Result<()> {
+    let mut functions = HashMap::new();
+    let mut remap = |function_index: u32| {
+        if !remap_functions {
+            return function_index;
+        }
+        let next = functions.len() as u32;
+        *functions.entry(function_index).or_insert(next)
+    };
+
+    match &elem.kind {
+        wasmparser::ElementKind::Passive => write!(key, "passive;")?,
+        wasmparser::ElementKind::Declared => write!(key, "declared;")?,
+        wasmparser::ElementKind::Active {
+            table_index,
+            init_expr,
+        } => {
+            write!(key, "active {};", table_index)?;
+            key.extend(expression(init_expr.get_operators_reader())?);
+        }
+    }
+    key.push(type_code(elem.ty).context("unsupported element type")?);
+
+    let mut items = elem.items.get_items_reader()?;
+    for _ in 0..items.get_count() {
+        match items.read()? {
+            wasmparser::ElementItem::Func(function_index) => {
+                write!(key, "func {};", remap(function_index))?;
+            }
+            wasmparser::ElementItem::Expr(init_expr) => {
+                let ops = init_expr.get_operators_reader().into_iter().map(|op| {
+                    op.map(|op| match op {
+                        Operator::RefFunc { function_index } => Operator::RefFunc {
+                            function_index: remap(function_index),
+                        },
+                        op => op,
+                    })
+                });
+                key.extend(expression(ops)?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Which module-local details to normalize away when keying code bodies.
+#[derive(Clone, Copy, Default)]
+pub struct CodeOptions {
+    /// Replace function, global, and type index immediates with placeholders.
+    pub indices: bool,
+    /// Sort the local declarations by type, merge them into one run per type,
+    /// and renumber local indices to match.
+    pub locals: bool,
+    /// Key every instruction and local declaration by its decoded form rather
+    /// than its bytes, so that over-long LEB128 encodings don't matter.
+    pub lebs: bool,
+    /// Replace the immediates of constant instructions with placeholders.
+    pub constants: bool,
+}
+
+/// Write a key for the code `body` (located within `wasm`, and belonging to a
+/// function with `params` parameters) into `key`, normalizing the details
+/// selected by `options`.
+///
+/// Instructions that are not normalized contribute their exact bytes to the
+/// key, so that each option only exposes the duplication it is responsible for.
+pub fn code_body(
+    wasm: &[u8],
+    body: &wasmparser::FunctionBody,
+    params: u32,
+    options: CodeOptions,
+    key: &mut Vec<u8>,
+) -> Result<()> {
+    let range = body.range();
+    let ops = body.get_operators_reader()?;
+
+    // Renumbered locals and decoded instructions are re-encoded, along with
+    // the local declarations, while everything else is copied as is.
+    let (locals, mut encoded) = if options.locals {
+        let locals = Locals::new(body, params)?;
+        let encoded = Encoded::new(locals.declarations()?);
+        (Some(locals), Some(encoded))
+    } else if options.lebs {
+        let mut reader = body.get_locals_reader()?;
+        let mut declarations = vec![];
+        for _ in 0..reader.get_count() {
+            let (count, ty) = reader.read()?;
+            declarations.push((count, val_type(ty).context("unsupported local type")?));
+        }
+        (None, Some(Encoded::new(declarations)))
+    } else {
+        key.extend_from_slice(&wasm[range.start..ops.original_position()]);
+        (None, None)
+    };
+
+    for_each_instruction(wasm, body, |op, bytes| {
+        let mut out = match &mut encoded {
+            Some(encoded) => Out::Encoded(encoded),
+            None => Out::Raw(key),
+        };
+        instruction(op, bytes, options, locals.as_ref(), &mut out);
+        Ok(())
+    })?;
+    if let Some(encoded) = encoded {
+        key.extend(encoded.finish());
+    }
+    Ok(())
+}
+
+/// Call `f` with each instruction of the code `body` (located within `wasm`)
+/// and the bytes that encode it.
+pub fn for_each_instruction<'a>(
+    wasm: &'a [u8],
+    body: &wasmparser::FunctionBody<'a>,
+    mut f: impl FnMut(&wasmparser::Operator<'a>, &'a [u8]) -> Result<()>,
+) -> Result<()> {
+    let end = body.range().end;
+    let mut ops = body.get_operators_reader()?;
+
+    // The bytes of an instruction end where the next one starts, so hold each
+    // instruction back until we know where its successor begins.
+    let mut pending: Option<(wasmparser::Operator, usize)> = None;
+    while !ops.eof() {
+        let (op, offset) = ops.read_with_offset()?;
+        if let Some((prev, start)) = pending.replace((op, offset)) {
+            f(&prev, &wasm[start..offset])?;
+        }
+    }
+    if let Some((prev, start)) = pending {
+        f(&prev, &wasm[start..end])?;
+    }
+
+    Ok(())
+}
+
+/// A function's local declarations, sorted by type.
+struct Locals {
+    params: u32,
+    /// The total number of locals of each type, in sorted type order.
+    declarations: Vec<(Type, u32)>,
+    /// The new index of each declared (non-parameter) local, in declaration
+    /// order.
+    remap: Vec<u32>,
+}
+
+impl Locals {
+    fn new(body: &wasmparser::FunctionBody, params: u32) -> Result<Self> {
+        let mut reader = body.get_locals_reader()?;
+        let mut runs = vec![];
+        let mut total = 0u64;
+        for _ in 0..reader.get_count() {
+            let (count, ty) = reader.read()?;
+            total += u64::from(count);
+            ensure!(type_code(ty).is_some(), "unsupported local type");
+            runs.push((ty, count));
+        }
+        // Same limit as the validator, so that we don't try to renumber
+        // billions of locals in an invalid module.
+        ensure!(total <= 50_000, "too many locals: {}", total);
+
+        let mut declarations: Vec<(Type, u32)> = vec![];
+        let mut sorted = runs.clone();
+        sorted.sort_by_key(|(ty, _)| type_code(*ty));
+        for (ty, count) in sorted {
+            match declarations.last_mut() {
+                Some((last, total)) if *last == ty => *total += count,
+                _ => declarations.push((ty, count)),
+            }
+        }
+
+        // Each type's locals start after all the locals of the types that sort
+        // before it, and keep their relative order within the type.
+        let mut next: HashMap<u8, u32> = HashMap::new();
+        let mut start = params;
+        for (ty, count) in &declarations {
+            next.insert(type_code(*ty).unwrap(), start);
+            start += count;
+        }
+        let mut remap = vec![];
+        for (ty, count) in &runs {
+            let next = next.get_mut(&type_code(*ty).unwrap()).unwrap();
+            remap.extend(*next..*next + count);
+            *next += count;
+        }
+
+        Ok(Locals {
+            params,
+            declarations,
+            remap,
+        })
+    }
+
+    /// The sorted declarations, as `wasm-encoder` takes them.
+    fn declarations(&self) -> Result<Vec<(u32, ValType)>> {
+        self.declarations
+            .iter()
+            .map(|(ty, count)| Ok((*count, val_type(*ty).context("unsupported local type")?)))
+            .collect()
+    }
+
+    fn index(&self, local_index: u32) -> u32 {
+        match local_index.checked_sub(self.params) {
+            Some(declared) => self
+                .remap
+                .get(declared as usize)
+                .copied()
+                .unwrap_or(local_index),
+            None => local_index,
+        }
+    }
+}
+
+/// Where the key of a code body goes: straight into the key, or into its
+/// re-encoding.
+enum Out<'a> {
+    Raw(&'a mut Vec<u8>),
+    Encoded(&'a mut Encoded),
+}
+
+impl Out<'_> {
+    fn raw(&mut self, bytes: &[u8]) {
+        match self {
+            Out::Raw(key) => key.extend_from_slice(bytes),
+            Out::Encoded(encoded) => encoded.raw(bytes),
+        }
+    }
+}
+
+fn instruction(
+    op: &Operator,
+    bytes: &[u8],
+    options: CodeOptions,
+    locals: Option<&Locals>,
+    out: &mut Out,
+) {
+    // `0xff` is not a valid opcode, so normalized instructions can't be
+    // confused with the bytes of an actual instruction.
+    if let Some(placeholder) = placeholder(op, options) {
+        out.raw(&[0xff]);
+        out.raw(placeholder.as_bytes());
+        out.raw(&[0]);
+        return;
+    }
+
+    if let (Some(locals), Out::Encoded(encoded)) = (locals, &mut *out) {
+        let local = match op {
+            Operator::LocalGet { local_index } => {
+                Some(Instruction::LocalGet(locals.index(*local_index)))
+            }
+            Operator::LocalSet { local_index } => {
+                Some(Instruction::LocalSet(locals.index(*local_index)))
+            }
+            Operator::LocalTee { local_index } => {
+                Some(Instruction::LocalTee(locals.index(*local_index)))
+            }
+            _ => None,
+        };
+        if let Some(local) = local {
+            encoded.function.instruction(local);
+            return;
+        }
+    }
+
+    match out {
+        Out::Encoded(encoded) if options.lebs => encoded.instruction(op, bytes),
+        out => out.raw(bytes),
+    }
+}
+
+/// A code body or constant expression re-encoded by `wasm-encoder`, so that
+/// its key doesn't depend on how its LEB128s were encoded.
+struct Encoded {
+    function: wasm_encoder::Function,
+}
+
+impl Encoded {
+    fn new(locals: Vec<(u32, ValType)>) -> Self {
+        Encoded {
+            function: wasm_encoder::Function::new(locals),
+        }
+    }
+
+    fn raw(&mut self, bytes: &[u8]) {
+        self.function.raw(bytes.iter().copied());
+    }
+
+    /// Add `op`, whose original encoding is `bytes`. Instructions that
+    /// `wasm-encoder` can't encode, like those of SIMD and threads, keep their
+    /// original bytes, apart from the LEB128 opcode after a prefix byte.
+    fn instruction(&mut self, op: &Operator, bytes: &[u8]) {
+        if let Operator::BrTable { table } = op {
+            if let Ok((targets, default)) = table.read_table() {
+                self.function
+                    .instruction(Instruction::BrTable(targets[..].into(), default));
+                return;
+            }
+        }
+        if let Some(instruction) = encode(op) {
+            self.function.instruction(instruction);
+        } else if let Some(canonical) = canonical_prefixed(bytes) {
+            self.raw(&canonical);
+        } else {
+            self.raw(bytes);
+        }
+    }
+
+    /// The bytes of a module with this as its only code body.
+    fn finish(self) -> Vec<u8> {
+        let mut code = wasm_encoder::CodeSection::new();
+        code.function(&self.function);
+        let mut module = wasm_encoder::Module::new();
+        module.section(&code);
+        module.finish()
+    }
+}
+
+/// The re-encoding of the constant expression `ops`.
+fn expression<'a>(
+    ops: impl IntoIterator<Item = wasmparser::Result<Operator<'a>>>,
+) -> Result<Vec<u8>> {
+    let mut encoded = Encoded::new(vec![]);
+    for op in ops {
+        let op = op?;
+        match encode(&op) {
+            Some(instruction) => {
+                encoded.function.instruction(instruction);
+            }
+            None => bail!("unsupported instruction in a constant expression: {:?}", op),
+        }
+    }
+    Ok(encoded.finish())
+}
+
+/// The encoding of an instruction that has no immediates other than the
+/// LEB128 opcode after its prefix byte, with that opcode encoded canonically.
+fn canonical_prefixed(bytes: &[u8]) -> Option<Vec<u8>> {
+    let (&prefix, _) = bytes.split_first()?;
+    if !(0xfc..=0xfe).contains(&prefix) {
+        return None;
+    }
+    let mut pos = 1;
+    let opcode = read_u32(bytes, &mut pos).ok()?;
+    if pos != bytes.len() {
+        return None;
+    }
+    let mut canonical = vec![prefix];
+    write_u32(&mut canonical, opcode);
+    Some(canonical)
+}
+
+/// The binary encoding of the value type `ty`, which also orders the sorted
+/// local declarations.
+fn type_code(ty: Type) -> Option<u8> {
+    Some(match ty {
+        Type::I32 => 0x7f,
+        Type::I64 => 0x7e,
+        Type::F32 => 0x7d,
+        Type::F64 => 0x7c,
+        Type::V128 => 0x7b,
+        Type::FuncRef => 0x70,
+        Type::ExternRef => 0x6f,
+        _ => return None,
+    })
+}
+
+fn val_type(ty: Type) -> Option<ValType> {
+    Some(match ty {
+        Type::I32 => ValType::I32,
+        Type::I64 => ValType::I64,
+        Type::F32 => ValType::F32,
+        Type::F64 => ValType::F64,
+        Type::V128 => ValType::V128,
+        Type::FuncRef => ValType::FuncRef,
+        Type::ExternRef => ValType::ExternRef,
+        _ => return None,
+    })
+}
+
+fn block_type(ty: TypeOrFuncType) -> Option<BlockType> {
+    Some(match ty {
+        TypeOrFuncType::Type(Type::EmptyBlockType) => BlockType::Empty,
+        TypeOrFuncType::Type(ty) => BlockType::Result(val_type(ty)?),
+        TypeOrFuncType::FuncType(index) => BlockType::FunctionType(index),
+    })
+}
+
+fn mem_arg(memarg: &wasmparser::MemoryImmediate) -> MemArg {
+    MemArg {
+        offset: memarg.offset,
+        align: u32::from(memarg.align),
+        memory_index: memarg.memory,
+    }
+}
+
+/// The `wasm-encoder` instruction for `op`, if it has immediates that may
+/// have been encoded in more than one way and `wasm-encoder` can encode it.
+/// Instructions without immediates only have one encoding already.
+fn encode(op: &Operator) -> Option<Instruction<'static>> {
+    macro_rules! memory {
+        ($($name:ident)*) => {
+            match op {
+                $(Operator::$name { memarg } => return Some(Instruction::$name(mem_arg(memarg))),)*
+                _ => {}
+            }
+        };
+    }
+    memory! {
+        I32Load I64Load F32Load F64Load I32Load8S I32Load8U I32Load16S I32Load16U
+        I64Load8S I64Load8U I64Load16S I64Load16U I64Load32S I64Load32U
+        I32Store I64Store F32Store F64Store I32Store8 I32Store16 I64Store8 I64Store16
+        I64Store32
+    }
+
+    Some(match op {
+        Operator::Block { ty } => Instruction::Block(block_type(*ty)?),
+        Operator::Loop { ty } => Instruction::Loop(block_type(*ty)?),
+        Operator::If { ty } => Instruction::If(block_type(*ty)?),
+        Operator::End => Instruction::End,
+        Operator::Br { relative_depth } => Instruction::Br(*relative_depth),
+        Operator::BrIf { relative_depth } => Instruction::BrIf(*relative_depth),
+        Operator::Call { function_index } => Instruction::Call(*function_index),
+        Operator::CallIndirect { index, table_index } => Instruction::CallIndirect {
+            ty: *index,
+            table: *table_index,
+        },
+        Operator::LocalGet { local_index } => Instruction::LocalGet(*local_index),
+        Operator::LocalSet { local_index } => Instruction::LocalSet(*local_index),
+        Operator::LocalTee { local_index } => Instruction::LocalTee(*local_index),
+        Operator::GlobalGet { global_index } => Instruction::GlobalGet(*global_index),
+        Operator::GlobalSet { global_index } => Instruction::GlobalSet(*global_index),
+        Operator::MemorySize { mem, .. } => Instruction::MemorySize(*mem),
+        Operator::MemoryGrow { mem, .. } => Instruction::MemoryGrow(*mem),
+        Operator::I32Const { value } => Instruction::I32Const(*value),
+        Operator::I64Const { value } => Instruction::I64Const(*value),
+        Operator::F32Const { value } => Instruction::F32Const(f32::from_bits(value.bits())),
+        Operator::F64Const { value } => Instruction::F64Const(f64::from_bits(value.bits())),
+        Operator::RefNull { ty } => Instruction::RefNull(val_type(*ty)?),
+        Operator::RefFunc { function_index } => Instruction::RefFunc(*function_index),
+        Operator::MemoryInit { segment, mem } => Instruction::MemoryInit {
+            mem: *mem,
+            data: *segment,
+        },
+        Operator::DataDrop { segment } => Instruction::DataDrop(*segment),
+        Operator::MemoryCopy { src, dst } => Instruction::MemoryCopy {
+            src: *src,
+            dst: *dst,
+        },
+        Operator::MemoryFill { mem } => Instruction::MemoryFill(*mem),
+        _ => return None,
+    })
+}
+
+fn placeholder(op: &wasmparser::Operator, options: CodeOptions) -> Option<&'static str> {
+    use wasmparser::{Operator, TypeOrFuncType};
+
+    if options.indices {
+        match op {
+            Operator::Call { .. } => return Some("call"),
+            Operator::ReturnCall { .. } => return Some("return_call"),
+            Operator::CallIndirect { .. } => return Some("call_indirect"),
+            Operator::ReturnCallIndirect { .. } => return Some("return_call_indirect"),
+            Operator::RefFunc { .. } => return Some("ref.func"),
+            Operator::GlobalGet { .. } => return Some("global.get"),
+            Operator::GlobalSet { .. } => return Some("global.set"),
+            Operator::Block {
+                ty: TypeOrFuncType::FuncType(_),
+            } => return Some("block (type)"),
+            Operator::Loop {
+                ty: TypeOrFuncType::FuncType(_),
+            } => return Some("loop (type)"),
+            Operator::If {
+                ty: TypeOrFuncType::FuncType(_),
+            } => return Some("if (type)"),
+            Operator::Try {
+                ty: TypeOrFuncType::FuncType(_),
+            } => return Some("try (type)"),
+            _ => {}
+        }
+    }
+
+    if options.constants {
+        match op {
+            Operator::I32Const { .. } => return Some("i32.const"),
+            Operator::I64Const { .. } => return Some("i64.const"),
+            Operator::F32Const { .. } => return Some("f32.const"),
+            Operator::F64Const { .. } => return Some("f64.const"),
+            Operator::V128Const { .. } => return Some("v128.const"),
+            _ => {}
+        }
+    }
+
+    None
+}
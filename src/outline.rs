@@ -0,0 +1,242 @@
+//! Estimating the wins from outlining repeated instruction sequences into
+//! shared helper functions.
+//!
+//! Every instruction is interned into a token, and code bodies are split into
+//! runs of instructions that could be moved into another function as-is. A
+//! suffix array over all runs finds every repeated sequence, and sequences are
+//! then greedily picked, most profitable first, as long as their occurrences
+//! don't overlap sequences that were picked before them.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// The estimated size of a `call` to an outlined helper: the opcode plus a
+/// two-byte function index.
+const CALL_SIZE: u64 = 3;
+
+/// The estimated fixed size of an outlined helper: its function and type
+/// section entries, body size, local declarations, and `end`.
+const HELPER_OVERHEAD: u64 = 6;
+
+/// A repeated instruction sequence that was picked for outlining.
+pub struct Sequence {
+    /// The number of instructions in the sequence.
+    pub instructions: usize,
+    /// The size of one copy of the sequence, in bytes.
+    pub size: u64,
+    /// The number of places the sequence is replaced by a call.
+    pub occurrences: usize,
+    /// The estimated bytes saved by outlining the sequence.
+    pub saved: u64,
+}
+
+/// A collection of code to search for outlinable sequences.
+#[derive(Default)]
+pub struct Outliner {
+    /// Every run of outlinable instructions, each followed by a separator.
+    tokens: Vec<u32>,
+    /// The size of each `tokens` entry, in bytes. Separators are empty.
+    sizes: Vec<u32>,
+    interned: HashMap<Vec<u8>, u32>,
+    separators: u32,
+}
+
+impl Outliner {
+    /// Add the instructions of the code `body`, located within `wasm`.
+    pub fn add_body(&mut self, wasm: &[u8], body: &wasmparser::FunctionBody) -> Result<()> {
+        let mut run = 0;
+        crate::normalize::for_each_instruction(wasm, body, |op, bytes| {
+            if outlinable(op) {
+                let next = self.interned.len() as u32;
+                let token = *self.interned.entry(bytes.to_vec()).or_insert(next);
+                self.tokens.push(token);
+                self.sizes.push(bytes.len() as u32);
+                run += 1;
+            } else if run > 0 {
+                self.separate();
+                run = 0;
+            }
+            Ok(())
+        })?;
+        if run > 0 {
+            self.separate();
+        }
+        Ok(())
+    }
+
+    /// End the current run with a token that matches nothing else. Separators
+    /// count down from the top of the token space so that they never collide
+    /// with interned instructions.
+    fn separate(&mut self) {
+        self.tokens.push(u32::MAX - self.separators);
+        self.sizes.push(0);
+        self.separators += 1;
+    }
+
+    /// Pick the sequences to outline, most bytes saved first.
+    pub fn sequences(&self) -> Vec<Sequence> {
+        let suffixes = suffix_array(&self.tokens);
+        let lcp = lcp_array(&self.tokens, &suffixes);
+
+        let mut offsets = Vec::with_capacity(self.sizes.len() + 1);
+        offsets.push(0u64);
+        for size in &self.sizes {
+            offsets.push(offsets.last().unwrap() + u64::from(*size));
+        }
+        let size_of = |start: usize, len: usize| offsets[start + len] - offsets[start];
+
+        // Each LCP interval is a maximal set of suffixes sharing a prefix of
+        // `len` tokens, i.e. every occurrence of a sequence that can't be
+        // extended without losing occurrences.
+        let mut candidates = vec![];
+        let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+        for i in 1..=suffixes.len() {
+            let len = lcp.get(i).copied().unwrap_or(0);
+            let mut lower = i - 1;
+            while len < stack.last().unwrap().0 {
+                let (top_len, top_lower) = stack.pop().unwrap();
+                let occurrences = (i - top_lower) as u64;
+                let size = size_of(suffixes[top_lower], top_len);
+                if let Some(saved) = savings(size, occurrences) {
+                    candidates.push((saved, top_len, top_lower, i));
+                }
+                lower = top_lower;
+            }
+            if len > stack.last().unwrap().0 {
+                stack.push((len, lower));
+            }
+        }
+        candidates.sort_by(|a, b| b.cmp(a));
+
+        let mut used = vec![false; self.tokens.len()];
+        let mut sequences = vec![];
+        let mut starts = vec![];
+        for (_, len, lower, upper) in candidates {
+            starts.clear();
+            starts.extend_from_slice(&suffixes[lower..upper]);
+            starts.sort_unstable();
+
+            let mut picked = vec![];
+            let mut end = 0;
+            for &start in &starts {
+                if start >= end && !used[start..start + len].iter().any(|u| *u) {
+                    picked.push(start);
+                    end = start + len;
+                }
+            }
+
+            let size = size_of(picked.first().copied().unwrap_or(0), len);
+            if let Some(saved) = savings(size, picked.len() as u64) {
+                for start in &picked {
+                    used[*start..*start + len]
+                        .iter_mut()
+                        .for_each(|u| *u = true);
+                }
+                sequences.push(Sequence {
+                    instructions: len,
+                    size,
+                    occurrences: picked.len(),
+                    saved,
+                });
+            }
+        }
+
+        sequences.sort_by_key(|s| std::cmp::Reverse(s.saved));
+        sequences
+    }
+}
+
+/// The bytes saved by replacing `occurrences` copies of a `size`-byte sequence
+/// with calls to one helper, if that saves anything.
+fn savings(size: u64, occurrences: u64) -> Option<u64> {
+    if occurrences < 2 {
+        return None;
+    }
+    let before = size * occurrences;
+    let after = CALL_SIZE * occurrences + size + HELPER_OVERHEAD;
+    before.checked_sub(after).filter(|saved| *saved > 0)
+}
+
+/// Whether `op` can be moved into a helper function without changing its
+/// meaning. Operands and results on the stack become the helper's parameters
+/// and results, but control flow and locals are tied to the enclosing
+/// function.
+fn outlinable(op: &wasmparser::Operator) -> bool {
+    use wasmparser::Operator;
+
+    !matches!(
+        op,
+        Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::Try { .. }
+            | Operator::Catch { .. }
+            | Operator::CatchAll
+            | Operator::Delegate { .. }
+            | Operator::Rethrow { .. }
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. }
+            | Operator::LocalGet { .. }
+            | Operator::LocalSet { .. }
+            | Operator::LocalTee { .. }
+    )
+}
+
+/// Build the suffix array of `tokens` by prefix doubling.
+fn suffix_array(tokens: &[u32]) -> Vec<usize> {
+    let n = tokens.len();
+    let mut suffixes: Vec<usize> = (0..n).collect();
+    let mut rank: Vec<u64> = tokens.iter().map(|t| u64::from(*t)).collect();
+    let mut next = vec![0u64; n];
+    let mut k = 1;
+    if n < 2 {
+        return suffixes;
+    }
+    loop {
+        let key = |i: usize| (rank[i], rank.get(i + k).map_or(0, |r| r + 1));
+        suffixes.sort_unstable_by_key(|i| key(*i));
+        next[suffixes[0]] = 0;
+        for w in 1..n {
+            let bump = u64::from(key(suffixes[w - 1]) != key(suffixes[w]));
+            next[suffixes[w]] = next[suffixes[w - 1]] + bump;
+        }
+        std::mem::swap(&mut rank, &mut next);
+        if rank[suffixes[n - 1]] as usize == n - 1 {
+            break;
+        }
+        k *= 2;
+    }
+    suffixes
+}
+
+/// Build the longest-common-prefix array of `tokens` with Kasai's algorithm:
+/// `lcp[i]` is the length of the prefix that `suffixes[i - 1]` and
+/// `suffixes[i]` share.
+fn lcp_array(tokens: &[u32], suffixes: &[usize]) -> Vec<usize> {
+    let n = tokens.len();
+    let mut rank = vec![0; n];
+    for (i, suffix) in suffixes.iter().enumerate() {
+        rank[*suffix] = i;
+    }
+    let mut lcp = vec![0; n];
+    let mut h = 0;
+    for i in 0..n {
+        if rank[i] > 0 {
+            let j = suffixes[rank[i] - 1];
+            while i + h < n && j + h < n && tokens[i + h] == tokens[j + h] {
+                h += 1;
+            }
+            lcp[rank[i]] = h;
+            h = h.saturating_sub(1);
+        } else {
+            h = 0;
+        }
+    }
+    lcp
+}
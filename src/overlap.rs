@@ -0,0 +1,72 @@
+//! Estimating the wins from overlapping items that share a prefix or a suffix,
+//! like a linker tail-merging the strings of a string table.
+//!
+//! Sorting items puts those sharing the longest prefixes next to each other,
+//! and storing them in a trie saves the sum of the common prefix lengths of
+//! adjacent items. Sorting the reversed items does the same for suffixes. An
+//! item can't share both its prefix and its suffix this way, so the two are
+//! alternatives rather than additive.
+
+/// The estimated wins for a kind of item.
+#[derive(Clone, Copy, Default)]
+pub struct Wins {
+    /// The bytes saved by sharing common prefixes between items.
+    pub prefixes: u64,
+    /// The bytes saved by sharing common suffixes between items.
+    pub suffixes: u64,
+}
+
+/// The first occurrence of each item of some kind.
+pub struct Overlaps {
+    /// Shared prefixes and suffixes shorter than this aren't worth the
+    /// indirection of splitting items to share them.
+    min_size: usize,
+    items: Vec<Box<[u8]>>,
+}
+
+impl Overlaps {
+    pub fn new(min_size: usize) -> Self {
+        Overlaps {
+            min_size,
+            items: vec![],
+        }
+    }
+
+    /// Add the first occurrence of an item.
+    pub fn add(&mut self, item: &[u8]) {
+        self.items.push(item.into());
+    }
+
+    pub fn wins(&self) -> Wins {
+        let mut sorted: Vec<&[u8]> = self.items.iter().map(|item| &item[..]).collect();
+        sorted.sort_unstable();
+        let prefixes = self.shared(&sorted, common_prefix);
+
+        sorted.sort_unstable_by(|a, b| a.iter().rev().cmp(b.iter().rev()));
+        let suffixes = self.shared(&sorted, common_suffix);
+
+        Wins { prefixes, suffixes }
+    }
+
+    /// The bytes shared by adjacent `sorted` items according to `common`.
+    fn shared(&self, sorted: &[&[u8]], common: fn(&[u8], &[u8]) -> usize) -> u64 {
+        sorted
+            .windows(2)
+            .map(|pair| common(pair[0], pair[1]))
+            .filter(|len| *len >= self.min_size)
+            .map(|len| len as u64)
+            .sum()
+    }
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix(a: &[u8], b: &[u8]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
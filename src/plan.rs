@@ -0,0 +1,81 @@
+//! Writing a machine-readable plan of what to deduplicate, for `--emit-plan`,
+//! so that other tools can do the rewriting.
+
+use crate::{hex, run::Run, Category, Counts};
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::{fs::File, io::BufWriter, path::Path};
+
+#[derive(Serialize)]
+struct Plan<'a> {
+    schema_version: u32,
+    /// The path of every file, indexed by `Occurrence::file`.
+    files: Vec<String>,
+    /// Every duplicated item, most redundant bytes first.
+    items: Vec<Item<'a>>,
+    /// What produced the plan.
+    run: Run,
+}
+
+#[derive(Serialize)]
+struct Item<'a> {
+    category: &'a str,
+    hash: String,
+    size: u64,
+    occurrences: Vec<Occurrence>,
+}
+
+#[derive(Serialize)]
+struct Occurrence {
+    file: usize,
+    /// The offset of the item in its file.
+    offset: usize,
+}
+
+/// Write every duplicated item of at least `--min-size` bytes in a strict
+/// category of `counts`, and where each copy of it is, to `path` as JSON, along
+/// with the provenance of the `run` that measured them.
+///
+/// Requires the offsets of every item to be recorded.
+pub fn write(counts: &Counts, path: &Path, run: Run) -> Result<()> {
+    let offsets = match &counts.offsets {
+        Some(offsets) => offsets,
+        None => bail!("`--max-memory` dropped the offsets of the items, so there is no plan"),
+    };
+    let mut items = vec![];
+    for category in Category::ALL {
+        for (hash, entry) in counts.duplicated_items(*category, 0) {
+            items.push(Item {
+                category: category.name(),
+                hash: hex(hash),
+                size: entry.size,
+                occurrences: offsets
+                    .get(&(*category, hash.clone()))
+                    .into_iter()
+                    .flatten()
+                    .map(|(file, offset)| Occurrence {
+                        file: *file,
+                        offset: *offset,
+                    })
+                    .collect(),
+            });
+        }
+    }
+    items.sort_by_key(|item| std::cmp::Reverse(item.size * (item.occurrences.len() as u64 - 1)));
+    let plan = Plan {
+        schema_version: 1,
+        files: counts
+            .files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect(),
+        items,
+        run,
+    };
+
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    serde_json::to_writer(BufWriter::new(file), &plan)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
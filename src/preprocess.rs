@@ -0,0 +1,78 @@
+//! Running every module through an external tool, such as `wasm-opt -Oz`,
+//! before measuring it, for `--preprocess`.
+
+use anyhow::{bail, ensure, Context, Result};
+use std::{
+    path::Path,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The placeholders in the command for the paths of the input and output
+/// modules.
+const INPUT: &str = "{input}";
+const OUTPUT: &str = "{output}";
+
+pub struct Preprocessor {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Preprocessor {
+    /// Parse `command`, a program and its arguments separated by whitespace.
+    /// Its `{input}` and `{output}` arguments are replaced by the paths of the
+    /// module to read and the module to write, and `{input} -o {output}` is
+    /// appended when it has neither, which is what `wasm-opt` expects.
+    pub fn new(command: &str) -> Result<Self> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words.next().context("`--preprocess` command is empty")?;
+        let mut args: Vec<String> = words.collect();
+        if !args
+            .iter()
+            .any(|arg| arg.contains(INPUT) || arg.contains(OUTPUT))
+        {
+            args.extend([INPUT.to_string(), "-o".to_string(), OUTPUT.to_string()]);
+        }
+        Ok(Preprocessor { program, args })
+    }
+
+    /// Run the command on the module `wasm`, and return the module it wrote.
+    pub fn run(&self, wasm: &[u8]) -> Result<Vec<u8>> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "measure-wasm-dedupe-wins-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let input = std::env::temp_dir().join(format!("{}-in.wasm", name));
+        let output = std::env::temp_dir().join(format!("{}-out.wasm", name));
+        let result = self.run_with_files(wasm, &input, &output);
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+        result
+    }
+
+    fn run_with_files(&self, wasm: &[u8], input: &Path, output: &Path) -> Result<Vec<u8>> {
+        std::fs::write(input, wasm)
+            .with_context(|| format!("failed to write {}", input.display()))?;
+        let args = self.args.iter().map(|arg| {
+            arg.replace(INPUT, &input.display().to_string())
+                .replace(OUTPUT, &output.display().to_string())
+        });
+        let status = Command::new(&self.program)
+            .args(args)
+            .status()
+            .with_context(|| format!("failed to run `{}`", self.program))?;
+        if !status.success() {
+            bail!("`{}` failed: {}", self.program, status);
+        }
+        let preprocessed = std::fs::read(output)
+            .with_context(|| format!("failed to read {}", output.display()))?;
+        ensure!(
+            preprocessed.starts_with(b"\0asm"),
+            "`{}` didn't write a Wasm module",
+            self.program
+        );
+        Ok(preprocessed)
+    }
+}
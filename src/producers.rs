@@ -0,0 +1,89 @@
+//! Telling which toolchain produced a module, from its `producers` section.
+//!
+//! See https://github.com/WebAssembly/tool-conventions/blob/main/ProducersSection.md
+
+use crate::dedupe::read_u32;
+use anyhow::{ensure, Context, Result};
+
+/// The toolchains we know by name, as a substring of a producer (checked in
+/// order) and the toolchain's name in the report.
+const KNOWN: &[(&str, &str)] = &[
+    ("emscripten", "Emscripten"),
+    ("tinygo", "TinyGo"),
+    ("wasm-bindgen", "Rust/wasm-bindgen"),
+    ("rust", "Rust"),
+    ("assemblyscript", "AssemblyScript"),
+    ("clang", "C/C++ (clang)"),
+];
+
+/// The name of the toolchain that produced `wasm`.
+pub fn toolchain(wasm: &[u8]) -> String {
+    let producers = match producers(wasm) {
+        Ok(producers) => producers,
+        Err(e) => {
+            log::debug!("Failed to read the producers section: {:#}", e);
+            return "unknown".to_string();
+        }
+    };
+    for (needle, name) in KNOWN {
+        if producers
+            .iter()
+            .any(|(_, producer)| producer.to_lowercase().contains(needle))
+        {
+            return name.to_string();
+        }
+    }
+    if producers.is_empty() {
+        "unknown".to_string()
+    } else {
+        let names: Vec<_> = producers.into_iter().map(|(_, name)| name).collect();
+        names.join("+")
+    }
+}
+
+/// The field (`language`, `processed-by`, or `sdk`) and name of every language,
+/// tool, and SDK in the `producers` section of `wasm`.
+pub fn producers(wasm: &[u8]) -> Result<Vec<(String, String)>> {
+    ensure!(
+        wasm.len() >= 8 && wasm.starts_with(b"\0asm"),
+        "not a Wasm module"
+    );
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_u32(wasm, &mut pos)? as usize;
+        let end = pos
+            .checked_add(size)
+            .filter(|end| *end <= wasm.len())
+            .context("section extends past the end of the module")?;
+        if id == 0 && read_name(&wasm[..end], &mut pos)? == "producers" {
+            let section = &wasm[..end];
+            let mut names = vec![];
+            for _ in 0..read_u32(section, &mut pos)? {
+                let field = read_name(section, &mut pos)?.to_string();
+                for _ in 0..read_u32(section, &mut pos)? {
+                    let name = read_name(section, &mut pos)?.to_string();
+                    names.push((field.clone(), name));
+                    let _version = read_name(section, &mut pos)?;
+                }
+            }
+            return Ok(names);
+        }
+        pos = end;
+    }
+    Ok(vec![])
+}
+
+/// Read a length-prefixed UTF-8 string at `*pos` in `bytes`, advancing `*pos`
+/// past it.
+fn read_name<'a>(bytes: &'a [u8], pos: &mut usize) -> Result<&'a str> {
+    let len = read_u32(bytes, pos)? as usize;
+    let end = pos
+        .checked_add(len)
+        .filter(|end| *end <= bytes.len())
+        .context("name extends past the end of its section")?;
+    let name = std::str::from_utf8(&bytes[*pos..end]).context("name is not UTF-8")?;
+    *pos = end;
+    Ok(name)
+}
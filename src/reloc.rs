@@ -0,0 +1,235 @@
+//! Relocatable Wasm object files, like the `.o` files that `clang` and `rustc`
+//! produce and static libraries bundle, for `--relocatable`.
+//!
+//! An object's `linking` custom section has the symbol table naming its
+//! functions, and its `reloc.*` custom sections list the immediates that the
+//! linker rewrites: function, global, and type indices, memory addresses, and
+//! table indices. The same function compiled into two objects differs only in
+//! those immediates, so items are keyed with them zeroed.
+
+use crate::dedupe::read_u32;
+use anyhow::{bail, ensure, Context, Result};
+use std::{collections::HashMap, ops::Range};
+
+/// The symbol table subsection of the `linking` section.
+const SYMBOL_TABLE: u8 = 8;
+
+/// The symbol kinds of the symbol table.
+const SYMBOL_FUNCTION: u8 = 0;
+const SYMBOL_DATA: u8 = 1;
+const SYMBOL_GLOBAL: u8 = 2;
+const SYMBOL_SECTION: u8 = 3;
+const SYMBOL_TAG: u8 = 4;
+const SYMBOL_TABLE_KIND: u8 = 5;
+
+/// The symbol flags that say whether a symbol is only referenced rather than
+/// defined, and whether an undefined symbol still has a name.
+const FLAG_UNDEFINED: u32 = 0x10;
+const FLAG_EXPLICIT_NAME: u32 = 0x40;
+
+/// The relocations and symbols of an object file.
+pub struct Object {
+    /// The range of every relocated immediate, by offset in the file, sorted.
+    patches: Vec<Range<usize>>,
+    /// The name of every defined function symbol, by function index.
+    functions: HashMap<u32, String>,
+}
+
+impl Object {
+    /// Parse the relocations and symbols of `wasm`, if it's an object file with
+    /// a `linking` section.
+    pub fn parse(wasm: &[u8]) -> Result<Option<Self>> {
+        ensure!(wasm.len() >= 8, "module is too short");
+        let mut linking = None;
+        let mut relocs = vec![];
+        // The offset of the contents of each section, by index.
+        let mut sections = vec![];
+
+        let mut pos = 8;
+        while pos < wasm.len() {
+            let id = wasm[pos];
+            pos += 1;
+            let size = read_u32(wasm, &mut pos)? as usize;
+            let end = pos + size;
+            ensure!(
+                end <= wasm.len(),
+                "section extends past the end of the module"
+            );
+            sections.push(pos);
+            if id == 0 {
+                let mut name_pos = pos;
+                let name = read_name(&wasm[..end], &mut name_pos)?;
+                if name == "linking" {
+                    linking = Some(&wasm[name_pos..end]);
+                } else if name.starts_with("reloc.") {
+                    relocs.push(&wasm[name_pos..end]);
+                }
+            }
+            pos = end;
+        }
+
+        let linking = match linking {
+            Some(linking) => linking,
+            None => return Ok(None),
+        };
+        let mut patches = vec![];
+        for reloc in relocs {
+            add_patches(reloc, &sections, &mut patches)?;
+        }
+        patches.sort_by_key(|patch| (patch.start, patch.end));
+        Ok(Some(Object {
+            patches,
+            functions: function_symbols(linking)?,
+        }))
+    }
+
+    /// Write the bytes of the item at `range` of the file into `key`, with its
+    /// relocated immediates zeroed.
+    pub fn key(&self, wasm: &[u8], range: Range<usize>, key: &mut Vec<u8>) {
+        key.extend_from_slice(&wasm[range.clone()]);
+        let first = self
+            .patches
+            .partition_point(|patch| patch.end <= range.start);
+        for patch in &self.patches[first..] {
+            if patch.start >= range.end {
+                break;
+            }
+            let start = patch.start.max(range.start) - range.start;
+            let end = patch.end.min(range.end) - range.start;
+            key[start..end].fill(0);
+        }
+    }
+
+    /// The name of the symbol of the defined function at `index`, if any.
+    pub fn function_name(&self, index: u32) -> Option<&str> {
+        self.functions.get(&index).map(|name| name.as_str())
+    }
+}
+
+/// Add the ranges of the immediates that the `reloc.*` section `data`
+/// relocates to `patches`, given the offset of the contents of each section.
+fn add_patches(data: &[u8], sections: &[usize], patches: &mut Vec<Range<usize>>) -> Result<()> {
+    let mut pos = 0;
+    let section = read_u32(data, &mut pos)? as usize;
+    let base = *sections
+        .get(section)
+        .with_context(|| format!("relocations for unknown section {}", section))?;
+    let count = read_u32(data, &mut pos)?;
+    for _ in 0..count {
+        let ty = *data.get(pos).context("unexpected end of relocations")?;
+        pos += 1;
+        let offset = read_u32(data, &mut pos)? as usize;
+        read_u32(data, &mut pos)?;
+        let (width, addend) = match ty {
+            // `R_WASM_FUNCTION_INDEX_LEB`, `R_WASM_TABLE_INDEX_SLEB`,
+            // `R_WASM_TYPE_INDEX_LEB`, `R_WASM_GLOBAL_INDEX_LEB`,
+            // `R_WASM_TAG_INDEX_LEB`, `R_WASM_TABLE_INDEX_REL_SLEB`, and
+            // `R_WASM_TABLE_NUMBER_LEB`.
+            0 | 1 | 6 | 7 | 10 | 12 | 20 => (5, false),
+            // `R_WASM_MEMORY_ADDR_LEB`, `R_WASM_MEMORY_ADDR_SLEB`,
+            // `R_WASM_MEMORY_ADDR_REL_SLEB`, and `R_WASM_MEMORY_ADDR_TLS_SLEB`.
+            3 | 4 | 11 | 21 => (5, true),
+            // `R_WASM_TABLE_INDEX_I32`, `R_WASM_GLOBAL_INDEX_I32`, and
+            // `R_WASM_FUNCTION_INDEX_I32`.
+            2 | 13 | 26 => (4, false),
+            // `R_WASM_MEMORY_ADDR_I32`, `R_WASM_FUNCTION_OFFSET_I32`,
+            // `R_WASM_SECTION_OFFSET_I32`, and `R_WASM_MEMORY_ADDR_LOCREL_I32`.
+            5 | 8 | 9 | 23 => (4, true),
+            // `R_WASM_TABLE_INDEX_SLEB64` and `R_WASM_TABLE_INDEX_REL_SLEB64`.
+            18 | 24 => (10, false),
+            // `R_WASM_MEMORY_ADDR_LEB64`, `R_WASM_MEMORY_ADDR_SLEB64`,
+            // `R_WASM_MEMORY_ADDR_REL_SLEB64`, and
+            // `R_WASM_MEMORY_ADDR_TLS_SLEB64`.
+            14 | 15 | 17 | 25 => (10, true),
+            // `R_WASM_TABLE_INDEX_I64`.
+            19 => (8, false),
+            // `R_WASM_MEMORY_ADDR_I64` and `R_WASM_FUNCTION_OFFSET_I64`.
+            16 | 22 => (8, true),
+            _ => bail!("unknown relocation type {}", ty),
+        };
+        if addend {
+            skip_leb(data, &mut pos)?;
+        }
+        patches.push(base + offset..base + offset + width);
+    }
+    Ok(())
+}
+
+/// The name of every defined function symbol of the `linking` section `data`,
+/// by function index.
+fn function_symbols(data: &[u8]) -> Result<HashMap<u32, String>> {
+    let mut functions = HashMap::new();
+    let mut pos = 0;
+    let version = read_u32(data, &mut pos)?;
+    ensure!(
+        version == 2,
+        "unsupported linking section version {}",
+        version
+    );
+    while pos < data.len() {
+        let id = data[pos];
+        pos += 1;
+        let size = read_u32(data, &mut pos)? as usize;
+        let contents = data
+            .get(pos..pos + size)
+            .context("linking subsection extends past the end of the section")?;
+        pos += size;
+        if id != SYMBOL_TABLE {
+            continue;
+        }
+
+        let mut pos = 0;
+        let count = read_u32(contents, &mut pos)?;
+        for _ in 0..count {
+            let kind = *contents.get(pos).context("unexpected end of symbols")?;
+            pos += 1;
+            let flags = read_u32(contents, &mut pos)?;
+            let defined = flags & FLAG_UNDEFINED == 0;
+            match kind {
+                SYMBOL_FUNCTION | SYMBOL_GLOBAL | SYMBOL_TAG | SYMBOL_TABLE_KIND => {
+                    let index = read_u32(contents, &mut pos)?;
+                    if defined || flags & FLAG_EXPLICIT_NAME != 0 {
+                        let name = read_name(contents, &mut pos)?;
+                        if kind == SYMBOL_FUNCTION && defined {
+                            functions.entry(index).or_insert_with(|| name.to_string());
+                        }
+                    }
+                }
+                SYMBOL_DATA => {
+                    read_name(contents, &mut pos)?;
+                    if defined {
+                        for _ in 0..3 {
+                            read_u32(contents, &mut pos)?;
+                        }
+                    }
+                }
+                SYMBOL_SECTION => {
+                    read_u32(contents, &mut pos)?;
+                }
+                _ => bail!("unknown symbol kind {}", kind),
+            }
+        }
+    }
+    Ok(functions)
+}
+
+fn read_name<'a>(bytes: &'a [u8], pos: &mut usize) -> Result<&'a str> {
+    let len = read_u32(bytes, pos)? as usize;
+    let name = bytes
+        .get(*pos..*pos + len)
+        .context("name extends past the end of its section")?;
+    *pos += len;
+    std::str::from_utf8(name).context("name is not UTF-8")
+}
+
+/// Skip a signed or unsigned LEB128 of up to 64 bits.
+fn skip_leb(bytes: &[u8], pos: &mut usize) -> Result<()> {
+    for _ in 0..10 {
+        let byte = *bytes.get(*pos).context("unexpected end of relocations")?;
+        *pos += 1;
+        if byte & 0x80 == 0 {
+            return Ok(());
+        }
+    }
+    bail!("invalid LEB128")
+}
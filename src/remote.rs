@@ -0,0 +1,104 @@
+//! Downloading the Wasm files listed in a `--manifest` of URLs.
+
+use crate::Selection;
+use anyhow::{bail, ensure, Context, Result};
+use rayon::prelude::*;
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Download every URL listed in the `manifest` file, one per line, into `dir`,
+/// `jobs` at a time, and return the paths of the downloaded files in the order
+/// of the manifest. Files that were already downloaded into `dir` by a
+/// previous run aren't downloaded again. Only the URLs in `selection` are
+/// downloaded.
+pub fn download(
+    manifest: &Path,
+    dir: &Path,
+    jobs: usize,
+    selection: &Selection,
+) -> Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string(manifest)
+        .with_context(|| format!("failed to read {}", manifest.display()))?;
+    let urls: Vec<&str> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter(|url| selection.contains(url))
+        .collect();
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    pool.install(|| {
+        urls.par_iter()
+            .map(|url| {
+                let path = local_path(dir, url)?;
+                if !path.exists() {
+                    fetch(url, &path).with_context(|| format!("failed to download {}", url))?;
+                }
+                Ok(path)
+            })
+            .collect()
+    })
+}
+
+/// Where to download `url` to in `dir`: `<dir>/<host>/<path>`, with a `.wasm`
+/// extension so that it looks like any other Wasm file.
+fn local_path(dir: &Path, url: &str) -> Result<PathBuf> {
+    let rest = match url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        Some(rest) => rest,
+        None => bail!("not an HTTP(S) URL: {}", url),
+    };
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, query)) => (rest, Some(query)),
+        None => (rest, None),
+    };
+    let mut path = dir.to_path_buf();
+    for component in rest.split('/').filter(|c| !c.is_empty()) {
+        ensure!(
+            component != "." && component != ".." && !component.contains('\\'),
+            "unsupported URL path: {}",
+            url
+        );
+        path.push(component);
+    }
+    let mut name = path
+        .file_name()
+        .context("URL has no path")?
+        .to_string_lossy()
+        .into_owned();
+    // Different query strings may name different files.
+    if let Some(query) = query {
+        name = format!(
+            "{}-{}",
+            name,
+            &blake3::hash(query.as_bytes()).to_hex()[..16]
+        );
+    }
+    if !name.ends_with(".wasm") {
+        name.push_str(".wasm");
+    }
+    path.set_file_name(name);
+    Ok(path)
+}
+
+fn fetch(url: &str, path: &Path) -> Result<()> {
+    log::info!("Downloading {}", url);
+    let mut bytes = vec![];
+    ureq::get(url)
+        .call()?
+        .into_reader()
+        .read_to_end(&mut bytes)?;
+
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    // Write to a temporary file first so that an interrupted download isn't
+    // mistaken for a complete one by the next run.
+    let tmp = path.with_extension("wasm.tmp");
+    fs::write(&tmp, bytes).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
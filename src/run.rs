@@ -0,0 +1,166 @@
+//! The provenance of a run, embedded in the machine-readable outputs so that
+//! archived measurements say what produced them: the tool's version and
+//! options, what was measured and when, and on which host.
+
+use crate::{Command, Counts, Options, Stats};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// When the run started, and its command-line arguments.
+pub struct Started {
+    time: SystemTime,
+    args: Vec<String>,
+}
+
+impl Started {
+    /// The start of the run, now, with the command-line arguments `args`,
+    /// including the program's name.
+    pub fn new(args: &[OsString]) -> Self {
+        let args = args
+            .iter()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        Started {
+            time: SystemTime::now(),
+            args,
+        }
+    }
+}
+
+impl Default for Started {
+    fn default() -> Self {
+        Started::new(&[])
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Run {
+    pub version: String,
+    /// The command-line arguments, with those from the `--config` file.
+    pub args: Vec<String>,
+    /// The corpus directories, `--manifest`, or indexes that were measured,
+    /// including those given to the subcommand.
+    pub corpus: Vec<String>,
+    pub files: usize,
+    /// UTC timestamps in the RFC 3339 format.
+    pub started_at: String,
+    pub finished_at: String,
+    pub host: Host,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Host {
+    pub hostname: Option<String>,
+    pub os: String,
+    pub arch: String,
+    pub cpus: usize,
+}
+
+impl Run {
+    /// The provenance of the run that measured `counts` with `options`,
+    /// finishing now.
+    pub fn new(counts: &Counts, options: &Options) -> Self {
+        let started = &options.started;
+        Run {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            args: started.args.clone(),
+            corpus: corpus(options)
+                .map(|path| path.display().to_string())
+                .collect(),
+            files: counts.files.len(),
+            started_at: timestamp(started.time),
+            finished_at: timestamp(SystemTime::now()),
+            host: Host {
+                hostname: hostname(),
+                os: std::env::consts::OS.to_string(),
+                arch: std::env::consts::ARCH.to_string(),
+                cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            },
+        }
+    }
+
+    /// Write the provenance of an output file at `path` that can't embed it,
+    /// such as a CSV file, next to it, as JSON in `<path>.run.json`.
+    pub fn write_next_to(&self, path: &Path) -> Result<()> {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".run.json");
+        #[derive(Serialize)]
+        struct Sidecar<'a> {
+            schema_version: u32,
+            #[serde(flatten)]
+            run: &'a Run,
+        }
+        let json = serde_json::to_string_pretty(&Sidecar {
+            schema_version: 1,
+            run: self,
+        })?;
+        std::fs::write(&sidecar, json)
+            .with_context(|| format!("failed to write {}", Path::new(&sidecar).display()))
+    }
+}
+
+/// The paths of what the run measured: the corpus or `--manifest`, or else
+/// the subcommand's corpora, or its indexes for `merge-index`.
+fn corpus(options: &Options) -> impl Iterator<Item = &PathBuf> {
+    let subcommand: Vec<&PathBuf> = match &options.command {
+        None => vec![],
+        Some(Command::Dedupe { corpus, .. })
+        | Some(Command::MergeFunctions { corpus, .. })
+        | Some(Command::Serve { corpus, .. })
+        | Some(Command::Stats {
+            stats: Stats::Opcodes { corpus, .. },
+        }) => vec![corpus],
+        Some(Command::Overlap { base, candidate }) => vec![base, candidate],
+        Some(Command::Diff { old, new }) => vec![old, new],
+        Some(Command::MergeIndex { indexes, .. }) => indexes.iter().collect(),
+    };
+    options
+        .corpus
+        .iter()
+        .chain(&options.manifest)
+        .chain(subcommand)
+}
+
+fn hostname() -> Option<String> {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Format `time` as an RFC 3339 timestamp in UTC, like
+/// `2021-11-30T17:04:05Z`.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Howard Hinnant's `civil_from_days`, for days since 1970.
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
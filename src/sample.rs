@@ -0,0 +1,115 @@
+//! Measuring a random sample of a corpus's files, for `--sample`, and
+//! extrapolating the totals of the whole corpus from it.
+//!
+//! Every file is in the sample with the same probability, decided by a hash of
+//! `--seed` and its path within the corpus, or its URL in a `--manifest`, so
+//! the same seed picks the same files again. Sums over files, like the total
+//! size and the bytes duplicated within modules, are estimated by scaling the
+//! sample up, with the variance of that estimate under this kind of sampling.
+//! Duplication across modules doesn't scale that way: an item in two files of
+//! the corpus is rarely in two files of the sample, so scaling the sample's
+//! only gives a lower bound.
+
+use anyhow::{ensure, Context, Result};
+use std::str::FromStr;
+
+/// The z-score of a 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+/// The fraction of files to measure.
+#[derive(Clone, Copy, Debug)]
+pub struct Fraction(pub f64);
+
+impl FromStr for Fraction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fraction = match s.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+            None => s.trim().parse::<f64>(),
+        }
+        .with_context(|| format!("invalid sample size: {}", s))?;
+        ensure!(
+            fraction > 0.0 && fraction <= 1.0,
+            "the sample must be more than 0% and at most 100% of the files"
+        );
+        Ok(Fraction(fraction))
+    }
+}
+
+/// A seeded random sample of files.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub fraction: f64,
+    pub seed: u64,
+}
+
+impl Sample {
+    /// Whether the file named `name`, its path within the corpus or its URL,
+    /// is in the sample.
+    pub fn contains(&self, name: &str) -> bool {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.seed.to_le_bytes());
+        hasher.update(name.as_bytes());
+        let hash = hasher.finalize();
+        let hash = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        (hash as f64) < self.fraction * u64::MAX as f64
+    }
+}
+
+/// The sums over the sampled files that the corpus's totals are extrapolated
+/// from.
+#[derive(Clone, Copy, Default)]
+pub struct Sums {
+    pub files: u64,
+    size: Sum,
+    within_modules: Sum,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Sum {
+    total: f64,
+    squares: f64,
+}
+
+impl Sum {
+    fn add(&mut self, value: u64) {
+        let value = value as f64;
+        self.total += value;
+        self.squares += value * value;
+    }
+
+    /// The estimated sum over the whole corpus and the half-width of its 95%
+    /// confidence interval.
+    fn estimate(&self, fraction: f64) -> (f64, f64) {
+        let variance = (1.0 - fraction) / (fraction * fraction) * self.squares;
+        (self.total / fraction, Z_95 * variance.sqrt())
+    }
+}
+
+impl Sums {
+    /// Add a sampled file of `size` bytes, `within_modules` of which are
+    /// duplicated within it.
+    pub fn add(&mut self, size: u64, within_modules: u64) {
+        self.files += 1;
+        self.size.add(size);
+        self.within_modules.add(within_modules);
+    }
+
+    /// The estimated number of files in the corpus, and the half-width of its
+    /// 95% confidence interval.
+    pub fn files(&self, fraction: f64) -> (f64, f64) {
+        let mut files = Sum::default();
+        files.total = self.files as f64;
+        files.squares = self.files as f64;
+        files.estimate(fraction)
+    }
+
+    pub fn size(&self, fraction: f64) -> (f64, f64) {
+        self.size.estimate(fraction)
+    }
+
+    pub fn within_modules(&self, fraction: f64) -> (f64, f64) {
+        self.within_modules.estimate(fraction)
+    }
+}
@@ -0,0 +1,86 @@
+//! The sizes of the sections of a module, for `--section-sizes`.
+
+use crate::{component, dedupe::read_u32};
+use anyhow::{ensure, Context, Result};
+
+/// The name and size, including its id and size, of each section of `wasm`,
+/// a module or a component, in order. Custom sections are named by their own
+/// names.
+pub fn sizes(wasm: &[u8]) -> Result<Vec<(String, u64)>> {
+    ensure!(
+        wasm.len() >= 8 && wasm.starts_with(b"\0asm"),
+        "not a Wasm module"
+    );
+    let name = if component::is_component(wasm) {
+        component_name
+    } else {
+        name
+    };
+    let mut sections = vec![];
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let start = pos;
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_u32(wasm, &mut pos)? as usize;
+        let end = pos
+            .checked_add(size)
+            .filter(|end| *end <= wasm.len())
+            .context("section extends past the end of the module")?;
+        let name = match id {
+            0 => {
+                let name_len = read_u32(&wasm[..end], &mut pos)? as usize;
+                ensure!(pos + name_len <= end, "custom section name is too long");
+                format!(
+                    "custom {:?}",
+                    String::from_utf8_lossy(&wasm[pos..pos + name_len])
+                )
+            }
+            _ => name(id).to_string(),
+        };
+        sections.push((name, (end - start) as u64));
+        pos = end;
+    }
+    Ok(sections)
+}
+
+/// The name of the non-custom section with the given `id`.
+fn name(id: u8) -> &'static str {
+    match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "data count",
+        13 => "tag",
+        14 => "module",
+        15 => "instance",
+        16 => "alias",
+        _ => "unknown",
+    }
+}
+
+/// The name of the non-custom section of a component with the given `id`.
+fn component_name(id: u8) -> &'static str {
+    match id {
+        1 => "core module",
+        2 => "core instance",
+        3 => "core type",
+        4 => "component",
+        5 => "instance",
+        6 => "alias",
+        7 => "type",
+        8 => "canon",
+        9 => "start",
+        10 => "import",
+        11 => "export",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,234 @@
+//! The `serve` subcommand, which serves a browsable HTML report of the
+//! measurements over HTTP.
+//!
+//! * `/` summarizes the corpus and lists its most duplicated items and its
+//!   files.
+//! * `/file/<index>` lists the duplicated items in one file.
+//! * `/item/<category>/<hash>` lists the files containing one item.
+
+use crate::{hex, percent, Category, Counts, SizeEntry, WideHash};
+use anyhow::{Context, Result};
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+};
+
+/// The number of items to list per category on the summary page.
+const TOP_ITEMS: usize = 20;
+
+/// Serve the report of `counts` on `port` until the process is killed.
+///
+/// Requires the files of every item to be tracked.
+pub fn run(counts: &Counts, port: u16) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .with_context(|| format!("failed to listen on port {}", port))?;
+    println!("Serving the report at http://127.0.0.1:{}/", port);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle(counts, stream) {
+            log::warn!("Failed to serve a request: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Respond to the one request on `stream`.
+fn handle(counts: &Counts, mut stream: TcpStream) -> Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    log::info!("Serving {}", path);
+
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let page = match segments.as_slice() {
+        [""] => Some(summary(counts)?),
+        ["file", index] => index
+            .parse::<usize>()
+            .ok()
+            .filter(|index| *index < counts.files.len())
+            .map(|index| file(counts, index))
+            .transpose()?,
+        ["item", category, hash] => find_item(counts, category, hash)
+            .map(|(category, hash, entry)| item(counts, category, hash, entry))
+            .transpose()?,
+        _ => None,
+    };
+
+    let (status, body) = match page {
+        Some(page) => ("200 OK", page),
+        None => ("404 Not Found", html("Not found", "<p>No such page.</p>")),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Find the item that `/item/<category>/<hash>` refers to.
+fn find_item<'a>(
+    counts: &'a Counts,
+    category: &str,
+    hash: &str,
+) -> Option<(Category, &'a WideHash, &'a SizeEntry)> {
+    let category = category.parse::<u8>().ok()?;
+    let (category, items) = counts.items.iter().find(|(c, _)| **c as u8 == category)?;
+    let (hash, entry) = items.iter().find(|(h, _)| hex(h) == hash)?;
+    Some((*category, hash, entry))
+}
+
+fn item_link(category: Category, hash: &WideHash) -> String {
+    let hash = hex(hash);
+    format!(
+        "<a href=\"/item/{}/{}\"><code>{}</code></a>",
+        category as u8,
+        hash,
+        &hash[..16]
+    )
+}
+
+fn file_link(counts: &Counts, index: usize) -> String {
+    format!(
+        "<a href=\"/file/{}\">{}</a>",
+        index,
+        escape(&counts.files[index].display().to_string())
+    )
+}
+
+fn summary(counts: &Counts) -> Result<String> {
+    let mut body = String::new();
+    writeln!(
+        body,
+        "<p>{} files, {} bytes.</p>",
+        counts.files.len(),
+        counts.total_size
+    )?;
+
+    body.push_str(
+        "<table><tr><th>Category</th><th>Within modules</th><th>Across modules</th></tr>",
+    );
+    for category in counts.items.keys() {
+        let dupe = counts.duplicated(*category);
+        writeln!(
+            body,
+            "<tr><td>{}</td><td>{} bytes ({:.02}%)</td><td>{} bytes ({:.02}%)</td></tr>",
+            category.name(),
+            dupe.within_modules,
+            percent(dupe.within_modules, counts.total_size),
+            dupe.across_modules,
+            percent(dupe.across_modules, counts.total_size)
+        )?;
+    }
+    body.push_str("</table>");
+
+    for category in counts.items.keys() {
+        let dupes = counts.duplicated_items(*category, 0);
+        if dupes.is_empty() {
+            continue;
+        }
+        writeln!(body, "<h2>Most duplicated {}</h2>", category.name())?;
+        body.push_str(
+            "<table><tr><th>Item</th><th>Size</th><th>Copies</th><th>Modules</th>\
+             <th>Redundant bytes</th></tr>",
+        );
+        for (hash, entry) in dupes.into_iter().take(TOP_ITEMS) {
+            writeln!(
+                body,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                item_link(*category, hash),
+                entry.size,
+                entry.count,
+                entry.modules,
+                entry.size * (entry.count - 1)
+            )?;
+        }
+        body.push_str("</table>");
+    }
+
+    body.push_str("<h2>Files</h2><table><tr><th>File</th><th>Size</th></tr>");
+    for (index, size) in counts.file_sizes.iter().enumerate() {
+        writeln!(
+            body,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            file_link(counts, index),
+            size
+        )?;
+    }
+    body.push_str("</table>");
+    Ok(html("Wasm dedupe wins", &body))
+}
+
+fn file(counts: &Counts, index: usize) -> Result<String> {
+    let mut body = String::new();
+    writeln!(body, "<p>{} bytes.</p>", counts.file_sizes[index])?;
+    body.push_str(
+        "<p>The duplicated items in this file:</p><table><tr><th>Category</th><th>Item</th>\
+         <th>Size</th><th>Copies</th><th>Modules</th></tr>",
+    );
+    for (category, items) in &counts.items {
+        let mut dupes: Vec<_> = items
+            .iter()
+            .filter(|(_, entry)| entry.count > 1 && entry.files.contains(&index))
+            .collect();
+        dupes.sort_by_key(|(hash, entry)| (std::cmp::Reverse(entry.size), *hash));
+        for (hash, entry) in dupes {
+            writeln!(
+                body,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                category.name(),
+                item_link(*category, hash),
+                entry.size,
+                entry.count,
+                entry.modules
+            )?;
+        }
+    }
+    body.push_str("</table>");
+    Ok(html(&counts.files[index].display().to_string(), &body))
+}
+
+fn item(counts: &Counts, category: Category, hash: &WideHash, entry: &SizeEntry) -> Result<String> {
+    let mut body = String::new();
+    writeln!(
+        body,
+        "<p>{} bytes, {} copies in {} modules.</p><p>Contained in:</p><ul>",
+        entry.size, entry.count, entry.modules
+    )?;
+    for file in &entry.files {
+        writeln!(body, "<li>{}</li>", file_link(counts, *file))?;
+    }
+    body.push_str("</ul>");
+    Ok(html(
+        &format!("{} {}", category.name(), hex(&hash[..8])),
+        &body,
+    ))
+}
+
+/// Wrap `body` in a page titled `title`.
+fn html(title: &str, body: &str) -> String {
+    let title = escape(title);
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title>\
+         <style>body {{ font-family: sans-serif; }} td, th {{ padding: 0 1em; text-align: left; }}\
+         </style></head><body><p><a href=\"/\">Summary</a></p><h1>{0}</h1>{1}</body></html>",
+        title, body
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
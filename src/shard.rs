@@ -0,0 +1,46 @@
+//! Splitting a corpus into shards for `--shard`, so that separate machines can
+//! each measure one and export its index, to be combined with `merge-index`.
+//!
+//! Files are assigned to shards by a hash of their path within the corpus, or
+//! of their URL in a `--manifest`, so that every worker agrees on the split
+//! without coordinating, and a file stays in its shard as the corpus grows.
+
+use anyhow::{ensure, Context, Result};
+use std::str::FromStr;
+
+/// One of `count` shards of a corpus, numbered from 1.
+#[derive(Clone, Copy, Debug)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl FromStr for Shard {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (index, count) = s.split_once('/').context("expected a shard like `3/8`")?;
+        let index = index
+            .trim()
+            .parse::<u64>()
+            .context("invalid shard number")?;
+        let count = count.trim().parse::<u64>().context("invalid shard count")?;
+        ensure!(count > 0, "the shard count must be at least 1");
+        ensure!(
+            (1..=count).contains(&index),
+            "the shard number must be between 1 and {}",
+            count
+        );
+        Ok(Shard { index, count })
+    }
+}
+
+impl Shard {
+    /// Whether the file named `name`, its path within the corpus or its URL,
+    /// is in this shard.
+    pub fn contains(&self, name: &str) -> bool {
+        let hash = blake3::hash(name.as_bytes());
+        let hash = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        hash % self.count == self.index - 1
+    }
+}
@@ -0,0 +1,258 @@
+//! Generating a candidate "shared runtime" module, in WAT, out of the most
+//! duplicated code bodies, data segments, and element segments.
+//!
+//! The items are copied as-is, so any function, global, memory, table, or type
+//! indices inside them still refer to the index spaces of a module they came
+//! from. The module is meant to be read and discussed, not instantiated.
+
+use crate::{dedupe::write_u32, hash, Category, Counts, Hasher, ModuleState, WideHash};
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+
+/// The categories that go into the shared module, and the WAT keyword
+/// introducing each of their items.
+const CATEGORIES: &[(Category, &str)] = &[
+    (Category::CodeBodies, "(func "),
+    (Category::ElemSegments, "(elem "),
+    (Category::DataSegments, "(data "),
+];
+
+/// An item found in a module of the corpus.
+struct Found {
+    bytes: Vec<u8>,
+    /// For code bodies, the encoded function type.
+    ty: Vec<u8>,
+}
+
+/// Build a WAT module out of the `limit` most redundant items of each category
+/// in `CATEGORIES`, with a comment before every item saying how many modules of
+/// the corpus would import it.
+///
+/// Requires the files of every item to be tracked.
+pub fn wat(counts: &Counts, limit: usize) -> Result<String> {
+    let mut wanted: Vec<(Category, &WideHash, &crate::SizeEntry)> = vec![];
+    for (category, _) in CATEGORIES {
+        wanted.extend(
+            counts
+                .duplicated_items(*category, 0)
+                .into_iter()
+                .take(limit)
+                .map(|(hash, entry)| (*category, hash, entry)),
+        );
+    }
+
+    // Find each item in the first file that contains it.
+    let mut by_file: HashMap<usize, HashSet<(Category, &WideHash)>> = HashMap::new();
+    for (category, hash, entry) in &wanted {
+        // The files of items may have stopped being tracked with
+        // `--max-memory`.
+        let file = match entry.files.first() {
+            Some(file) => *file,
+            None => continue,
+        };
+        by_file.entry(file).or_default().insert((*category, *hash));
+    }
+    let mut found = HashMap::new();
+    let mut wasm = vec![];
+    for (file, items) in &by_file {
+        let path = &counts.files[*file];
+        counts.load(path, &mut wasm)?;
+        find(&wasm, items, &counts.hasher, &mut found)
+            .with_context(|| format!("failed to find shared items in {}", path.display()))?;
+    }
+
+    let mut types = vec![];
+    let mut funcs = vec![];
+    let mut code = vec![];
+    let mut elems = vec![];
+    let mut data = vec![];
+    let mut annotations: HashMap<Category, Vec<String>> = HashMap::new();
+    for (category, hash, entry) in &wanted {
+        let item = match found.get(&(*category, (*hash).clone())) {
+            Some(item) => item,
+            None => continue,
+        };
+        if *category == Category::CodeBodies && item.ty.is_empty() {
+            // We couldn't tell what type the function has.
+            continue;
+        }
+        annotations.entry(*category).or_default().push(format!(
+            ";; imported by {} modules ({} copies, {} bytes each)",
+            entry.modules, entry.count, entry.size
+        ));
+        match category {
+            Category::CodeBodies => {
+                funcs.push(types.len() as u32);
+                types.push(&item.ty);
+                write_u32(&mut code, item.bytes.len() as u32);
+                code.extend_from_slice(&item.bytes);
+            }
+            Category::ElemSegments => elems.push(&item.bytes),
+            Category::DataSegments => data.push(&item.bytes),
+            _ => unreachable!(),
+        }
+    }
+
+    let mut module = wasm_encoder::Module::new();
+    let mut section = |id: u8, count: usize, contents: &mut dyn Iterator<Item = &[u8]>| {
+        if count > 0 {
+            let mut bytes = vec![];
+            write_u32(&mut bytes, count as u32);
+            contents.for_each(|item| bytes.extend_from_slice(item));
+            module.section(&wasm_encoder::RawSection { id, data: &bytes });
+        }
+    };
+    let mut func_indices = vec![];
+    for ty in &funcs {
+        write_u32(&mut func_indices, *ty);
+    }
+    section(1, types.len(), &mut types.iter().map(|ty| ty.as_slice()));
+    section(
+        3,
+        funcs.len(),
+        &mut std::iter::once(func_indices.as_slice()),
+    );
+    section(
+        9,
+        elems.len(),
+        &mut elems.iter().map(|elem| elem.as_slice()),
+    );
+    section(10, funcs.len(), &mut std::iter::once(code.as_slice()));
+    section(11, data.len(), &mut data.iter().map(|data| data.as_slice()));
+    let wat = wasmprinter::print_bytes(module.finish())?;
+
+    // Put each item's annotation on the line before it.
+    let mut annotated = String::new();
+    let mut next: HashMap<Category, usize> = HashMap::new();
+    for line in wat.lines() {
+        let trimmed = line.trim_start();
+        for (category, keyword) in CATEGORIES {
+            if trimmed.starts_with(keyword) {
+                let i = next.entry(*category).or_insert(0);
+                if let Some(annotation) = annotations.get(category).and_then(|a| a.get(*i)) {
+                    annotated.push_str(&line[..line.len() - trimmed.len()]);
+                    annotated.push_str(annotation);
+                    annotated.push('\n');
+                }
+                *i += 1;
+            }
+        }
+        annotated.push_str(line);
+        annotated.push('\n');
+    }
+    Ok(annotated)
+}
+
+/// Find the `wanted` items of the module `wasm`, and add them to `found`.
+fn find(
+    wasm: &[u8],
+    wanted: &HashSet<(Category, &WideHash)>,
+    hasher: &Hasher,
+    found: &mut HashMap<(Category, WideHash), Found>,
+) -> Result<()> {
+    let mut input = wasm;
+    let mut parsers = vec![wasmparser::Parser::new(0)];
+    let mut states = vec![ModuleState::default()];
+    let mut func_types: Vec<Vec<Vec<u8>>> = vec![vec![]];
+    while !parsers.is_empty() {
+        let (payload, consumed) = match parsers.last_mut().unwrap().parse(input, true)? {
+            wasmparser::Chunk::NeedMoreData(_) => unreachable!(),
+            wasmparser::Chunk::Parsed { consumed, payload } => (payload, consumed),
+        };
+        input = &input[consumed..];
+
+        let mut add = |category: Category, bytes: &[u8], ty: Vec<u8>| {
+            let hash = hash(bytes, hasher);
+            if wanted.contains(&(category, &hash)) {
+                found.entry((category, hash)).or_insert_with(|| Found {
+                    bytes: bytes.to_vec(),
+                    ty,
+                });
+            }
+        };
+        match payload {
+            wasmparser::Payload::TypeSection(mut reader) => {
+                let types = func_types.last_mut().unwrap();
+                for _ in 0..reader.get_count() {
+                    types.push(match reader.read()? {
+                        wasmparser::TypeDef::Func(ty) => func_type(&ty).unwrap_or_default(),
+                        // Not a function type, so no function can have it.
+                        _ => vec![],
+                    });
+                }
+            }
+            wasmparser::Payload::FunctionSection(mut reader) => {
+                let state = states.last_mut().unwrap();
+                for _ in 0..reader.get_count() {
+                    state.func_types.push(reader.read()?);
+                }
+            }
+            wasmparser::Payload::DataSection(mut reader) => {
+                for _ in 0..reader.get_count() {
+                    let data = reader.read()?;
+                    add(
+                        Category::DataSegments,
+                        &wasm[data.range.start..data.range.end],
+                        vec![],
+                    );
+                }
+            }
+            wasmparser::Payload::ElementSection(mut reader) => {
+                for _ in 0..reader.get_count() {
+                    let elem = reader.read()?;
+                    add(
+                        Category::ElemSegments,
+                        &wasm[elem.range.start..elem.range.end],
+                        vec![],
+                    );
+                }
+            }
+            wasmparser::Payload::CodeSectionEntry(body) => {
+                let state = states.last_mut().unwrap();
+                let ty = state
+                    .func_types
+                    .get(state.code_entries)
+                    .and_then(|ty| func_types.last().unwrap().get(*ty as usize))
+                    .cloned()
+                    .unwrap_or_default();
+                state.code_entries += 1;
+                let range = body.range();
+                add(Category::CodeBodies, &wasm[range.start..range.end], ty);
+            }
+            wasmparser::Payload::ModuleSectionEntry { parser, .. } => {
+                parsers.push(parser);
+                states.push(ModuleState::default());
+                func_types.push(vec![]);
+            }
+            wasmparser::Payload::End => {
+                parsers.pop();
+                states.pop();
+                func_types.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Encode a function type for the type section, if it only uses value types
+/// that we know how to encode.
+fn func_type(ty: &wasmparser::FuncType) -> Option<Vec<u8>> {
+    let mut bytes = vec![0x60];
+    for types in [&ty.params, &ty.returns] {
+        write_u32(&mut bytes, types.len() as u32);
+        for ty in types.iter() {
+            bytes.push(match ty {
+                wasmparser::Type::I32 => 0x7f,
+                wasmparser::Type::I64 => 0x7e,
+                wasmparser::Type::F32 => 0x7d,
+                wasmparser::Type::F64 => 0x7c,
+                wasmparser::Type::V128 => 0x7b,
+                wasmparser::Type::FuncRef => 0x70,
+                wasmparser::Type::ExternRef => 0x6f,
+                _ => return None,
+            });
+        }
+    }
+    Some(bytes)
+}
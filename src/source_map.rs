@@ -0,0 +1,20 @@
+//! Recognizing source maps embedded in custom sections.
+//!
+//! See https://sourcemaps.info/spec.html
+
+/// Whether the custom section `name` with contents `data` holds a source map:
+/// either the `sourceMappingURL` section, whose URL is often a `data:` URL with
+/// the whole map inlined, or a section whose contents are a source map's JSON.
+pub fn is_source_map(name: &str, data: &[u8]) -> bool {
+    if name == "sourceMappingURL" {
+        return true;
+    }
+    let json: Vec<u8> = data
+        .iter()
+        .copied()
+        .skip_while(u8::is_ascii_whitespace)
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .take(12)
+        .collect();
+    json.starts_with(br#"{"version":3"#)
+}
@@ -0,0 +1,57 @@
+//! Splitting modules into separately fetchable resources, to model how much of
+//! a corpus a shared HTTP cache could serve, for `--http-split`.
+
+use crate::{component, dedupe::read_u32, strip};
+use anyhow::{ensure, Context, Result};
+
+/// The resources a module is split into: its code section, its data section,
+/// its debug info custom sections, and everything else.
+#[derive(Default)]
+pub struct Resources {
+    pub code: Vec<u8>,
+    pub data: Vec<u8>,
+    pub debug: Vec<u8>,
+    pub rest: Vec<u8>,
+}
+
+/// Split the module `wasm` into resources, each the concatenation of its
+/// sections in order. Nested modules are left in their parent's sections, so
+/// all of a component but its debug info is in `rest`.
+pub fn resources(wasm: &[u8]) -> Result<Resources> {
+    ensure!(
+        wasm.len() >= 8 && wasm.starts_with(b"\0asm"),
+        "not a Wasm module"
+    );
+    let is_component = component::is_component(wasm);
+    let mut resources = Resources::default();
+    resources.rest.extend_from_slice(&wasm[..8]);
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let start = pos;
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_u32(wasm, &mut pos)? as usize;
+        let end = pos
+            .checked_add(size)
+            .filter(|end| *end <= wasm.len())
+            .context("section extends past the end of the module")?;
+        let resource = match id {
+            0 => {
+                let name_len = read_u32(&wasm[..end], &mut pos)? as usize;
+                ensure!(pos + name_len <= end, "custom section name is too long");
+                if strip::is_debug(&wasm[pos..pos + name_len]) {
+                    &mut resources.debug
+                } else {
+                    &mut resources.rest
+                }
+            }
+            // These are the imports and exports of a component.
+            10 if !is_component => &mut resources.code,
+            11 if !is_component => &mut resources.data,
+            _ => &mut resources.rest,
+        };
+        resource.extend_from_slice(&wasm[start..end]);
+        pos = end;
+    }
+    Ok(resources)
+}
@@ -0,0 +1,133 @@
+//! Writing the measurements to an SQLite database, for SQL-based analysis.
+
+use crate::{hex, run::Run, Category, Counts};
+use anyhow::{Context, Result};
+use rusqlite::params;
+use std::path::Path;
+
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS files;
+    DROP TABLE IF EXISTS items;
+    DROP TABLE IF EXISTS occurrences;
+    DROP TABLE IF EXISTS summary;
+    DROP TABLE IF EXISTS run;
+
+    -- Every Wasm file in the corpus.
+    CREATE TABLE files (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        size INTEGER NOT NULL
+    );
+
+    -- Every distinct item, per category.
+    CREATE TABLE items (
+        id INTEGER PRIMARY KEY,
+        category TEXT NOT NULL,
+        hash TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        -- The number of copies of the item in the whole corpus.
+        count INTEGER NOT NULL,
+        -- The number of files containing the item.
+        modules INTEGER NOT NULL
+    );
+
+    -- Which files contain which items.
+    CREATE TABLE occurrences (
+        item_id INTEGER NOT NULL REFERENCES items (id),
+        file_id INTEGER NOT NULL REFERENCES files (id)
+    );
+
+    -- The numbers in the text summary.
+    CREATE TABLE summary (
+        metric TEXT NOT NULL,
+        value INTEGER NOT NULL
+    );
+
+    -- What produced the measurements: the tool's version, its arguments, and
+    -- the corpus, as JSON arrays, when it ran, and on which host.
+    CREATE TABLE run (
+        key TEXT NOT NULL,
+        value TEXT NOT NULL
+    );
+";
+
+/// Write `counts`, measured by `run`, to a database at `path`, replacing any
+/// tables a previous run wrote there.
+///
+/// Requires the files of every item to be tracked.
+pub fn write(counts: &Counts, path: &Path, run: &Run) -> Result<()> {
+    let mut db = rusqlite::Connection::open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    db.execute_batch(SCHEMA)?;
+
+    let tx = db.transaction()?;
+    {
+        let mut insert = tx.prepare("INSERT INTO files (id, path, size) VALUES (?, ?, ?)")?;
+        for (id, (path, size)) in counts.files.iter().zip(&counts.file_sizes).enumerate() {
+            insert.execute(params![id as i64, path.display().to_string(), *size as i64])?;
+        }
+
+        let mut insert_item = tx.prepare(
+            "INSERT INTO items (id, category, hash, size, count, modules) VALUES (?, ?, ?, ?, ?, ?)",
+        )?;
+        let mut insert_occurrence =
+            tx.prepare("INSERT INTO occurrences (item_id, file_id) VALUES (?, ?)")?;
+        let mut id = 0i64;
+        for category in counts.items.keys() {
+            for (hash, entry) in counts.sorted_items(*category) {
+                insert_item.execute(params![
+                    id,
+                    category.name(),
+                    hex(hash),
+                    entry.size as i64,
+                    entry.count as i64,
+                    entry.modules as i64
+                ])?;
+                for file in &entry.files {
+                    insert_occurrence.execute(params![id, *file as i64])?;
+                }
+                id += 1;
+            }
+        }
+
+        let mut insert_metric = tx.prepare("INSERT INTO summary (metric, value) VALUES (?, ?)")?;
+        insert_metric.execute(params!["total size", counts.total_size as i64])?;
+        for category in counts.items.keys() {
+            let dupe = counts.duplicated(*category);
+            let metrics = [
+                ("within modules", dupe.within_modules),
+                ("across modules", dupe.across_modules),
+            ];
+            for (kind, value) in metrics {
+                insert_metric.execute(params![
+                    format!("duplicated {} {}", category.name(), kind),
+                    value as i64
+                ])?;
+            }
+        }
+        let strict: u64 = Category::ALL
+            .iter()
+            .map(|category| counts.duplicated(*category).total())
+            .sum();
+        insert_metric.execute(params!["total duplicated data", strict as i64])?;
+
+        let mut insert_run = tx.prepare("INSERT INTO run (key, value) VALUES (?, ?)")?;
+        let rows = [
+            ("version", run.version.clone()),
+            ("args", serde_json::to_string(&run.args)?),
+            ("corpus", serde_json::to_string(&run.corpus)?),
+            ("files", run.files.to_string()),
+            ("started_at", run.started_at.clone()),
+            ("finished_at", run.finished_at.clone()),
+            ("hostname", run.host.hostname.clone().unwrap_or_default()),
+            ("os", run.host.os.clone()),
+            ("arch", run.host.arch.clone()),
+            ("cpus", run.host.cpus.to_string()),
+        ];
+        for (key, value) in rows {
+            insert_run.execute(params![key, value])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
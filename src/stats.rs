@@ -0,0 +1,48 @@
+//! The `stats` subcommands, which report facts about a corpus other than its
+//! duplication.
+
+use crate::{for_each_wasm_file, ngrams::Ngrams, percent, size, Options};
+use anyhow::Result;
+use std::path::Path;
+
+/// Print the `top` opcodes of the code in `corpus` with the largest total
+/// size, along with how many times each occurs.
+pub fn opcodes(corpus: &Path, top: usize, options: &Options) -> Result<()> {
+    // An opcode is a 1-gram.
+    let mut opcodes = Ngrams::new(1);
+    let mut total_size = 0;
+    for_each_wasm_file(corpus, options, |_, wasm| {
+        total_size += wasm.len() as u64;
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            if let wasmparser::Payload::CodeSectionEntry(body) = payload? {
+                opcodes.add_body(wasm, &body)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    let tallies = opcodes.top(usize::MAX);
+    let code_size: u64 = tallies.iter().map(|(_, tally)| tally.size).sum();
+    let count: u64 = tallies.iter().map(|(_, tally)| tally.count).sum();
+    println!(
+        "Instructions: {} taking {} bytes ({:.02}% of the corpus)",
+        count,
+        code_size,
+        percent(code_size, total_size)
+    );
+    println!();
+    println!(
+        "  {:<32}{:>14}{:>16}{:>10}",
+        "opcode", "occurrences", "total", "share"
+    );
+    for (names, tally) in tallies.into_iter().take(top) {
+        println!(
+            "  {:<32}{:>14}{:>16}{:>9.02}%",
+            names[0],
+            tally.count,
+            size(tally.size, options),
+            percent(tally.size, code_size)
+        );
+    }
+    Ok(())
+}
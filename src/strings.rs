@@ -0,0 +1,28 @@
+//! Finding the string literals, such as panic messages and format strings, in
+//! the payloads of data segments.
+
+/// Whether `byte` may be part of a string: printable ASCII, whitespace, or part
+/// of a multi-byte UTF-8 character.
+fn is_text(byte: u8) -> bool {
+    (byte >= 0x20 && byte != 0x7f) || matches!(byte, b'\t' | b'\n' | b'\r')
+}
+
+/// The runs of valid UTF-8 text of at least `min_size` bytes in `data`, which
+/// are separated by NULs and other control characters, and by invalid UTF-8.
+pub fn split<'a>(data: &'a [u8], min_size: usize) -> Vec<&'a [u8]> {
+    let mut strings = vec![];
+    let mut push = |string: &'a [u8]| {
+        if !string.is_empty() && string.len() >= min_size {
+            strings.push(string);
+        }
+    };
+    for mut run in data.split(|byte| !is_text(*byte)) {
+        while let Err(e) = std::str::from_utf8(run) {
+            let (valid, invalid) = run.split_at(e.valid_up_to());
+            push(valid);
+            run = &invalid[e.error_len().unwrap_or(invalid.len())..];
+        }
+        push(run);
+    }
+    strings
+}
@@ -0,0 +1,42 @@
+//! Stripping debug info from modules before measuring them, for `--strip-debug`.
+
+use crate::dedupe::read_u32;
+use anyhow::{ensure, Context, Result};
+
+/// Whether the custom section `name` holds debug info: the `name` section or
+/// DWARF.
+pub fn is_debug(name: &[u8]) -> bool {
+    name == b"name" || name.starts_with(b".debug_")
+}
+
+/// The module `wasm` without its debug info custom sections. Nested modules are
+/// left alone.
+pub fn debug(wasm: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        wasm.len() >= 8 && wasm.starts_with(b"\0asm"),
+        "not a Wasm module"
+    );
+    let mut stripped = wasm[..8].to_vec();
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let start = pos;
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_u32(wasm, &mut pos)? as usize;
+        let end = pos
+            .checked_add(size)
+            .filter(|end| *end <= wasm.len())
+            .context("section extends past the end of the module")?;
+        if id == 0 {
+            let name_len = read_u32(&wasm[..end], &mut pos)? as usize;
+            ensure!(pos + name_len <= end, "custom section name is too long");
+            if is_debug(&wasm[pos..pos + name_len]) {
+                pos = end;
+                continue;
+            }
+        }
+        stripped.extend_from_slice(&wasm[start..end]);
+        pos = end;
+    }
+    Ok(stripped)
+}
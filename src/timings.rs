@@ -0,0 +1,120 @@
+//! Where the time of a run goes, for `--timings`: listing directories, reading
+//! files, waiting for them, hashing items, and counting each file.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// A phase of a run that happens for many files or items, and whose time is
+/// added up across them.
+#[derive(Clone, Copy)]
+pub enum Phase {
+    /// Listing directories and reading the metadata of their entries.
+    Walking,
+    /// Reading the contents of files.
+    Reading,
+    /// Counting being blocked until the next file has been read.
+    Waiting,
+    /// Hashing items, on every thread.
+    Hashing,
+}
+
+/// The time spent in each phase, which every thread adds to.
+pub struct Phases {
+    /// When timings were enabled, at the start of the run.
+    started: Instant,
+    /// The nanoseconds spent in each phase.
+    nanos: [AtomicU64; 4],
+}
+
+impl Phases {
+    pub fn new() -> Self {
+        Phases {
+            started: Instant::now(),
+            nanos: Default::default(),
+        }
+    }
+
+    /// The time since timings were enabled.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Add `time` to the time spent in `phase`.
+    pub fn record(&self, phase: Phase, time: Duration) {
+        self.nanos[phase as usize].fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// The total time spent in `phase`.
+    pub fn total(&self, phase: Phase) -> Duration {
+        Duration::from_nanos(self.nanos[phase as usize].load(Ordering::Relaxed))
+    }
+}
+
+/// Call `f`, adding the time it takes to `phase` of `phases` if timings are
+/// enabled.
+pub fn time<T>(phases: Option<&Phases>, phase: Phase, f: impl FnOnce() -> T) -> T {
+    let phases = match phases {
+        Some(phases) => phases,
+        None => return f(),
+    };
+    let start = Instant::now();
+    let result = f();
+    phases.record(phase, start.elapsed());
+    result
+}
+
+/// The time spent counting files, which is parsing them and hashing and
+/// counting their items, and the files that took the longest.
+pub struct Counting {
+    pub phases: Arc<Phases>,
+    pub total: Duration,
+    top: usize,
+    /// The `top` slowest files so far, the fastest of them at the top.
+    slowest: BinaryHeap<Reverse<(Duration, PathBuf)>>,
+}
+
+impl Counting {
+    pub fn new(top: usize, phases: Arc<Phases>) -> Self {
+        Counting {
+            phases,
+            total: Duration::ZERO,
+            top,
+            slowest: BinaryHeap::new(),
+        }
+    }
+
+    /// Add the file at `path`, which took `time` to count.
+    pub fn add(&mut self, path: &Path, time: Duration) {
+        self.total += time;
+        let faster = self
+            .slowest
+            .peek()
+            .map_or(false, |Reverse((fastest, _))| time <= *fastest);
+        if self.slowest.len() == self.top && faster {
+            return;
+        }
+        self.slowest.push(Reverse((time, path.to_path_buf())));
+        if self.slowest.len() > self.top {
+            self.slowest.pop();
+        }
+    }
+
+    /// The slowest files, and how long each took, the slowest first.
+    pub fn slowest(&self) -> Vec<(Duration, &Path)> {
+        let mut slowest: Vec<_> = self
+            .slowest
+            .iter()
+            .map(|Reverse((time, path))| (*time, path.as_path()))
+            .collect();
+        slowest.sort_by(|a, b| b.cmp(a));
+        slowest
+    }
+}
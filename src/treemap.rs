@@ -0,0 +1,224 @@
+//! Writing a static SVG treemap of the duplicated bytes, for `--treemap`.
+//!
+//! The bytes are nested by category, then by the file the redundant copies are
+//! in, or by name for custom sections, and then by item, or by file for custom
+//! sections. Rectangles are laid out with the squarified algorithm of Bruls,
+//! Huizing, and van Wijk, which keeps them close to square so that their
+//! labels fit.
+
+use crate::{attribution::Attribution, Counts};
+use anyhow::{Context, Result};
+use std::{fmt::Write as _, path::Path};
+
+const WIDTH: f64 = 1600.0;
+const HEIGHT: f64 = 1000.0;
+
+/// The height of the label at the top of a rectangle with children.
+const HEADER: f64 = 16.0;
+
+/// The most children drawn for a rectangle. The smallest of the rest are drawn
+/// as a single rectangle.
+const MAX_CHILDREN: usize = 50;
+
+/// Write the treemap of `attribution` to `path`, taking the paths of files and
+/// the names of functions from `counts`.
+pub fn write(attribution: &Attribution, counts: &Counts, path: &Path) -> Result<()> {
+    let categories = attribution
+        .labeled(counts)
+        .into_iter()
+        .map(|(category, groups)| {
+            let groups = groups
+                .into_iter()
+                .map(|(label, items)| {
+                    let items = items
+                        .into_iter()
+                        .map(|(label, size)| Node::leaf(label, size))
+                        .collect();
+                    Node::new(label, items)
+                })
+                .collect();
+            Node::new(category.name().to_string(), groups)
+        })
+        .collect();
+    let root = Node::new(String::new(), categories);
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"12\">",
+        WIDTH, HEIGHT
+    )?;
+    let canvas = Rect {
+        x: 0.0,
+        y: 0.0,
+        w: WIDTH,
+        h: HEIGHT,
+    };
+    for (i, (category, rect)) in root
+        .children
+        .iter()
+        .zip(squarify(&root.children, canvas))
+        .enumerate()
+    {
+        // Spread the categories' hues evenly around the color wheel.
+        let hue = i * 360 / root.children.len();
+        render(&mut svg, category, rect, hue, 0)?;
+    }
+    writeln!(svg, "</svg>")?;
+
+    std::fs::write(path, svg).with_context(|| format!("failed to write {}", path.display()))
+}
+
+struct Node {
+    label: String,
+    size: u64,
+    /// The largest first.
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn leaf(label: String, size: u64) -> Node {
+        Node {
+            label,
+            size,
+            children: vec![],
+        }
+    }
+
+    fn new(label: String, mut children: Vec<Node>) -> Node {
+        children.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.label.cmp(&b.label)));
+        if children.len() > MAX_CHILDREN {
+            let rest = children.split_off(MAX_CHILDREN - 1);
+            let label = format!("{} more", rest.len());
+            children.push(Node::leaf(label, rest.iter().map(|node| node.size).sum()));
+        }
+        Node {
+            label,
+            size: children.iter().map(|node| node.size).sum(),
+            children,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+/// Draw `node` into `rect`, and its children inside it.
+fn render(svg: &mut String, node: &Node, rect: Rect, hue: usize, depth: usize) -> Result<()> {
+    let lightness = 40 + 15 * depth;
+    writeln!(
+        svg,
+        "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" \
+         fill=\"hsl({}, 60%, {}%)\" stroke=\"white\" stroke-width=\"0.5\">\
+         <title>{} ({} bytes)</title></rect>",
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        hue,
+        lightness,
+        escape(&node.label),
+        node.size
+    )?;
+    if rect.w >= 40.0 && rect.h >= HEADER {
+        // Roughly the width of a character of the font.
+        let chars = (rect.w / 7.0) as usize;
+        let mut label = format!("{} ({} bytes)", node.label, node.size);
+        if label.chars().count() > chars {
+            label = label.chars().take(chars.saturating_sub(1)).collect();
+            label.push('…');
+        }
+        writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\">{}</text>",
+            rect.x + 3.0,
+            rect.y + 12.0,
+            if depth == 0 { "white" } else { "black" },
+            escape(&label)
+        )?;
+    }
+
+    let inner = Rect {
+        x: rect.x + 2.0,
+        y: rect.y + HEADER,
+        w: rect.w - 4.0,
+        h: rect.h - HEADER - 2.0,
+    };
+    if node.children.is_empty() || inner.w < 1.0 || inner.h < 1.0 {
+        return Ok(());
+    }
+    for (child, rect) in node.children.iter().zip(squarify(&node.children, inner)) {
+        render(svg, child, rect, hue, depth + 1)?;
+    }
+    Ok(())
+}
+
+/// Lay out `nodes`, largest first, in `rect`, with areas proportional to their
+/// sizes.
+fn squarify(nodes: &[Node], mut rect: Rect) -> Vec<Rect> {
+    let total: u64 = nodes.iter().map(|node| node.size).sum();
+    let scale = rect.w * rect.h / total.max(1) as f64;
+    let areas: Vec<f64> = nodes.iter().map(|node| node.size as f64 * scale).collect();
+
+    let mut rects = Vec::with_capacity(nodes.len());
+    let mut start = 0;
+    while start < areas.len() {
+        // Add rectangles to the row along the shorter side for as long as that
+        // makes its worst aspect ratio better.
+        let side = rect.w.min(rect.h);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst(&areas[start..=end], side) <= worst(&areas[start..end], side)
+        {
+            end += 1;
+        }
+        let row = &areas[start..end];
+        let row_area: f64 = row.iter().sum();
+        if rect.w >= rect.h {
+            let w = row_area / rect.h;
+            let mut y = rect.y;
+            for area in row {
+                let h = area / w;
+                rects.push(Rect { x: rect.x, y, w, h });
+                y += h;
+            }
+            rect.x += w;
+            rect.w -= w;
+        } else {
+            let h = row_area / rect.w;
+            let mut x = rect.x;
+            for area in row {
+                let w = area / h;
+                rects.push(Rect { x, y: rect.y, w, h });
+                x += w;
+            }
+            rect.y += h;
+            rect.h -= h;
+        }
+        start = end;
+    }
+    rects
+}
+
+/// The worst aspect ratio of the rectangles of a row of `areas` along a side of
+/// length `side`.
+fn worst(areas: &[f64], side: f64) -> f64 {
+    let sum: f64 = areas.iter().sum();
+    let max = areas.iter().cloned().fold(0.0, f64::max);
+    let min = areas.iter().cloned().fold(f64::INFINITY, f64::min);
+    let side = side * side;
+    (side * max / (sum * sum)).max(sum * sum / (side * min))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,51 @@
+//! Weighting files by how often they're downloaded, for `--weights`.
+//!
+//! A weights file is a CSV file of `path,downloads` rows, optionally after a
+//! header row, where each path may contain `*` and `?` wildcards.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+pub struct Weights {
+    /// Each path pattern and its weight, in the order of the file.
+    patterns: Vec<(String, u64)>,
+}
+
+impl Weights {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut patterns = vec![];
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (pattern, weight) = line.rsplit_once(',').with_context(|| {
+                format!("{}:{}: expected `path,downloads`", path.display(), i + 1)
+            })?;
+            let weight = match weight.trim().parse() {
+                Ok(weight) => weight,
+                // A header row.
+                Err(_) if i == 0 => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("{}:{}: invalid download count", path.display(), i + 1)
+                    })
+                }
+            };
+            let pattern = pattern.trim().trim_matches('"').to_string();
+            patterns.push((pattern, weight));
+        }
+        Ok(Weights { patterns })
+    }
+
+    /// The weight of the first pattern matching `path`, if any does.
+    pub fn weight(&self, path: &Path) -> Option<u64> {
+        let path = path.display().to_string();
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| crate::glob_matches(pattern.as_bytes(), path.as_bytes()))
+            .map(|(_, weight)| *weight)
+    }
+}
@@ -0,0 +1,48 @@
+//! The provenance that subcommands embed in their JSON output.
+
+use std::{fs, path::PathBuf, process::Command};
+
+/// A new directory under the system's temporary directory holding one empty
+/// module.
+fn corpus(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "measure-wasm-dedupe-wins-{}-{}",
+        std::process::id(),
+        name
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("empty.wasm"), b"\0asm\x01\0\0\0").unwrap();
+    dir
+}
+
+#[test]
+fn overlap_records_its_corpora() {
+    let base = corpus("base");
+    let candidate = corpus("candidate");
+    let output = Command::new(env!("CARGO_BIN_EXE_measure-wasm-dedupe-wins"))
+        .args(["--format", "json=v1", "overlap", "--base"])
+        .arg(&base)
+        .arg("--candidate")
+        .arg(&candidate)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let corpus: Vec<&str> = json["run"]["corpus"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|path| path.as_str().unwrap())
+        .collect();
+    assert_eq!(
+        corpus,
+        [base.display().to_string(), candidate.display().to_string()]
+    );
+    for dir in [base, candidate] {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}